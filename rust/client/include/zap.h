@@ -0,0 +1,92 @@
+#ifndef ZAP_H
+#define ZAP_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Outcome of a call, `ZAP_STATUS_OK` or the kind of failure.
+typedef enum ZapStatus {
+  ZAP_STATUS_OK = 0,
+  // A required pointer was null, or a string was not valid UTF-8 or JSON.
+  ZAP_STATUS_INVALID_ARGUMENT = 1,
+  ZAP_STATUS_INVALID_CONFIG = 2,
+  ZAP_STATUS_PROXY_CONNECT = 3,
+  ZAP_STATUS_CONNECT_REJECTED = 4,
+  ZAP_STATUS_TLS_HANDSHAKE = 5,
+  ZAP_STATUS_UNSUPPORTED_CIPHER_SUITE = 6,
+  ZAP_STATUS_NOTARY_REJECTED = 7,
+  // The notary may still issue the attestation, the message carries the session id.
+  ZAP_STATUS_PROOF_INTERRUPTED = 8,
+  ZAP_STATUS_MALFORMED_PROOF = 9,
+  ZAP_STATUS_VERIFICATION_FAILED = 10,
+  ZAP_STATUS_IO = 11,
+  // The library panicked. The client must not be used again.
+  ZAP_STATUS_PANIC = 12,
+} ZapStatus;
+
+typedef struct ZapClient ZapClient;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a client from a JSON `ZapServerConfig`. The client is stored in `*out_client`.
+//
+// # Safety
+//
+// `config_json` must be null or a NUL-terminated string, `out_client` must be valid for
+// writes and `out_error` must be null or valid for writes.
+enum ZapStatus zap_client_new(const char *config_json, ZapClient **out_client, char **out_error);
+
+// Releases a client created by `zap_client_new`. Does nothing if `client` is null.
+//
+// # Safety
+//
+// `client` must be null or a client returned by `zap_client_new` that was not freed yet,
+// and must not be in use by another thread.
+void zap_client_free(ZapClient *client);
+
+// Proves the endpoint described by `endpoint_json` and stores the proof as JSON in
+// `*out_proof_json`. Blocks until the proof is received, the client may be used from
+// several threads at once.
+//
+// # Safety
+//
+// `client` must be a live client from `zap_client_new`, `endpoint_json` must be null or a
+// NUL-terminated string, `out_proof_json` must be valid for writes and `out_error` must be
+// null or valid for writes.
+enum ZapStatus zap_client_prove(const ZapClient *client,
+                                const char *endpoint_json,
+                                char **out_proof_json,
+                                char **out_error);
+
+// Verifies a proof in JSON against the notary keys in `keys_json`, a JSON array as served
+// by `GET /keys`. On success the attestation is stored as JSON in `*out_attestation_json`,
+// with an `http_response` field holding the disclosed response or `null`.
+//
+// # Safety
+//
+// `proof_json` and `keys_json` must be null or NUL-terminated strings,
+// `out_attestation_json` must be valid for writes and `out_error` must be null or valid for
+// writes.
+enum ZapStatus zap_proof_verify(const char *proof_json,
+                                const char *keys_json,
+                                char **out_attestation_json,
+                                char **out_error);
+
+// Releases a string returned by the library. Does nothing if `s` is null.
+//
+// # Safety
+//
+// `s` must be null or a string returned by the library that was not freed yet.
+void zap_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ZAP_H */
@@ -0,0 +1,143 @@
+use futures_util::stream::{self, StreamExt};
+use rustls::KeyLog;
+use std::sync::{Arc, Mutex};
+use tracing::{info_span, Instrument};
+
+use crate::error::ZapError;
+use crate::http::HttpClient;
+use crate::transport::Transport;
+use crate::types::{Endpoint, NotaryInfo, NotaryPublicKey, Proof, TargetTls, ZapServerConfig};
+
+/// Asynchronous client for the Zap proxy, for callers running on tokio. Exported with the
+/// `async` feature, which the `wasm` feature enables since browsers only have this client.
+///
+/// The blocking [`ZapClient`](crate::prelude::ZapClient) runs this client on its own runtime,
+/// so it is compiled into every build.
+///
+/// The TLS configurations and HTTP client are built on first use and shared by every
+/// subsequent call, so one client can serve many concurrent proofs.
+pub struct AsyncZapClient {
+    zap_server_config: ZapServerConfig,
+    target_tls: TargetTls,
+    key_log: Option<Arc<dyn KeyLog>>,
+    transport: Arc<dyn Transport>,
+    http: Mutex<Option<Arc<HttpClient>>>,
+}
+
+impl AsyncZapClient {
+    pub fn new(zap_server_config: ZapServerConfig) -> Self {
+        let transport = default_transport(&zap_server_config);
+        Self {
+            zap_server_config,
+            target_tls: TargetTls::default(),
+            key_log: None,
+            transport,
+            http: Mutex::new(None),
+        }
+    }
+
+    /// Uses `target_tls` to verify, and authenticate to, the proven endpoints.
+    pub fn with_target_tls(mut self, target_tls: TargetTls) -> Self {
+        self.target_tls = target_tls;
+        self.http = Mutex::new(None);
+        self
+    }
+
+    /// Logs the handshake secrets of the target connections to `key_log`, e.g. a
+    /// [`MemoryKeyLog`](crate::prelude::MemoryKeyLog) or `rustls::KeyLogFile`. Nothing is
+    /// logged by default.
+    pub fn with_key_log(mut self, key_log: Arc<dyn KeyLog>) -> Self {
+        self.key_log = Some(key_log);
+        self.http = Mutex::new(None);
+        self
+    }
+
+    /// Reaches the proxy's CONNECT listener through `transport` instead of TCP, or instead of
+    /// a WebSocket in browser builds.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self.http = Mutex::new(None);
+        self
+    }
+
+    fn http(&self) -> Result<Arc<HttpClient>, ZapError> {
+        let mut http = self.http.lock().unwrap();
+        if let Some(http) = &*http {
+            return Ok(http.clone());
+        }
+
+        let client = Arc::new(HttpClient::new(
+            self.zap_server_config.clone(),
+            &self.target_tls,
+            self.key_log.clone(),
+            self.transport.clone(),
+        )?);
+        *http = Some(client.clone());
+        Ok(client)
+    }
+
+    /// Proves `endpoint`, verifying the proof against the pinned notary keys if any are set.
+    pub async fn prove(&self, endpoint: Endpoint) -> Result<Proof, ZapError> {
+        let span = info_span!("prove", host = endpoint.get_host(), port = endpoint.get_port());
+        let proof = self.http()?.perform(&endpoint).instrument(span).await?;
+
+        let pinned_keys = self.zap_server_config.get_pinned_keys();
+        if !pinned_keys.is_empty() && !proof.verify(pinned_keys) {
+            return Err(ZapError::VerificationFailed { key_id: proof.get_key_id().to_string() });
+        }
+
+        Ok(proof)
+    }
+
+    /// Proves every endpoint, running at most `parallelism` proofs at a time. The results are
+    /// returned in the order of `endpoints`, a failed proof does not stop the others.
+    pub async fn prove_many<I>(&self, endpoints: I, parallelism: usize) -> Vec<Result<Proof, ZapError>>
+    where
+        I: IntoIterator<Item = Endpoint>,
+    {
+        stream::iter(endpoints)
+            .map(|endpoint| self.prove(endpoint))
+            .buffered(parallelism.max(1))
+            .collect()
+            .await
+    }
+
+    pub async fn fetch_info(&self) -> Result<NotaryInfo, ZapError> {
+        self.http()?.api().get_json("/info").await
+    }
+
+    pub async fn fetch_keys(&self) -> Result<Vec<NotaryPublicKey>, ZapError> {
+        self.http()?.api().get_json("/keys").await
+    }
+
+    /// Fetches the attestation the notary issued for a session, e.g. after [`prove`](Self::prove)
+    /// failed with [`ZapError::ProofInterrupted`].
+    pub async fn fetch_attestation(&self, session_id: &str) -> Result<Proof, ZapError> {
+        let proof: Proof =
+            self.http()?.api().get_json(&format!("/sessions/{}/attestation", session_id)).await?;
+
+        let pinned_keys = self.zap_server_config.get_pinned_keys();
+        if !pinned_keys.is_empty() && !proof.verify(pinned_keys) {
+            return Err(ZapError::VerificationFailed { key_id: proof.get_key_id().to_string() });
+        }
+        Ok(proof)
+    }
+
+    /// Fetches the notary's current keys and pins them for subsequent proofs.
+    pub async fn pin_notary_keys(&mut self) -> Result<&[NotaryPublicKey], ZapError> {
+        let keys = self.fetch_keys().await?;
+        self.zap_server_config = self.zap_server_config.clone().with_pinned_keys(keys);
+        Ok(self.zap_server_config.get_pinned_keys())
+    }
+}
+
+#[cfg(feature = "native")]
+fn default_transport(_zap_server_config: &ZapServerConfig) -> Arc<dyn Transport> {
+    Arc::new(crate::transport::TcpTransport)
+}
+
+/// Browsers only open WebSockets, over `wss://` when the proxy listeners use TLS.
+#[cfg(not(feature = "native"))]
+fn default_transport(zap_server_config: &ZapServerConfig) -> Arc<dyn Transport> {
+    Arc::new(crate::wasm::WebSocketTransport::new(zap_server_config.get_tls().is_some()))
+}
@@ -1,18 +1,159 @@
-use std::io;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
 
-use crate::http::HttpClient;
-use crate::types::{Endpoint, Proof, ZapServerConfig};
+use rustls::KeyLog;
+use tokio::runtime::{self, Runtime};
+
+use crate::async_client::AsyncZapClient;
+use crate::error::ZapError;
+use crate::transport::Transport;
+use crate::types::{Endpoint, NotaryInfo, NotaryPublicKey, Proof, TargetTls, ZapServerConfig};
+
+/// Blocking client for the Zap proxy, a thin wrapper running [`AsyncZapClient`] to completion.
+///
+/// The client is `Send + Sync` and meant to be reused: the TLS configurations, HTTP client and
+/// runtime are shared by every call, including concurrent calls from several threads.
+///
+/// Must not be used from within a tokio runtime; use `AsyncZapClient` there instead.
 pub struct ZapClient {
-    zap_server_config: ZapServerConfig,
+    inner: AsyncZapClient,
+    runtime: ClientRuntime,
 }
 
 impl ZapClient {
     pub fn new(zap_server_config: ZapServerConfig) -> Self {
-        Self { zap_server_config }
+        Self { inner: AsyncZapClient::new(zap_server_config), runtime: ClientRuntime::default() }
+    }
+
+    /// Uses `target_tls` to verify, and authenticate to, the proven endpoints.
+    pub fn with_target_tls(self, target_tls: TargetTls) -> Self {
+        Self { inner: self.inner.with_target_tls(target_tls), ..self }
+    }
+
+    /// Logs the handshake secrets of the target connections to `key_log`, e.g. a
+    /// [`MemoryKeyLog`](crate::prelude::MemoryKeyLog) or `rustls::KeyLogFile`. Nothing is
+    /// logged by default.
+    pub fn with_key_log(self, key_log: Arc<dyn KeyLog>) -> Self {
+        Self { inner: self.inner.with_key_log(key_log), ..self }
+    }
+
+    /// Reaches the proxy's CONNECT listener through `transport` instead of TCP.
+    pub fn with_transport(self, transport: Arc<dyn Transport>) -> Self {
+        Self { inner: self.inner.with_transport(transport), ..self }
+    }
+
+    /// Proves `endpoint`, verifying the proof against the pinned notary keys if any are set.
+    pub fn prove(&self, endpoint: Endpoint) -> Result<Proof, ZapError> {
+        block_on(&self.runtime.0, self.inner.prove(endpoint))?
+    }
+
+    /// Proves every endpoint, running at most `parallelism` proofs at a time. The results are
+    /// returned in the order of `endpoints`, a failed proof does not stop the others.
+    pub fn prove_many<I>(
+        &self,
+        endpoints: I,
+        parallelism: usize,
+    ) -> Result<Vec<Result<Proof, ZapError>>, ZapError>
+    where
+        I: IntoIterator<Item = Endpoint>,
+    {
+        block_on(&self.runtime.0, self.inner.prove_many(endpoints, parallelism))
+    }
+
+    pub fn fetch_info(&self) -> Result<NotaryInfo, ZapError> {
+        block_on(&self.runtime.0, self.inner.fetch_info())?
+    }
+
+    pub fn fetch_keys(&self) -> Result<Vec<NotaryPublicKey>, ZapError> {
+        block_on(&self.runtime.0, self.inner.fetch_keys())?
+    }
+
+    /// Fetches the attestation the notary issued for a session, e.g. after [`prove`](Self::prove)
+    /// failed with [`ZapError::ProofInterrupted`].
+    pub fn fetch_attestation(&self, session_id: &str) -> Result<Proof, ZapError> {
+        block_on(&self.runtime.0, self.inner.fetch_attestation(session_id))?
     }
 
-    pub fn prove(&self, endpoint: Endpoint) -> io::Result<Proof> {
-        let client = HttpClient::new(endpoint, self.zap_server_config.clone());
-        client.perform()
+    /// Fetches the notary's current keys and pins them for subsequent proofs.
+    pub fn pin_notary_keys(&mut self) -> Result<&[NotaryPublicKey], ZapError> {
+        block_on(&self.runtime.0, self.inner.pin_notary_keys())?
+    }
+}
+
+/// The runtime of a [`ZapClient`], built on first use.
+#[derive(Default)]
+struct ClientRuntime(OnceLock<Runtime>);
+
+impl Drop for ClientRuntime {
+    fn drop(&mut self) {
+        // Dropping a runtime waits for its workers, which panics when the client is dropped
+        // on a runtime thread, e.g. `zap_client_free` called from a tokio task
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ZapClient>();
+};
+
+/// Worker threads of the client's runtime, which drive the I/O of every concurrent call.
+const MAX_RUNTIME_WORKERS: usize = 4;
+
+/// Runs `future` on the client's runtime, built on first use.
+///
+/// The runtime is multi-threaded so that calls made from several threads at once are not all
+/// driven by whichever thread happens to hold a single runtime core.
+fn block_on<F: Future>(runtime: &OnceLock<Runtime>, future: F) -> Result<F::Output, ZapError> {
+    let runtime = match runtime.get() {
+        Some(runtime) => runtime,
+        None => {
+            let workers =
+                std::thread::available_parallelism().map_or(1, |n| n.get()).min(MAX_RUNTIME_WORKERS);
+            let built = runtime::Builder::new_multi_thread()
+                .worker_threads(workers)
+                .thread_name("zap-client")
+                .enable_all()
+                .build()?;
+            // Another thread may have won the race, its runtime is kept and ours dropped
+            runtime.get_or_init(|| built)
+        }
+    };
+    Ok(runtime.block_on(future))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tokio::runtime::RuntimeFlavor;
+
+    use super::*;
+
+    #[test]
+    fn concurrent_calls_share_one_multi_thread_runtime() {
+        let runtime = OnceLock::new();
+        let started = Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let sleep = async { tokio::time::sleep(Duration::from_millis(200)).await };
+                scope.spawn(|| block_on(&runtime, sleep).unwrap());
+            }
+        });
+        // The sleeps overlap instead of running one after the other
+        assert!(started.elapsed() < Duration::from_millis(800));
+        assert_eq!(runtime.get().unwrap().handle().runtime_flavor(), RuntimeFlavor::MultiThread);
+    }
+
+    #[test]
+    fn drops_inside_a_tokio_runtime() {
+        let client = ZapClient::new(ZapServerConfig::default());
+        block_on(&client.runtime.0, async {}).unwrap();
+        assert!(client.runtime.0.get().is_some());
+
+        let runtime = runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async move { drop(client) });
     }
 }
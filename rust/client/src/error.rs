@@ -0,0 +1,66 @@
+use std::{error::Error, fmt, io};
+
+/// Errors returned by the Zap client.
+#[derive(Debug)]
+pub enum ZapError {
+    /// The client configuration or endpoint is incomplete or invalid.
+    InvalidConfig(String),
+    /// The proxy could not be reached, on its CONNECT listener or its HTTP API.
+    ProxyConnect(io::Error),
+    /// The proxy answered the CONNECT request with a non-2xx status.
+    ConnectRejected { status: u16, reason: String },
+    /// The TLS handshake with the target through the tunnel failed.
+    TlsHandshake(io::Error),
+    /// The session negotiated a cipher suite whose secrets cannot be shared with the notary.
+    UnsupportedCipherSuite(String),
+    /// The notary's HTTP API answered with a non-2xx status.
+    NotaryRejected { status: u16, body: String },
+    /// The `/proof` request failed before the notary answered. The notary may still issue the
+    /// attestation, fetch it later with `fetch_attestation(session_id)`.
+    ProofInterrupted { session_id: String, source: Box<ZapError> },
+    /// The notary returned a proof, or another response, that could not be decoded.
+    MalformedProof(String),
+    /// The proof is not signed by any of the pinned notary keys.
+    VerificationFailed { key_id: String },
+    /// Any other I/O error, e.g. while sending the request through the tunnel.
+    Io(io::Error),
+}
+
+impl fmt::Display for ZapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZapError::InvalidConfig(reason) => write!(f, "Invalid configuration: {}", reason),
+            ZapError::ProxyConnect(e) => write!(f, "Failed to connect to the proxy: {}", e),
+            ZapError::ConnectRejected { status, reason } => {
+                write!(f, "Proxy rejected the CONNECT request: {} {}", status, reason)
+            }
+            ZapError::TlsHandshake(e) => write!(f, "TLS handshake with the target failed: {}", e),
+            ZapError::UnsupportedCipherSuite(suite) => write!(f, "Unsupported cipher suite {}", suite),
+            ZapError::NotaryRejected { status, body } => write!(f, "Notary returned {}: {}", status, body),
+            ZapError::ProofInterrupted { session_id, source } => {
+                write!(f, "Proof request for session {} was interrupted: {}", session_id, source)
+            }
+            ZapError::MalformedProof(reason) => write!(f, "Malformed notary response: {}", reason),
+            ZapError::VerificationFailed { key_id } => {
+                write!(f, "Proof is not signed by a pinned notary key (key id {})", key_id)
+            }
+            ZapError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl Error for ZapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ZapError::ProxyConnect(e) | ZapError::TlsHandshake(e) | ZapError::Io(e) => Some(e),
+            ZapError::ProofInterrupted { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ZapError {
+    fn from(e: io::Error) -> Self {
+        ZapError::Io(e)
+    }
+}
@@ -0,0 +1,390 @@
+//! C API over [`ZapClient`], for native apps linking the `cdylib` or `staticlib`.
+//!
+//! Configurations, endpoints and proofs cross the boundary as JSON in the serde form of
+//! [`ZapServerConfig`], [`Endpoint`] and [`Proof`]. Every function returns a [`ZapStatus`].
+//! On failure, a message is stored in `*out_error` when that pointer is not null. Strings
+//! returned by the library must be released with [`zap_string_free`], and clients with
+//! [`zap_client_free`]. The declarations are in `include/zap.h`.
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::client::ZapClient;
+use crate::error::ZapError;
+use crate::types::{Attestation, Endpoint, HttpResponse, NotaryPublicKey, Proof, ZapServerConfig};
+
+/// Outcome of a call, `ZAP_STATUS_OK` or the kind of failure.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZapStatus {
+    Ok = 0,
+    /// A required pointer was null, or a string was not valid UTF-8 or JSON.
+    InvalidArgument = 1,
+    InvalidConfig = 2,
+    ProxyConnect = 3,
+    ConnectRejected = 4,
+    TlsHandshake = 5,
+    UnsupportedCipherSuite = 6,
+    NotaryRejected = 7,
+    /// The notary may still issue the attestation, the message carries the session id.
+    ProofInterrupted = 8,
+    MalformedProof = 9,
+    VerificationFailed = 10,
+    Io = 11,
+    /// The library panicked. The client must not be used again.
+    Panic = 12,
+}
+
+impl From<&ZapError> for ZapStatus {
+    fn from(e: &ZapError) -> Self {
+        match e {
+            ZapError::InvalidConfig(_) => ZapStatus::InvalidConfig,
+            ZapError::ProxyConnect(_) => ZapStatus::ProxyConnect,
+            ZapError::ConnectRejected { .. } => ZapStatus::ConnectRejected,
+            ZapError::TlsHandshake(_) => ZapStatus::TlsHandshake,
+            ZapError::UnsupportedCipherSuite(_) => ZapStatus::UnsupportedCipherSuite,
+            ZapError::NotaryRejected { .. } => ZapStatus::NotaryRejected,
+            ZapError::ProofInterrupted { .. } => ZapStatus::ProofInterrupted,
+            ZapError::MalformedProof(_) => ZapStatus::MalformedProof,
+            ZapError::VerificationFailed { .. } => ZapStatus::VerificationFailed,
+            ZapError::Io(_) => ZapStatus::Io,
+        }
+    }
+}
+
+/// Failure of a call, turned into a status and the message handed to the caller.
+struct FfiError {
+    status: ZapStatus,
+    message: String,
+}
+
+impl FfiError {
+    fn invalid_argument(message: String) -> Self {
+        FfiError { status: ZapStatus::InvalidArgument, message }
+    }
+}
+
+impl From<ZapError> for FfiError {
+    fn from(e: ZapError) -> Self {
+        FfiError { status: ZapStatus::from(&e), message: e.to_string() }
+    }
+}
+
+/// Creates a client from a JSON `ZapServerConfig`. The client is stored in `*out_client`.
+///
+/// # Safety
+///
+/// `config_json` must be null or a NUL-terminated string, `out_client` must be valid for
+/// writes and `out_error` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zap_client_new(
+    config_json: *const c_char,
+    out_client: *mut *mut ZapClient,
+    out_error: *mut *mut c_char,
+) -> ZapStatus {
+    call(out_error, || {
+        if out_client.is_null() {
+            return Err(FfiError::invalid_argument("out_client is null".to_string()));
+        }
+        let config: ZapServerConfig = unsafe { from_json(config_json, "config_json") }?;
+        let client = Box::new(ZapClient::new(config));
+        unsafe { *out_client = Box::into_raw(client) };
+        Ok(())
+    })
+}
+
+/// Releases a client created by `zap_client_new`. Does nothing if `client` is null.
+///
+/// # Safety
+///
+/// `client` must be null or a client returned by `zap_client_new` that was not freed yet,
+/// and must not be in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn zap_client_free(client: *mut ZapClient) {
+    if !client.is_null() {
+        drop(unsafe { Box::from_raw(client) });
+    }
+}
+
+/// Proves the endpoint described by `endpoint_json` and stores the proof as JSON in
+/// `*out_proof_json`. Blocks until the proof is received, the client may be used from
+/// several threads at once.
+///
+/// # Safety
+///
+/// `client` must be a live client from `zap_client_new`, `endpoint_json` must be null or a
+/// NUL-terminated string, `out_proof_json` must be valid for writes and `out_error` must be
+/// null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zap_client_prove(
+    client: *const ZapClient,
+    endpoint_json: *const c_char,
+    out_proof_json: *mut *mut c_char,
+    out_error: *mut *mut c_char,
+) -> ZapStatus {
+    call(out_error, || {
+        if client.is_null() || out_proof_json.is_null() {
+            return Err(FfiError::invalid_argument("client or out_proof_json is null".to_string()));
+        }
+        let client = unsafe { &*client };
+        let endpoint: Endpoint = unsafe { from_json(endpoint_json, "endpoint_json") }?;
+
+        let proof = client.prove(endpoint)?;
+        let json = serde_json::to_string(&proof).map_err(|e| ZapError::MalformedProof(e.to_string()))?;
+        unsafe { *out_proof_json = into_c_string(json) };
+        Ok(())
+    })
+}
+
+/// Attestation returned by `zap_proof_verify`, with the disclosed HTTP response if any.
+#[derive(Serialize)]
+struct VerifiedAttestation {
+    #[serde(flatten)]
+    attestation: Attestation,
+    http_response: Option<HttpResponse>,
+}
+
+/// Verifies a proof in JSON against the notary keys in `keys_json`, a JSON array as served
+/// by `GET /keys`. On success the attestation is stored as JSON in `*out_attestation_json`,
+/// with an `http_response` field holding the disclosed response or `null`.
+///
+/// # Safety
+///
+/// `proof_json` and `keys_json` must be null or NUL-terminated strings,
+/// `out_attestation_json` must be valid for writes and `out_error` must be null or valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn zap_proof_verify(
+    proof_json: *const c_char,
+    keys_json: *const c_char,
+    out_attestation_json: *mut *mut c_char,
+    out_error: *mut *mut c_char,
+) -> ZapStatus {
+    call(out_error, || {
+        if out_attestation_json.is_null() {
+            return Err(FfiError::invalid_argument("out_attestation_json is null".to_string()));
+        }
+        let proof: Proof = unsafe { from_json(proof_json, "proof_json") }?;
+        let keys: Vec<NotaryPublicKey> = unsafe { from_json(keys_json, "keys_json") }?;
+        if !proof.verify(&keys) {
+            return Err(ZapError::VerificationFailed { key_id: proof.get_key_id().to_string() }.into());
+        }
+
+        let attestation = proof
+            .attestation()
+            .map_err(|e| ZapError::MalformedProof(format!("Invalid attestation: {}", e)))?;
+        let verified = VerifiedAttestation { http_response: attestation.get_http_response(), attestation };
+        let json = serde_json::to_string(&verified).map_err(|e| ZapError::MalformedProof(e.to_string()))?;
+        unsafe { *out_attestation_json = into_c_string(json) };
+        Ok(())
+    })
+}
+
+/// Releases a string returned by the library. Does nothing if `s` is null.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by the library that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn zap_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Runs `f`, translating its error or panic into a status and the message in `*out_error`.
+fn call<F>(out_error: *mut *mut c_char, f: F) -> ZapStatus
+where
+    F: FnOnce() -> Result<(), FfiError>,
+{
+    let error = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return ZapStatus::Ok,
+        Ok(Err(e)) => e,
+        Err(_) => FfiError { status: ZapStatus::Panic, message: "Zap client panicked".to_string() },
+    };
+
+    if !out_error.is_null() {
+        // Safety: callers guarantee `out_error` is valid for writes when it is not null
+        unsafe { *out_error = into_c_string(error.message) };
+    }
+    error.status
+}
+
+/// Parses the NUL-terminated JSON string `json`, naming it `name` in errors.
+///
+/// # Safety
+///
+/// `json` must be null or a NUL-terminated string.
+unsafe fn from_json<T: DeserializeOwned>(json: *const c_char, name: &str) -> Result<T, FfiError> {
+    if json.is_null() {
+        return Err(FfiError::invalid_argument(format!("{} is null", name)));
+    }
+    let json = unsafe { CStr::from_ptr(json) }
+        .to_str()
+        .map_err(|_| FfiError::invalid_argument(format!("{} is not valid UTF-8", name)))?;
+    serde_json::from_str(json).map_err(|e| FfiError::invalid_argument(format!("Invalid {}: {}", name, e)))
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // Interior NULs cannot be represented, those strings are cut at the first one
+    let s = CString::new(s).unwrap_or_else(|e| {
+        let nul = e.nul_position();
+        CString::new(&e.into_vec()[..nul]).unwrap()
+    });
+    s.into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::process::Command;
+    use std::ptr;
+
+    use super::*;
+
+    /// Every status with its name in `include/zap.h`.
+    const STATUSES: [(ZapStatus, &str); 13] = [
+        (ZapStatus::Ok, "ZAP_STATUS_OK"),
+        (ZapStatus::InvalidArgument, "ZAP_STATUS_INVALID_ARGUMENT"),
+        (ZapStatus::InvalidConfig, "ZAP_STATUS_INVALID_CONFIG"),
+        (ZapStatus::ProxyConnect, "ZAP_STATUS_PROXY_CONNECT"),
+        (ZapStatus::ConnectRejected, "ZAP_STATUS_CONNECT_REJECTED"),
+        (ZapStatus::TlsHandshake, "ZAP_STATUS_TLS_HANDSHAKE"),
+        (ZapStatus::UnsupportedCipherSuite, "ZAP_STATUS_UNSUPPORTED_CIPHER_SUITE"),
+        (ZapStatus::NotaryRejected, "ZAP_STATUS_NOTARY_REJECTED"),
+        (ZapStatus::ProofInterrupted, "ZAP_STATUS_PROOF_INTERRUPTED"),
+        (ZapStatus::MalformedProof, "ZAP_STATUS_MALFORMED_PROOF"),
+        (ZapStatus::VerificationFailed, "ZAP_STATUS_VERIFICATION_FAILED"),
+        (ZapStatus::Io, "ZAP_STATUS_IO"),
+        (ZapStatus::Panic, "ZAP_STATUS_PANIC"),
+    ];
+
+    /// Calls `zap_client_new` with `config_json`, returning the status and the error message.
+    fn new_client(config_json: *const c_char) -> (ZapStatus, Option<String>) {
+        let mut client = ptr::null_mut();
+        let mut error = ptr::null_mut();
+        let status = unsafe { zap_client_new(config_json, &mut client, &mut error) };
+        unsafe { zap_client_free(client) };
+        let message = (!error.is_null()).then(|| {
+            let message = unsafe { CStr::from_ptr(error) }.to_str().unwrap().to_string();
+            unsafe { zap_string_free(error) };
+            message
+        });
+        (status, message)
+    }
+
+    #[test]
+    fn creates_clients_from_json() {
+        let config = serde_json::to_string(&ZapServerConfig::new("localhost", 3000, 55688)).unwrap();
+        let config = CString::new(config).unwrap();
+        assert_eq!(new_client(config.as_ptr()), (ZapStatus::Ok, None));
+    }
+
+    #[test]
+    fn rejects_invalid_client_configs() {
+        assert_eq!(
+            new_client(ptr::null()),
+            (ZapStatus::InvalidArgument, Some("config_json is null".to_string()))
+        );
+
+        let not_utf8 = CString::new(vec![b'{', 0xff, b'}']).unwrap();
+        assert_eq!(
+            new_client(not_utf8.as_ptr()),
+            (ZapStatus::InvalidArgument, Some("config_json is not valid UTF-8".to_string()))
+        );
+
+        let not_json = CString::new("{\"host\":").unwrap();
+        let (status, message) = new_client(not_json.as_ptr());
+        assert_eq!(status, ZapStatus::InvalidArgument);
+        assert!(message.unwrap().starts_with("Invalid config_json: "));
+
+        let config = CString::new("{}").unwrap();
+        let mut error = ptr::null_mut();
+        let status = unsafe { zap_client_new(config.as_ptr(), ptr::null_mut(), &mut error) };
+        assert_eq!(status, ZapStatus::InvalidArgument);
+        assert_eq!(unsafe { CString::from_raw(error) }.to_str().unwrap(), "out_client is null");
+    }
+
+    #[test]
+    fn frees_null_pointers() {
+        unsafe {
+            zap_string_free(ptr::null_mut());
+            zap_client_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn maps_errors_to_statuses() {
+        let io = || io::Error::other("io");
+        let errors = [
+            (ZapError::InvalidConfig(String::new()), ZapStatus::InvalidConfig),
+            (ZapError::ProxyConnect(io()), ZapStatus::ProxyConnect),
+            (ZapError::ConnectRejected { status: 407, reason: String::new() }, ZapStatus::ConnectRejected),
+            (ZapError::TlsHandshake(io()), ZapStatus::TlsHandshake),
+            (ZapError::UnsupportedCipherSuite(String::new()), ZapStatus::UnsupportedCipherSuite),
+            (ZapError::NotaryRejected { status: 500, body: String::new() }, ZapStatus::NotaryRejected),
+            (
+                ZapError::ProofInterrupted {
+                    session_id: String::new(),
+                    source: Box::new(ZapError::Io(io())),
+                },
+                ZapStatus::ProofInterrupted,
+            ),
+            (ZapError::MalformedProof(String::new()), ZapStatus::MalformedProof),
+            (ZapError::VerificationFailed { key_id: String::new() }, ZapStatus::VerificationFailed),
+            (ZapError::Io(io()), ZapStatus::Io),
+        ];
+        for (error, status) in &errors {
+            assert_eq!(ZapStatus::from(error), *status, "{:?}", error);
+        }
+
+        // The values are part of the C ABI
+        for (value, (status, _)) in STATUSES.iter().enumerate() {
+            assert_eq!(*status as usize, value);
+        }
+        assert_eq!(call(ptr::null_mut(), || panic!("boom")), ZapStatus::Panic);
+    }
+
+    #[test]
+    fn header_matches_ffi() {
+        let manifest_dir = env!("CARGO_MANIFEST_DIR");
+        let verify = Command::new("cbindgen")
+            .current_dir(manifest_dir)
+            .args(["--config", "cbindgen.toml", "--verify", "--output", "include/zap.h"])
+            .output();
+        if let Ok(output) = verify {
+            assert!(
+                output.status.success(),
+                "include/zap.h is out of date, regenerate it with cbindgen: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            return;
+        }
+
+        // Without cbindgen, check the header declares every function and status
+        let header = std::fs::read_to_string(format!("{}/include/zap.h", manifest_dir)).unwrap();
+        let source = std::fs::read_to_string(format!("{}/src/ffi.rs", manifest_dir)).unwrap();
+        let functions: Vec<&str> = source
+            .lines()
+            .filter_map(|line| line.strip_prefix("pub unsafe extern \"C\" fn "))
+            .map(|line| &line[..line.find('(').unwrap()])
+            .collect();
+        assert_eq!(
+            functions,
+            ["zap_client_new", "zap_client_free", "zap_client_prove", "zap_proof_verify", "zap_string_free"]
+        );
+        for function in functions {
+            assert!(
+                header.contains(&format!(" {}(", function)),
+                "{} is missing from include/zap.h",
+                function
+            );
+        }
+        for (status, name) in STATUSES {
+            let declaration = format!("{} = {},", name, status as i32);
+            assert!(header.contains(&declaration), "{} is missing from include/zap.h", declaration);
+        }
+    }
+}
@@ -1,110 +1,292 @@
-use http::{method, Request, Uri};
-use reqwest::blocking::Client as ReqwestClient;
+#[cfg(feature = "native")]
+use reqwest::{Client as ReqwestClient, RequestBuilder};
 use rustls::ProtocolVersion;
-use rustls::{pki_types::ServerName, ClientConfig, KeyLogFile, RootCertStore};
+use rustls::{pki_types::ServerName, CipherSuite, ClientConfig, ClientConnection, KeyLog};
+#[cfg(feature = "native")]
+use serde::{de::DeserializeOwned, Serialize};
+use std::io;
 use std::sync::Arc;
-use std::{
-    io::{self, Read, Write},
-    net::TcpStream,
-};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::TlsConnector;
+use tracing::{debug, info_span, Instrument};
 
-use crate::types::{Endpoint, Proof, SecretsPayload, ZapServerConfig};
-use crate::utils::{extract, serialize};
+use crate::error::ZapError;
+use crate::tls::{bake_proxy_tls_config, bake_target_tls_config};
+use crate::transport::{ProxyStream, Transport};
+#[cfg(feature = "native")]
+use crate::types::ProxyTls;
+use crate::types::{ConnectResponse, Endpoint, Proof, SecretsPayload, TargetTls, ZapServerConfig};
+use crate::utils::{extract, parse, redact, serialize};
 
-pub struct HttpClient {
+fn server_name(host: &str) -> Result<ServerName<'static>, ZapError> {
+    ServerName::try_from(host.to_string())
+        .map_err(|_| ZapError::InvalidConfig(format!("Invalid server name {:?}", host)))
+}
+
+/// Upper bound on the proxy's CONNECT response header block.
+const MAX_CONNECT_RESPONSE_BYTES: usize = 16 * 1024;
+
+#[cfg(not(feature = "native"))]
+pub use crate::wasm::ApiClient;
+
+/// Headers of an API request, authenticated with the session from `session` when there is
+/// one, falling back to the configured credentials.
+pub(crate) fn api_headers(
+    zap_server_config: &ZapServerConfig,
+    session: Option<&ConnectResponse>,
+) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    let session_token = session.and_then(ConnectResponse::get_session_token);
+    if let Some(token) = session_token {
+        headers.push(("Authorization".to_string(), format!("Bearer {}", token)));
+    } else if let Some(credentials) = zap_server_config.get_credentials() {
+        headers.push(("Authorization".to_string(), credentials.get_header_value()));
+    }
+
+    for (name, value) in session.into_iter().flat_map(ConnectResponse::get_session_metadata) {
+        headers.push((name.to_string(), value.to_string()));
+    }
+    headers
+}
+
+/// Client for the proxy's HTTP API, authenticated with the configured credentials.
+#[cfg(feature = "native")]
+pub struct ApiClient {
     client: ReqwestClient,
-    config: ClientConfig,
-    endpoint: Endpoint,
     zap_server_config: ZapServerConfig,
 }
 
-impl HttpClient {
-    pub fn new(endpoint: Endpoint, zap_server_config: ZapServerConfig) -> Self {
-        Self { client: ReqwestClient::new(), endpoint, zap_server_config, config: Self::bake_config() }
+#[cfg(feature = "native")]
+impl ApiClient {
+    pub fn with_tls_config(
+        zap_server_config: ZapServerConfig,
+        proxy_tls_config: Option<Arc<ClientConfig>>,
+    ) -> Result<Self, ZapError> {
+        // reqwest is built without a default crypto provider, so it always gets a rustls config
+        let tls_config = match proxy_tls_config {
+            Some(proxy_tls_config) => proxy_tls_config,
+            None => bake_proxy_tls_config(&ProxyTls::default())?,
+        };
+        let client = ReqwestClient::builder()
+            .use_preconfigured_tls(ClientConfig::clone(&tls_config))
+            .build()
+            .map_err(|e| ZapError::InvalidConfig(format!("Failed to build HTTP client: {}", e)))?;
+
+        Ok(Self { client, zap_server_config })
     }
 
-    fn bake_config() -> ClientConfig {
-        let root_store = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.into() };
-        let mut config = ClientConfig::builder().with_root_certificates(root_store).with_no_client_auth();
+    fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        session: Option<&ConnectResponse>,
+    ) -> RequestBuilder {
+        let url = format!("{}{}", self.zap_server_config.get_api_base_url(), path);
+        let mut request = self.client.request(method, url);
+        for (name, value) in api_headers(&self.zap_server_config, session) {
+            request = request.header(name, value);
+        }
+        request
+    }
 
-        config.key_log = Arc::new(KeyLogFile::new());
-        config.enable_secret_extraction = true;
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, ZapError> {
+        Self::send(self.request(reqwest::Method::GET, path, None)).await
+    }
 
-        config
+    pub async fn post_json<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+        session: Option<&ConnectResponse>,
+    ) -> Result<T, ZapError> {
+        Self::send(self.request(reqwest::Method::POST, path, session).json(body)).await
     }
 
-    fn get_tls_version(&self, conn: &rustls::ClientConnection) -> ProtocolVersion {
-        conn.protocol_version().expect("Failed to get protocol version")
+    async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, ZapError> {
+        let response = request.send().await.map_err(|e| ZapError::ProxyConnect(io::Error::other(e)))?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| ZapError::Io(io::Error::other(e)))?;
+        if !status.is_success() {
+            return Err(ZapError::NotaryRejected { status: status.as_u16(), body });
+        }
+
+        serde_json::from_str(&body).map_err(|e| ZapError::MalformedProof(e.to_string()))
     }
+}
+
+/// Proves endpoints through the proxy. The TLS configurations and the API client are built
+/// once and shared by every proof.
+pub struct HttpClient {
+    api: ApiClient,
+    config: Arc<ClientConfig>,
+    proxy_tls_config: Option<Arc<ClientConfig>>,
+    transport: Arc<dyn Transport>,
+    zap_server_config: ZapServerConfig,
+}
 
-    fn establish_connection(&self) -> io::Result<TcpStream> {
-        let serialized_request =
-            serialize::request(&self.endpoint, http::Method::CONNECT, http::Version::HTTP_11);
-        println!("Connect request: {:?}", serialized_request);
-        let mut sock = TcpStream::connect(self.zap_server_config.get_proxy_url())?;
-        sock.write_all(serialized_request.as_bytes())?;
+impl HttpClient {
+    pub fn new(
+        zap_server_config: ZapServerConfig,
+        target_tls: &TargetTls,
+        key_log: Option<Arc<dyn KeyLog>>,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self, ZapError> {
+        let proxy_tls_config = zap_server_config.get_tls().map(bake_proxy_tls_config).transpose()?;
+        let api = ApiClient::with_tls_config(zap_server_config.clone(), proxy_tls_config.clone())?;
+        // The transport may already encrypt the tunnel to the proxy, e.g. over `wss://`
+        let proxy_tls_config = proxy_tls_config.filter(|_| !transport.is_secure());
+        let config = Arc::new(bake_target_tls_config(target_tls, key_log)?);
 
-        let mut response = [0; 4096];
-        sock.read(&mut response)?;
+        Ok(Self { api, zap_server_config, proxy_tls_config, transport, config })
+    }
 
-        Ok(sock)
+    pub fn api(&self) -> &ApiClient {
+        &self.api
     }
 
-    pub fn perform(&self) -> io::Result<Proof> {
-        let server_name = ServerName::try_from(self.endpoint.get_host().to_string())
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        let mut conn = rustls::ClientConnection::new(Arc::new(self.config.clone()), server_name)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    fn get_tls_version(&self, conn: &ClientConnection) -> Option<ProtocolVersion> {
+        conn.protocol_version()
+    }
 
-        let mut sock = self.establish_connection()?;
+    async fn connect_to_proxy(&self) -> Result<Box<dyn ProxyStream>, ZapError> {
+        let sock = self
+            .transport
+            .connect(&self.zap_server_config.get_proxy_url())
+            .await
+            .map_err(ZapError::ProxyConnect)?;
 
-        if conn.is_handshaking() {
-            conn.process_new_packets().unwrap();
+        match &self.proxy_tls_config {
+            Some(proxy_tls_config) => {
+                let server_name = server_name(self.zap_server_config.get_host())?;
+                let tls = TlsConnector::from(proxy_tls_config.clone())
+                    .connect(server_name, sock)
+                    .await
+                    .map_err(ZapError::ProxyConnect)?;
+                Ok(Box::new(tls))
+            }
+            None => Ok(sock),
         }
+    }
 
-        let serialized_request =
-            serialize::request(&self.endpoint, self.endpoint.get_method(), http::Version::HTTP_11);
-        println!("Endpoint Request: {:?}", serialized_request);
-        let mut tls = rustls::Stream::new(&mut conn, &mut sock);
-        tls.write_all(serialized_request.as_bytes())?;
+    async fn establish_connection(
+        &self,
+        endpoint: &Endpoint,
+    ) -> Result<(Box<dyn ProxyStream>, ConnectResponse), ZapError> {
+        let serialized_request = serialize::connect_request(
+            endpoint,
+            http::Version::HTTP_11,
+            self.zap_server_config.get_credentials(),
+        );
+        debug!(request = %redact::request_head(&serialized_request), "Sending CONNECT request");
+        let mut sock = self.connect_to_proxy().await?;
+        sock.write_all(serialized_request.as_bytes()).await.map_err(ZapError::ProxyConnect)?;
 
-        println!("TLS version: {:?}", self.get_tls_version(&conn));
+        let response = Self::read_connect_response(&mut sock).await?;
+        debug!(
+            status = response.get_status(),
+            session_id = response.get_session_id(),
+            "Received CONNECT response"
+        );
+        if !(200..300).contains(&response.get_status()) {
+            return Err(ZapError::ConnectRejected {
+                status: response.get_status(),
+                reason: response.get_reason().to_string(),
+            });
+        }
 
-        let secrets_payload = self.extract_secrets_payload(conn)?;
-        Ok(self.generate_proof(secrets_payload)?)
+        Ok((sock, response))
     }
 
-    fn extract_secrets_payload(&self, conn: rustls::ClientConnection) -> io::Result<SecretsPayload> {
-        let extracted_secrets = conn.dangerous_extract_secrets().expect("Failed to extract secrets");
-        let secrets_payload = extract::secrets_payload(extracted_secrets);
+    /// Reads the CONNECT response header block. The target has not been sent anything yet,
+    /// so no tunnel bytes can follow it.
+    async fn read_connect_response(sock: &mut Box<dyn ProxyStream>) -> Result<ConnectResponse, ZapError> {
+        let mut buf = Vec::with_capacity(1024);
+        let mut chunk = [0; 1024];
+        loop {
+            let n = sock.read(&mut chunk).await.map_err(ZapError::ProxyConnect)?;
+            if n == 0 {
+                return Err(ZapError::ProxyConnect(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Proxy closed the connection before answering CONNECT",
+                )));
+            }
+            buf.extend_from_slice(&chunk[..n]);
 
-        Ok(secrets_payload)
+            if let Some((response, len)) = parse::connect_response(&buf)? {
+                if len != buf.len() {
+                    return Err(ZapError::ProxyConnect(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Unexpected data after the CONNECT response",
+                    )));
+                }
+                return Ok(response);
+            }
+            if buf.len() > MAX_CONNECT_RESPONSE_BYTES {
+                return Err(ZapError::ProxyConnect(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "CONNECT response header block too large",
+                )));
+            }
+        }
     }
 
-    fn generate_proof(&self, secrets_payload: SecretsPayload) -> io::Result<Proof> {
-        let api_endpoint = format!("http://{}/proof", self.zap_server_config.get_api_url());
-        let payload_json = serde_json::to_string(&secrets_payload).expect("Failed to serialize secrets");
+    pub async fn perform(&self, endpoint: &Endpoint) -> Result<Proof, ZapError> {
+        let server_name = server_name(endpoint.get_host())?;
+        let (sock, connect_response) =
+            self.establish_connection(endpoint).instrument(info_span!("connect")).await?;
 
-        let response = self
-            .client
-            .post(&api_endpoint)
-            .header("Content-Type", "application/json")
-            .body(payload_json)
-            .send();
+        let connector = TlsConnector::from(self.config.clone());
+        let handshake_span = info_span!("handshake");
+        let mut tls = connector
+            .connect(server_name, sock)
+            .instrument(handshake_span.clone())
+            .await
+            .map_err(ZapError::TlsHandshake)?;
+        debug!(
+            parent: &handshake_span,
+            tls_version = ?self.get_tls_version(tls.get_ref().1),
+            cipher_suite = ?tls.get_ref().1.negotiated_cipher_suite().map(|suite| suite.suite()),
+            "Handshake complete"
+        );
 
-        match response {
-            Ok(res) => {
-                if res.status().is_success() {
-                    let response_body = res.text().expect("Failed to read response body");
-                    println!("Response body from proxy /proof: {:?}", response_body);
-                    let proof: Proof =
-                        serde_json::from_str(&response_body).expect("Failed to deserialize proof");
-                    Ok(proof)
-                } else {
-                    Err(io::Error::new(io::ErrorKind::Other, "Failed to send secrets to proxy"))
-                }
+        let serialized_request = serialize::request(endpoint, endpoint.get_method(), http::Version::HTTP_11);
+        debug!(request = %redact::request_head(&serialized_request), "Sending endpoint request");
+        tls.write_all(serialized_request.as_bytes()).await?;
+        tls.flush().await?;
+
+        let (_, conn) = tls.into_inner();
+        let secrets_payload = self.extract_secrets_payload(conn)?;
+        self.generate_proof(secrets_payload, &connect_response).instrument(info_span!("proof")).await
+    }
+
+    fn extract_secrets_payload(&self, conn: ClientConnection) -> Result<SecretsPayload, ZapError> {
+        let suite =
+            conn.negotiated_cipher_suite().map(|suite| suite.suite()).unwrap_or(CipherSuite::Unknown(0));
+        let extracted_secrets =
+            conn.dangerous_extract_secrets().map_err(|e| ZapError::TlsHandshake(io::Error::other(e)))?;
+        extract::secrets_payload(suite, extracted_secrets)
+    }
+
+    async fn generate_proof(
+        &self,
+        secrets_payload: SecretsPayload,
+        connect_response: &ConnectResponse,
+    ) -> Result<Proof, ZapError> {
+        let proof: Proof = match self.api.post_json("/proof", &secrets_payload, Some(connect_response)).await
+        {
+            Ok(proof) => proof,
+            // The notary may have received the secrets, let the caller fetch the attestation later
+            Err(e @ (ZapError::ProxyConnect(_) | ZapError::Io(_))) => {
+                return Err(match connect_response.get_session_id() {
+                    Some(session_id) => {
+                        ZapError::ProofInterrupted { session_id: session_id.to_string(), source: Box::new(e) }
+                    }
+                    None => e,
+                });
             }
-            Err(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
-        }
+            Err(e) => return Err(e),
+        };
+        debug!(key_id = proof.get_key_id(), "Received proof");
+        Ok(proof)
     }
 }
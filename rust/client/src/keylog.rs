@@ -0,0 +1,130 @@
+use rustls::KeyLog;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+/// Entries kept by a [`MemoryKeyLog`], enough for 200 TLS 1.3 handshakes of 5 secrets each.
+const MAX_ENTRIES: usize = 1000;
+
+/// A TLS secret logged during a handshake, in the fields of an NSS key log line. The secret
+/// is wiped when the entry is dropped.
+#[derive(Clone)]
+pub struct KeyLogEntry {
+    label: String,
+    client_random: Vec<u8>,
+    secret: Zeroizing<Vec<u8>>,
+}
+
+impl KeyLogEntry {
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn get_client_random(&self) -> &[u8] {
+        &self.client_random
+    }
+
+    pub fn get_secret(&self) -> &[u8] {
+        &self.secret
+    }
+}
+
+impl fmt::Debug for KeyLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyLogEntry")
+            .field("label", &self.label)
+            .field("client_random", &self.client_random)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Keeps the handshake secrets in memory instead of writing them to `SSLKEYLOGFILE`, so they
+/// can be handed to the proxy for handshake verification.
+///
+/// Secrets stay in memory until taken, drain each handshake's secrets with [`take_for`] once
+/// its proof is done. Past `MAX_ENTRIES` entries, the oldest are wiped to make room.
+///
+/// [`take_for`]: MemoryKeyLog::take_for
+#[derive(Debug, Default)]
+pub struct MemoryKeyLog {
+    entries: Mutex<VecDeque<KeyLogEntry>>,
+}
+
+impl MemoryKeyLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of every secret logged so far.
+    pub fn entries(&self) -> Vec<KeyLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Returns the secrets logged for the handshake identified by `client_random`.
+    pub fn entries_for(&self, client_random: &[u8]) -> Vec<KeyLogEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().filter(|entry| entry.client_random == client_random).cloned().collect()
+    }
+
+    /// Removes and returns the secrets logged for the handshake identified by `client_random`.
+    pub fn take_for(&self, client_random: &[u8]) -> Vec<KeyLogEntry> {
+        let mut entries = self.entries.lock().unwrap();
+        let (taken, kept): (VecDeque<_>, _) =
+            std::mem::take(&mut *entries).into_iter().partition(|entry| entry.client_random == client_random);
+        *entries = kept;
+        taken.into()
+    }
+
+    /// Removes and returns every secret logged so far.
+    pub fn take(&self) -> Vec<KeyLogEntry> {
+        std::mem::take(&mut *self.entries.lock().unwrap()).into()
+    }
+}
+
+impl KeyLog for MemoryKeyLog {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let entry = KeyLogEntry {
+            label: label.to_string(),
+            client_random: client_random.to_vec(),
+            secret: Zeroizing::new(secret.to_vec()),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == MAX_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_the_secrets_of_one_handshake() {
+        let log = MemoryKeyLog::new();
+        log.log("CLIENT_HANDSHAKE_TRAFFIC_SECRET", &[1; 32], &[0xaa; 48]);
+        log.log("CLIENT_HANDSHAKE_TRAFFIC_SECRET", &[2; 32], &[0xbb; 48]);
+        log.log("SERVER_HANDSHAKE_TRAFFIC_SECRET", &[1; 32], &[0xcc; 48]);
+
+        let taken = log.take_for(&[1; 32]);
+        assert_eq!(taken.iter().map(KeyLogEntry::get_secret).collect::<Vec<_>>(), [[0xaa; 48], [0xcc; 48]]);
+        assert!(log.entries_for(&[1; 32]).is_empty());
+        assert_eq!(log.take().len(), 1);
+        assert!(log.entries().is_empty());
+    }
+
+    #[test]
+    fn drops_the_oldest_secrets_past_the_cap() {
+        let log = MemoryKeyLog::new();
+        for i in 0..MAX_ENTRIES + 2 {
+            log.log("CLIENT_TRAFFIC_SECRET_0", &(i as u32).to_be_bytes(), &[0; 48]);
+        }
+        let entries = log.entries();
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].get_client_random(), 2u32.to_be_bytes());
+        assert_eq!(entries.last().unwrap().get_client_random(), ((MAX_ENTRIES + 1) as u32).to_be_bytes());
+    }
+}
@@ -1,9 +1,35 @@
+mod async_client;
+#[cfg(feature = "native")]
 mod client;
+mod error;
+#[cfg(feature = "native")]
+pub mod ffi;
 mod http;
+mod keylog;
+mod tls;
+mod transport;
 mod types;
 mod utils;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(not(any(feature = "native", feature = "wasm")))]
+compile_error!("Enable the `native` feature, or `wasm` for browser builds");
 
 pub mod prelude {
+    #[cfg(feature = "async")]
+    pub use crate::async_client::AsyncZapClient;
+    #[cfg(feature = "native")]
     pub use crate::client::ZapClient;
-    pub use crate::types::{Endpoint, EndpointBuilder, Proof, ZapServerConfig};
+    pub use crate::error::ZapError;
+    pub use crate::keylog::{KeyLogEntry, MemoryKeyLog};
+    #[cfg(feature = "native")]
+    pub use crate::transport::TcpTransport;
+    pub use crate::transport::{ConnectFuture, ProxyStream, Transport};
+    pub use crate::types::{
+        Attestation, Endpoint, EndpointBuilder, HttpResponse, NotaryInfo, NotaryPublicKey, Proof,
+        ProxyCredentials, ProxyTls, TargetTls, ZapServerConfig,
+    };
+    #[cfg(feature = "wasm")]
+    pub use crate::wasm::WebSocketTransport;
 }
@@ -0,0 +1,232 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{CipherSuite, ClientConfig, DigitallySignedStruct, KeyLog, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::ZapError;
+use crate::types::{ProxyTls, TargetTls};
+
+/// CA bundles of common platforms, tried in order when system roots are enabled and
+/// `SSL_CERT_FILE` is not set.
+const SYSTEM_CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/ssl/cert.pem",
+];
+
+/// The provider installed for the process if any, otherwise aws-lc-rs for native builds and
+/// ring for browser builds.
+fn crypto_provider() -> Arc<CryptoProvider> {
+    if let Some(provider) = CryptoProvider::get_default() {
+        return provider.clone();
+    }
+    #[cfg(feature = "native")]
+    let provider = rustls::crypto::aws_lc_rs::default_provider();
+    #[cfg(not(feature = "native"))]
+    let provider = rustls::crypto::ring::default_provider();
+    Arc::new(provider)
+}
+
+fn config_builder(
+    provider: Arc<CryptoProvider>,
+) -> Result<rustls::ConfigBuilder<ClientConfig, rustls::WantsVerifier>, ZapError> {
+    #[cfg(feature = "native")]
+    let builder = ClientConfig::builder_with_provider(provider);
+    // std's clock panics on wasm32-unknown-unknown, certificates are checked against the browser's
+    #[cfg(not(feature = "native"))]
+    let builder = ClientConfig::builder_with_details(provider, Arc::new(crate::wasm::BrowserTime));
+    builder
+        .with_safe_default_protocol_versions()
+        .map_err(|e| ZapError::InvalidConfig(format!("Unusable crypto provider: {}", e)))
+}
+
+/// Builds the TLS configuration used for the proxy's own listeners.
+pub fn bake_proxy_tls_config(tls: &ProxyTls) -> Result<Arc<ClientConfig>, ZapError> {
+    let mut root_store = RootCertStore::empty();
+    match tls.get_ca_file() {
+        Some(ca_file) => add_ca_file(&mut root_store, ca_file)?,
+        None => root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let builder = config_builder(crypto_provider())?.with_root_certificates(root_store);
+    let config = match tls.get_client_identity() {
+        Some((cert_file, key_file)) => with_client_identity(builder, cert_file, key_file)?,
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(Arc::new(config))
+}
+
+/// Builds the TLS configuration for the target, with secret extraction enabled so the
+/// session keys can be handed to the notary. Handshake secrets are only logged to `key_log`.
+pub fn bake_target_tls_config(
+    tls: &TargetTls,
+    key_log: Option<Arc<dyn KeyLog>>,
+) -> Result<ClientConfig, ZapError> {
+    let mut root_store = tls.get_root_store().clone();
+    if tls.uses_webpki_roots() {
+        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    }
+    if tls.uses_system_roots() {
+        add_system_roots(&mut root_store)?;
+    }
+
+    let provider = notary_suites(crypto_provider());
+    let verifier = WebPkiServerVerifier::builder_with_provider(Arc::new(root_store), provider.clone())
+        .build()
+        .map_err(|e| ZapError::InvalidConfig(format!("Invalid target root store: {}", e)))?;
+    let builder = if tls.get_spki_pins().is_empty() {
+        config_builder(provider)?.with_webpki_verifier(verifier)
+    } else {
+        let verifier = PinnedServerVerifier { inner: verifier, pins: tls.get_spki_pins().to_vec() };
+        config_builder(provider)?.dangerous().with_custom_certificate_verifier(Arc::new(verifier))
+    };
+
+    let mut config = match tls.get_client_identity() {
+        Some((cert_file, key_file)) => with_client_identity(builder, cert_file, key_file)?,
+        None => builder.with_no_client_auth(),
+    };
+
+    if let Some(key_log) = key_log {
+        config.key_log = key_log;
+    }
+    config.enable_secret_extraction = true;
+
+    Ok(config)
+}
+
+/// Narrows `provider` to the suites the notary can decrypt, see `cipher_suites` in `GET /info`,
+/// so every session the client can open to the target can also be proven.
+fn notary_suites(provider: Arc<CryptoProvider>) -> Arc<CryptoProvider> {
+    let mut provider = CryptoProvider::clone(&provider);
+    provider.cipher_suites.retain(|suite| suite.suite() == CipherSuite::TLS13_AES_256_GCM_SHA384);
+    Arc::new(provider)
+}
+
+fn with_client_identity(
+    builder: rustls::ConfigBuilder<ClientConfig, rustls::client::WantsClientCert>,
+    cert_file: &Path,
+    key_file: &Path,
+) -> Result<ClientConfig, ZapError> {
+    let key = PrivateKeyDer::from_pem_file(key_file).map_err(|e| {
+        ZapError::InvalidConfig(format!("Failed to load client key from {}: {}", key_file.display(), e))
+    })?;
+    builder
+        .with_client_auth_cert(load_certs(cert_file)?, key)
+        .map_err(|e| ZapError::InvalidConfig(format!("Invalid client certificate or key: {}", e)))
+}
+
+fn add_ca_file(root_store: &mut RootCertStore, ca_file: &Path) -> Result<(), ZapError> {
+    for cert in load_certs(ca_file)? {
+        root_store
+            .add(cert)
+            .map_err(|e| ZapError::InvalidConfig(format!("Invalid CA certificate: {}", e)))?;
+    }
+    Ok(())
+}
+
+fn add_system_roots(root_store: &mut RootCertStore) -> Result<(), ZapError> {
+    let bundle = match env::var_os("SSL_CERT_FILE") {
+        Some(path) => Some(path.into()),
+        None => SYSTEM_CA_BUNDLES.iter().map(Path::new).find(|path| path.is_file()).map(Path::to_path_buf),
+    };
+    let Some(bundle) = bundle else {
+        return Err(ZapError::InvalidConfig("No system CA bundle found, set SSL_CERT_FILE".to_string()));
+    };
+
+    // System bundles may hold certificates webpki cannot use, those are skipped
+    let (added, _ignored) = root_store.add_parsable_certificates(load_certs(&bundle)?);
+    if added == 0 {
+        return Err(ZapError::InvalidConfig(format!("No usable certificates in {}", bundle.display())));
+    }
+    Ok(())
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, ZapError> {
+    CertificateDer::pem_file_iter(path).and_then(|certs| certs.collect::<Result<Vec<_>, _>>()).map_err(|e| {
+        ZapError::InvalidConfig(format!("Failed to load certificates from {}: {}", path.display(), e))
+    })
+}
+
+/// Verifies the chain as usual, then requires the end-entity key to match one of the SPKI
+/// pins configured for the host, if any.
+#[derive(Debug)]
+struct PinnedServerVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<(String, [u8; 32])>,
+}
+
+impl ServerCertVerifier for PinnedServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified =
+            self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+
+        let host = server_name.to_str();
+        let pins: Vec<&[u8; 32]> = self
+            .pins
+            .iter()
+            .filter(|(pinned_host, _)| pinned_host.eq_ignore_ascii_case(&host))
+            .map(|(_, pin)| pin)
+            .collect();
+        if pins.is_empty() {
+            return Ok(verified);
+        }
+
+        let cert = webpki::EndEntityCert::try_from(end_entity)
+            .map_err(|e| rustls::Error::General(format!("Invalid end-entity certificate: {}", e)))?;
+        let digest: [u8; 32] = Sha256::digest(cert.subject_public_key_info().as_ref()).into();
+        if pins.contains(&&digest) {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General(format!("Certificate for {} does not match any SPKI pin", host)))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_config_offers_only_notary_suites() {
+        let config = bake_target_tls_config(&TargetTls::default(), None).unwrap();
+        let suites: Vec<CipherSuite> =
+            config.crypto_provider().cipher_suites.iter().map(|suite| suite.suite()).collect();
+        assert_eq!(suites, [CipherSuite::TLS13_AES_256_GCM_SHA384]);
+    }
+}
@@ -0,0 +1,46 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+#[cfg(feature = "native")]
+use tokio::net::TcpStream;
+
+/// Connection to the proxy's CONNECT listener, as returned by a [`Transport`].
+pub trait ProxyStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ProxyStream for T {}
+
+/// The connection being opened by [`Transport::connect`].
+pub type ConnectFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Box<dyn ProxyStream>>> + Send + 'a>>;
+
+/// Opens the connections to the proxy's CONNECT listener, TCP by default.
+///
+/// Supply another transport where raw sockets are unavailable, e.g. one carrying the bytes
+/// over a WebSocket to the proxy listener. TLS to the proxy is still added on top when
+/// [`ProxyTls`](crate::prelude::ProxyTls) is configured, unless the transport reports that
+/// it already encrypts the connection.
+pub trait Transport: Send + Sync {
+    /// Connects to the proxy listener at `address`, in `host:port` form.
+    fn connect<'a>(&'a self, address: &'a str) -> ConnectFuture<'a>;
+
+    /// Whether the connections are already encrypted to the proxy, e.g. over `wss://`.
+    fn is_secure(&self) -> bool {
+        false
+    }
+}
+
+/// Connects to the proxy over plain TCP.
+#[cfg(feature = "native")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TcpTransport;
+
+#[cfg(feature = "native")]
+impl Transport for TcpTransport {
+    fn connect<'a>(&'a self, address: &'a str) -> ConnectFuture<'a> {
+        Box::pin(async move {
+            let stream = TcpStream::connect(address).await?;
+            Ok(Box::new(stream) as Box<dyn ProxyStream>)
+        })
+    }
+}
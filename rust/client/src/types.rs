@@ -1,120 +1,146 @@
-use serde::{Deserialize, Serialize};
+use rustls::RootCertStore;
+use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Debug, Clone)]
-pub struct SecretData {
-    cipher_suite: String,
-    key: String,
-    iv: String,
+pub use zap_types::{
+    Attestation, Endpoint, HttpResponse, NotaryInfo, NotaryPublicKey, Proof, ProxyCredentials, ProxyTls,
+    SecretData, SecretsPayload, ZapServerConfig, SESSION_ID_HEADER, SESSION_TOKEN_HEADER,
+};
+
+use crate::error::ZapError;
+
+/// TLS settings for the connection to the target through the tunnel.
+///
+/// By default the target is verified against the public web PKI roots.
+#[derive(Debug, Clone)]
+pub struct TargetTls {
+    root_store: RootCertStore,
+    webpki_roots: bool,
+    system_roots: bool,
+    spki_pins: Vec<(String, [u8; 32])>,
+    client_cert_file: Option<PathBuf>,
+    client_key_file: Option<PathBuf>,
 }
 
-impl SecretData {
-    pub fn new(cipher_suite: &str, key: &str, iv: &str) -> Self {
-        SecretData { cipher_suite: cipher_suite.to_string(), key: key.to_string(), iv: iv.to_string() }
+impl Default for TargetTls {
+    fn default() -> Self {
+        Self {
+            root_store: RootCertStore::empty(),
+            webpki_roots: true,
+            system_roots: false,
+            spki_pins: vec![],
+            client_cert_file: None,
+            client_key_file: None,
+        }
     }
 }
 
-#[derive(Serialize, Debug, Clone)]
-pub struct SecretsPayload {
-    rx_sequence_number: u64,
-    tx_sequence_number: u64,
-    rx_secret: SecretData,
-    tx_secret: SecretData,
-}
+impl TargetTls {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-impl SecretsPayload {
-    pub fn new(
-        rx_sequence_number: u64,
-        tx_sequence_number: u64,
-        rx_secret: SecretData,
-        tx_secret: SecretData,
-    ) -> Self {
-        SecretsPayload { rx_sequence_number, tx_sequence_number, rx_secret, tx_secret }
+    /// Trusts the roots in `root_store` instead of the public web PKI roots. Call
+    /// `webpki_roots(true)` afterwards to trust both.
+    pub fn root_store(mut self, root_store: RootCertStore) -> Self {
+        self.root_store = root_store;
+        self.webpki_roots = false;
+        self
     }
-}
 
-#[derive(Serialize, Debug, Clone)]
-pub struct ZapServerConfig {
-    host: String,
-    api_port: u16,
-    proxy_port: u16,
-}
+    pub fn webpki_roots(mut self, enabled: bool) -> Self {
+        self.webpki_roots = enabled;
+        self
+    }
+
+    /// Also trusts the platform CA bundle, read from `SSL_CERT_FILE` or the usual locations.
+    pub fn system_roots(mut self, enabled: bool) -> Self {
+        self.system_roots = enabled;
+        self
+    }
 
-impl ZapServerConfig {
-    pub fn new(host: &str, api_port: u16, proxy_port: u16) -> Self {
-        Self { host: host.to_string(), api_port, proxy_port }
+    /// Requires the certificate of `host` to carry a public key whose SHA-256 SPKI digest is
+    /// `digest`. Pinning the same host several times accepts any of the pinned keys.
+    pub fn pin_spki_sha256(mut self, host: &str, digest: [u8; 32]) -> Self {
+        self.spki_pins.push((host.to_string(), digest));
+        self
     }
 
-    pub fn default() -> Self {
-        Self::new("localhost", 8080, 55688)
+    /// Presents a client certificate to targets that require mTLS.
+    pub fn client_identity<P: Into<PathBuf>>(mut self, cert_file: P, key_file: P) -> Self {
+        self.client_cert_file = Some(cert_file.into());
+        self.client_key_file = Some(key_file.into());
+        self
     }
 
-    pub fn get_host(&self) -> &str {
-        &self.host
+    pub fn get_root_store(&self) -> &RootCertStore {
+        &self.root_store
     }
 
-    pub fn get_api_port(&self) -> u16 {
-        self.api_port
+    pub fn uses_webpki_roots(&self) -> bool {
+        self.webpki_roots
     }
 
-    pub fn get_proxy_port(&self) -> u16 {
-        self.proxy_port
+    pub fn uses_system_roots(&self) -> bool {
+        self.system_roots
     }
 
-    pub fn get_proxy_url(&self) -> String {
-        format!("{}:{}", self.host, self.proxy_port)
+    pub fn get_spki_pins(&self) -> &[(String, [u8; 32])] {
+        &self.spki_pins
     }
 
-    pub fn get_api_url(&self) -> String {
-        format!("{}:{}", self.host, self.api_port)
+    pub fn get_client_identity(&self) -> Option<(&Path, &Path)> {
+        self.client_cert_file.as_deref().zip(self.client_key_file.as_deref())
     }
 }
 
+/// Prefix of the session metadata headers the proxy may return on CONNECT.
+const SESSION_HEADER_PREFIX: &str = "x-zap-";
+
+/// The proxy's answer to a CONNECT request.
 #[derive(Debug, Clone)]
-pub struct Endpoint {
-    host: String,
-    port: u16,
-    route: String,
-    method: http::Method,
+pub struct ConnectResponse {
+    status: u16,
+    reason: String,
     headers: Vec<(String, String)>,
 }
 
-impl Endpoint {
-    pub fn new(
-        host: &str,
-        port: u16,
-        route: &str,
-        method: http::Method,
-        headers: Vec<(String, String)>,
-    ) -> Self {
-        Endpoint { host: host.to_string(), port, route: route.to_string(), method, headers }
-    }
-
-    pub fn default() -> Self {
-        Endpoint::new("www.example.com", 443, "/", http::Method::GET, vec![])
+impl ConnectResponse {
+    pub fn new(status: u16, reason: &str, headers: Vec<(String, String)>) -> Self {
+        ConnectResponse { status, reason: reason.to_string(), headers }
     }
 
-    pub fn get_host(&self) -> &str {
-        &self.host
+    pub fn get_status(&self) -> u16 {
+        self.status
     }
 
-    pub fn get_port(&self) -> u16 {
-        self.port
+    pub fn get_reason(&self) -> &str {
+        &self.reason
     }
 
-    pub fn get_url(&self) -> String {
-        format!("{}:{}", self.host, self.port)
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
     }
 
-    pub fn get_route(&self) -> &str {
-        &self.route
+    pub fn get_session_token(&self) -> Option<&str> {
+        self.get_header(SESSION_TOKEN_HEADER)
     }
 
-    pub fn get_method(&self) -> http::Method {
-        self.method.clone()
+    pub fn get_session_id(&self) -> Option<&str> {
+        self.get_header(SESSION_ID_HEADER)
     }
 
-    pub fn get_headers(&self) -> Vec<(String, String)> {
-        self.headers.clone()
+    /// The `X-Zap-*` headers describing the session, other than the session token.
+    pub fn get_session_metadata(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers
+            .iter()
+            .filter(|(name, _)| {
+                name.to_ascii_lowercase().starts_with(SESSION_HEADER_PREFIX)
+                    && !name.eq_ignore_ascii_case(SESSION_TOKEN_HEADER)
+            })
+            .map(|(name, value)| (name.as_str(), value.as_str()))
     }
 }
 
@@ -127,6 +153,12 @@ pub struct EndpointBuilder {
     headers: Vec<(String, String)>,
 }
 
+impl Default for EndpointBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EndpointBuilder {
     pub fn new() -> Self {
         EndpointBuilder { host: None, port: None, route: None, method: None, headers: vec![] }
@@ -157,33 +189,14 @@ impl EndpointBuilder {
         self
     }
 
-    pub fn build(self) -> Endpoint {
-        Endpoint::new(
-            self.host.as_deref().expect("Host is required"),
-            self.port.expect("Port is required"),
+    pub fn build(self) -> Result<Endpoint, ZapError> {
+        let missing = |field: &str| ZapError::InvalidConfig(format!("Endpoint {} is required", field));
+        Ok(Endpoint::new(
+            self.host.as_deref().ok_or_else(|| missing("host"))?,
+            self.port.ok_or_else(|| missing("port"))?,
             self.route.as_deref().unwrap_or("/"),
-            self.method.expect("Method is required"),
+            self.method.ok_or_else(|| missing("method"))?,
             self.headers,
-        )
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Proof {
-    data: String,
-    signature: String,
-}
-
-impl Proof {
-    pub fn new(data: &str, signature: &str) -> Self {
-        Proof { data: data.to_string(), signature: signature.to_string() }
-    }
-
-    pub fn get_data(&self) -> &str {
-        &self.data
-    }
-
-    pub fn get_signature(&self) -> &str {
-        &self.signature
+        ))
     }
 }
@@ -1,23 +1,35 @@
 pub mod serialize {
-    use crate::types::Endpoint;
+    use crate::types::{Endpoint, ProxyCredentials};
 
-    pub fn request(endpoint: &Endpoint, http_method: http::Method, http_version: http::Version) -> String {
-        let mut request_string;
-
-        if http_method == http::Method::CONNECT {
-            request_string = format!(
-                "{} {}:{} {:?}\r\n",
-                http_method,
-                endpoint.get_host(),
-                endpoint.get_port(),
-                http_version
-            );
-        } else {
-            request_string = format!("{} {} {:?}\r\n", http_method, endpoint.get_route(), http_version);
+    /// Serializes the CONNECT request sent to the proxy. Only the proxy's own headers are
+    /// included, the endpoint headers are meant for the target and travel inside the tunnel.
+    pub fn connect_request(
+        endpoint: &Endpoint,
+        http_version: http::Version,
+        credentials: Option<&ProxyCredentials>,
+    ) -> String {
+        let mut request_string = format!(
+            "{} {} {:?}\r\nHost: {}\r\n",
+            http::Method::CONNECT,
+            endpoint.get_url(),
+            http_version,
+            endpoint.get_url()
+        );
+
+        if let Some(credentials) = credentials {
+            request_string.push_str(&format!("Proxy-Authorization: {}\r\n", credentials.get_header_value()));
         }
 
+        request_string.push_str("\r\n");
+
+        request_string
+    }
+
+    pub fn request(endpoint: &Endpoint, http_method: http::Method, http_version: http::Version) -> String {
+        let mut request_string = format!("{} {} {:?}\r\n", http_method, endpoint.get_route(), http_version);
+
         let mut headers = endpoint.get_headers().clone();
-        headers.push(("Host".to_string(), endpoint.get_host().to_string()));
+        headers.push(("Host".to_string(), endpoint.get_authority_host()));
         headers.push(("Connection".to_string(), "close".to_string()));
 
         for (name, value) in headers {
@@ -31,33 +43,142 @@ pub mod serialize {
 }
 
 pub mod extract {
+    use crate::error::ZapError;
     use crate::types::{SecretData, SecretsPayload};
-    use rustls::{ConnectionTrafficSecrets, ExtractedSecrets};
+    use rustls::{CipherSuite, ConnectionTrafficSecrets, ExtractedSecrets};
 
-    fn secret_data(secret: ConnectionTrafficSecrets) -> SecretData {
+    /// Only AES-256-GCM secrets are sent, the notary can't decrypt other suites (see
+    /// `cipher_suites` in `GET /info`) so the proof fails here instead of at the notary.
+    fn secret_data(suite: CipherSuite, secret: ConnectionTrafficSecrets) -> Result<SecretData, ZapError> {
         match secret {
-            ConnectionTrafficSecrets::Aes128Gcm { ref key, ref iv } => {
-                SecretData::new("Aes128Gcm", &hex::encode(key.as_ref()), &hex::encode(iv.as_ref()))
-            }
             ConnectionTrafficSecrets::Aes256Gcm { ref key, ref iv } => {
-                SecretData::new("Aes256Gcm", &hex::encode(key.as_ref()), &hex::encode(iv.as_ref()))
+                Ok(SecretData::new("Aes256Gcm", key.as_ref(), iv.as_ref()))
             }
-            ConnectionTrafficSecrets::Chacha20Poly1305 { ref key, ref iv } => {
-                SecretData::new("Chacha20Poly1305", &hex::encode(key.as_ref()), &hex::encode(iv.as_ref()))
-            }
-            _ => panic!("Unsupported cipher suite, unable to extract secrets"),
+            _ => Err(ZapError::UnsupportedCipherSuite(format!("{:?}", suite))),
         }
     }
 
-    pub fn secrets_payload(extracted_secrets: ExtractedSecrets) -> SecretsPayload {
+    /// `suite` is the connection's negotiated suite, named in the error if it is unsupported.
+    pub fn secrets_payload(
+        suite: CipherSuite,
+        extracted_secrets: ExtractedSecrets,
+    ) -> Result<SecretsPayload, ZapError> {
         let (rx_sequence_number, rx_secret) = extracted_secrets.rx;
         let (tx_sequence_number, tx_secret) = extracted_secrets.tx;
 
-        SecretsPayload::new(
+        Ok(SecretsPayload::new(
             rx_sequence_number,
             tx_sequence_number,
-            secret_data(rx_secret),
-            secret_data(tx_secret),
-        )
+            secret_data(suite, rx_secret)?,
+            secret_data(suite, tx_secret)?,
+        ))
+    }
+}
+
+pub mod parse {
+    use crate::error::ZapError;
+    use crate::types::ConnectResponse;
+    use std::io;
+
+    const MAX_HEADERS: usize = 64;
+
+    /// Parses the proxy's response to a CONNECT request. Returns `None` while the header
+    /// block is incomplete, otherwise the response and the length of the header block.
+    pub fn connect_response(buf: &[u8]) -> Result<Option<(ConnectResponse, usize)>, ZapError> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut response = httparse::Response::new(&mut headers);
+        let len = match response.parse(buf) {
+            Ok(httparse::Status::Complete(len)) => len,
+            Ok(httparse::Status::Partial) => return Ok(None),
+            Err(e) => return Err(malformed(format!("Malformed CONNECT response: {}", e))),
+        };
+
+        let status = response.code.ok_or_else(|| malformed("CONNECT response has no status".to_string()))?;
+        let headers = response
+            .headers
+            .iter()
+            .map(|header| {
+                let value = std::str::from_utf8(header.value)
+                    .map_err(|_| malformed(format!("Invalid value for header {}", header.name)))?;
+                Ok((header.name.to_string(), value.to_string()))
+            })
+            .collect::<Result<Vec<_>, ZapError>>()?;
+
+        Ok(Some((ConnectResponse::new(status, response.reason.unwrap_or(""), headers), len)))
+    }
+
+    fn malformed(reason: String) -> ZapError {
+        ZapError::ProxyConnect(io::Error::new(io::ErrorKind::InvalidData, reason))
+    }
+}
+
+pub mod redact {
+    /// Headers whose values are credentials and never logged.
+    const SENSITIVE_HEADERS: &[&str] =
+        &["authorization", "proxy-authorization", "cookie", "set-cookie", "x-zap-session-token"];
+
+    /// Returns a serialized request head with the values of credential headers replaced,
+    /// safe to log.
+    pub fn request_head(head: &str) -> String {
+        head.split("\r\n")
+            .map(|line| match line.split_once(':') {
+                Some((name, _)) if SENSITIVE_HEADERS.contains(&name.trim().to_ascii_lowercase().as_str()) => {
+                    format!("{}: [redacted]", name)
+                }
+                _ => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\r\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract, serialize};
+    use crate::error::ZapError;
+    use crate::types::Endpoint;
+    use rustls::crypto::cipher::Iv;
+    use rustls::{CipherSuite, ConnectionTrafficSecrets, ExtractedSecrets};
+
+    #[test]
+    fn connect_request_brackets_ipv6_targets() {
+        let endpoint = Endpoint::new("::1", 8443, "/", http::Method::GET, vec![]);
+        let request = serialize::connect_request(&endpoint, http::Version::HTTP_11, None);
+        assert_eq!(request, "CONNECT [::1]:8443 HTTP/1.1\r\nHost: [::1]:8443\r\n\r\n");
+
+        let request = serialize::request(&endpoint, http::Method::GET, http::Version::HTTP_11);
+        assert!(request.contains("\r\nHost: [::1]\r\n"));
+    }
+
+    #[test]
+    fn connect_request_keeps_names_and_ipv4_unbracketed() {
+        for host in ["example.com", "127.0.0.1"] {
+            let endpoint = Endpoint::new(host, 443, "/", http::Method::GET, vec![]);
+            let request = serialize::connect_request(&endpoint, http::Version::HTTP_11, None);
+            assert!(request.starts_with(&format!("CONNECT {}:443 HTTP/1.1\r\n", host)));
+        }
+    }
+
+    fn extracted(secret: fn() -> ConnectionTrafficSecrets) -> ExtractedSecrets {
+        ExtractedSecrets { tx: (1, secret()), rx: (2, secret()) }
+    }
+
+    #[test]
+    fn extracts_only_aes_256_gcm_secrets() {
+        let aes256 = || ConnectionTrafficSecrets::Aes256Gcm { key: [7; 32].into(), iv: Iv::new([9; 12]) };
+        let payload =
+            extract::secrets_payload(CipherSuite::TLS13_AES_256_GCM_SHA384, extracted(aes256)).unwrap();
+        assert_eq!(payload.get_rx_sequence_number(), 2);
+        assert_eq!(payload.get_rx_secret().get_cipher_suite(), "Aes256Gcm");
+        assert_eq!(payload.get_tx_secret().get_key(), [7; 32]);
+
+        let chacha =
+            || ConnectionTrafficSecrets::Chacha20Poly1305 { key: [7; 32].into(), iv: Iv::new([9; 12]) };
+        match extract::secrets_payload(CipherSuite::TLS13_CHACHA20_POLY1305_SHA256, extracted(chacha)) {
+            Err(ZapError::UnsupportedCipherSuite(suite)) => {
+                assert_eq!(suite, "TLS13_CHACHA20_POLY1305_SHA256")
+            }
+            other => panic!("expected an unsupported cipher suite, got {:?}", other.map(|_| ())),
+        }
     }
 }
@@ -0,0 +1,378 @@
+//! Browser support for the `wasm` feature. Browsers have neither raw sockets nor a clock std
+//! can read on wasm32-unknown-unknown, so tunnels go over a WebSocket to the proxy listener,
+//! the HTTP API is called with `fetch`, and certificates are checked against `Date.now()`.
+//!
+//! Everything runs on the page's or worker's event loop, the client's futures are driven with
+//! `wasm_bindgen_futures::spawn_local`.
+//!
+//! The browser tests below need a wasm32 toolchain and a browser, run them from `rust/client` with
+//! `wasm-pack test --headless --chrome -- --no-default-features --features wasm`.
+
+use std::cell::RefCell;
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use futures_channel::{mpsc, oneshot};
+use futures_util::StreamExt;
+use js_sys::{ArrayBuffer, Uint8Array};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{BinaryType, CloseEvent, Event, MessageEvent, WebSocket};
+
+use crate::transport::{ConnectFuture, ProxyStream, Transport};
+
+#[cfg(not(feature = "native"))]
+pub use fetch::{ApiClient, BrowserTime};
+
+/// Connects to the proxy listener over a WebSocket, which the proxy accepts on the same port
+/// as CONNECT requests.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WebSocketTransport {
+    secure: bool,
+}
+
+impl WebSocketTransport {
+    /// Connects over `wss://` when `secure` is set, which the proxy serves when its listeners
+    /// use TLS. The browser then verifies the proxy's certificate, not the client's `ProxyTls`.
+    pub fn new(secure: bool) -> Self {
+        Self { secure }
+    }
+
+    fn url(&self, address: &str) -> String {
+        let scheme = if self.secure { "wss" } else { "ws" };
+        format!("{}://{}/", scheme, address)
+    }
+}
+
+impl Transport for WebSocketTransport {
+    fn connect<'a>(&'a self, address: &'a str) -> ConnectFuture<'a> {
+        // The socket itself stays on this thread, the returned future only holds channels
+        let opening = open(&self.url(address));
+        Box::pin(async move {
+            let (opened, stream) = opening?;
+            match opened.await {
+                Ok(Ok(())) => Ok(Box::new(stream) as Box<dyn ProxyStream>),
+                Ok(Err(e)) => Err(e),
+                Err(_) => {
+                    Err(io::Error::new(io::ErrorKind::ConnectionAborted, "WebSocket dropped while opening"))
+                }
+            }
+        })
+    }
+
+    fn is_secure(&self) -> bool {
+        self.secure
+    }
+}
+
+/// Opens a WebSocket to `url`. The receiver resolves once it is open, or failed to open.
+fn open(url: &str) -> io::Result<(oneshot::Receiver<io::Result<()>>, WebSocketStream)> {
+    let socket = WebSocket::new(url).map_err(js_error)?;
+    socket.set_binary_type(BinaryType::Arraybuffer);
+
+    let (incoming_sender, incoming) = mpsc::unbounded();
+    let (outgoing, mut outgoing_receiver) = mpsc::unbounded::<Vec<u8>>();
+    let (opened_sender, opened) = oneshot::channel();
+    let opened_sender = Rc::new(RefCell::new(Some(opened_sender)));
+
+    let on_open = {
+        let opened_sender = opened_sender.clone();
+        Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            if let Some(sender) = opened_sender.borrow_mut().take() {
+                let _ = sender.send(Ok(()));
+            }
+        })
+    };
+    let on_message = {
+        let incoming_sender = incoming_sender.clone();
+        // The proxy only sends binary messages
+        Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            if let Ok(buffer) = event.data().dyn_into::<ArrayBuffer>() {
+                let _ = incoming_sender.unbounded_send(Uint8Array::new(&buffer).to_vec());
+            }
+        })
+    };
+    // Browsers follow every error with a close event, which ends the stream
+    let on_close = Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+        if let Some(sender) = opened_sender.borrow_mut().take() {
+            let message = format!("WebSocket closed while opening, code {}", event.code());
+            let _ = sender.send(Err(io::Error::new(io::ErrorKind::ConnectionRefused, message)));
+        }
+        incoming_sender.close_channel();
+    });
+    socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+    // Owns the socket and its handlers until the stream is shut down or dropped
+    wasm_bindgen_futures::spawn_local(async move {
+        while let Some(data) = outgoing_receiver.next().await {
+            if socket.send_with_u8_array(&data).is_err() {
+                break;
+            }
+        }
+        socket.set_onopen(None);
+        socket.set_onmessage(None);
+        socket.set_onclose(None);
+        let _ = socket.close();
+        drop((on_open, on_message, on_close));
+    });
+
+    Ok((opened, WebSocketStream { incoming, outgoing, read_buffer: Vec::new() }))
+}
+
+fn js_error(value: JsValue) -> io::Error {
+    let message = match value.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()),
+        None => value.as_string().unwrap_or_else(|| format!("{:?}", value)),
+    };
+    io::Error::other(message)
+}
+
+/// Byte stream over the messages of a WebSocket, see [`open`].
+struct WebSocketStream {
+    incoming: mpsc::UnboundedReceiver<Vec<u8>>,
+    outgoing: mpsc::UnboundedSender<Vec<u8>>,
+    /// Rest of the last message, not read yet.
+    read_buffer: Vec<u8>,
+}
+
+impl AsyncRead for WebSocketStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.read_buffer.is_empty() {
+            match self.incoming.poll_next_unpin(cx) {
+                Poll::Ready(Some(message)) => self.read_buffer = message,
+                // Closed by either side
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = self.read_buffer.len().min(buf.remaining());
+        buf.put_slice(&self.read_buffer[..n]);
+        self.read_buffer.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for WebSocketStream {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        if data.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        // The browser buffers what the socket can't send yet
+        match self.outgoing.unbounded_send(data.to_vec()) {
+            Ok(()) => Poll::Ready(Ok(data.len())),
+            Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.outgoing.close_channel();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// What browser builds use in place of reqwest and the system clock.
+#[cfg(not(feature = "native"))]
+mod fetch {
+    use std::sync::Arc;
+
+    use rustls::pki_types::UnixTime;
+    use rustls::time_provider::TimeProvider;
+    use rustls::ClientConfig;
+    use serde::{de::DeserializeOwned, Serialize};
+    use std::time::Duration;
+    use wasm_bindgen::{JsCast, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{Request, RequestInit, Response, Window, WorkerGlobalScope};
+    use zeroize::Zeroizing;
+
+    use super::js_error;
+    use crate::error::ZapError;
+    use crate::http::api_headers;
+    use crate::types::{ConnectResponse, ZapServerConfig};
+
+    /// Client for the proxy's HTTP API over `fetch`, authenticated with the configured
+    /// credentials. Works in pages and in workers, such as extension service workers.
+    pub struct ApiClient {
+        zap_server_config: ZapServerConfig,
+    }
+
+    impl ApiClient {
+        /// The browser verifies the API's certificate itself, `_proxy_tls_config` is unused.
+        pub fn with_tls_config(
+            zap_server_config: ZapServerConfig,
+            _proxy_tls_config: Option<Arc<ClientConfig>>,
+        ) -> Result<Self, ZapError> {
+            Ok(Self { zap_server_config })
+        }
+
+        pub async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, ZapError> {
+            self.send("GET", path, None, None).await
+        }
+
+        pub async fn post_json<B: Serialize, T: DeserializeOwned>(
+            &self,
+            path: &str,
+            body: &B,
+            session: Option<&ConnectResponse>,
+        ) -> Result<T, ZapError> {
+            // The body of `/proof` holds the session keys
+            let body = Zeroizing::new(
+                serde_json::to_string(body).map_err(|e| ZapError::InvalidConfig(e.to_string()))?,
+            );
+            self.send("POST", path, Some(&body), session).await
+        }
+
+        async fn send<T: DeserializeOwned>(
+            &self,
+            method: &str,
+            path: &str,
+            body: Option<&str>,
+            session: Option<&ConnectResponse>,
+        ) -> Result<T, ZapError> {
+            let init = RequestInit::new();
+            init.set_method(method);
+            if let Some(body) = body {
+                init.set_body(&JsValue::from_str(body));
+            }
+            let url = format!("{}{}", self.zap_server_config.get_api_base_url(), path);
+            let request = Request::new_with_str_and_init(&url, &init)
+                .map_err(|e| ZapError::InvalidConfig(js_error(e).to_string()))?;
+
+            let headers = request.headers();
+            let mut request_headers = api_headers(&self.zap_server_config, session);
+            if body.is_some() {
+                request_headers.push(("Content-Type".to_string(), "application/json".to_string()));
+            }
+            for (name, value) in request_headers {
+                headers.set(&name, &value).map_err(|e| ZapError::InvalidConfig(js_error(e).to_string()))?;
+            }
+
+            let response =
+                JsFuture::from(fetch(&request)?).await.map_err(|e| ZapError::ProxyConnect(js_error(e)))?;
+            let response: Response = response.dyn_into().map_err(|_| {
+                ZapError::ProxyConnect(std::io::Error::other("fetch did not return a Response"))
+            })?;
+            let text = response.text().map_err(|e| ZapError::Io(js_error(e)))?;
+            let text = JsFuture::from(text).await.map_err(|e| ZapError::Io(js_error(e)))?;
+            let body = text.as_string().unwrap_or_default();
+            if !response.ok() {
+                return Err(ZapError::NotaryRejected { status: response.status(), body });
+            }
+
+            serde_json::from_str(&body).map_err(|e| ZapError::MalformedProof(e.to_string()))
+        }
+    }
+
+    /// `fetch` of the window, or of the worker the client runs in.
+    fn fetch(request: &Request) -> Result<js_sys::Promise, ZapError> {
+        let global = js_sys::global();
+        if let Some(window) = global.dyn_ref::<Window>() {
+            Ok(window.fetch_with_request(request))
+        } else if let Some(worker) = global.dyn_ref::<WorkerGlobalScope>() {
+            Ok(worker.fetch_with_request(request))
+        } else {
+            Err(ZapError::InvalidConfig("fetch is not available in this context".to_string()))
+        }
+    }
+
+    /// The browser's wall clock, for certificate validity checks.
+    #[derive(Debug)]
+    pub struct BrowserTime;
+
+    impl TimeProvider for BrowserTime {
+        fn current_time(&self) -> Option<UnixTime> {
+            let millis = js_sys::Date::now();
+            (millis >= 0.0).then(|| UnixTime::since_unix_epoch(Duration::from_millis(millis as u64)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_websocket_urls() {
+        assert_eq!(WebSocketTransport::new(false).url("proxy.example:55688"), "ws://proxy.example:55688/");
+        assert_eq!(WebSocketTransport::new(true).url("proxy.example:55688"), "wss://proxy.example:55688/");
+        assert!(WebSocketTransport::new(true).is_secure());
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod browser_tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    use super::*;
+    use crate::error::ZapError;
+    use crate::types::ZapServerConfig;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    /// Nothing listens here on the test machine.
+    const CLOSED_PORT: u16 = 59_999;
+
+    #[wasm_bindgen_test]
+    async fn stream_reads_across_messages_and_writes_one_message_per_write() {
+        let (incoming_sender, incoming) = mpsc::unbounded();
+        let (outgoing, mut outgoing_receiver) = mpsc::unbounded();
+        let mut stream = WebSocketStream { incoming, outgoing, read_buffer: Vec::new() };
+
+        incoming_sender.unbounded_send(b"hel".to_vec()).unwrap();
+        incoming_sender.unbounded_send(b"lo, world".to_vec()).unwrap();
+        incoming_sender.close_channel();
+        let mut start = [0u8; 5];
+        stream.read_exact(&mut start).await.unwrap();
+        assert_eq!(&start, b"hello");
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b", world");
+
+        stream.write_all(b"ping").await.unwrap();
+        assert_eq!(stream.write(b"").await.unwrap(), 0);
+        stream.shutdown().await.unwrap();
+        assert_eq!(outgoing_receiver.next().await.unwrap(), b"ping");
+        assert_eq!(outgoing_receiver.next().await, None);
+        assert_eq!(stream.write(b"late").await.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[wasm_bindgen_test]
+    async fn connect_fails_when_the_socket_closes_while_opening() {
+        let address = format!("127.0.0.1:{}", CLOSED_PORT);
+        let error = match WebSocketTransport::new(false).connect(&address).await {
+            Ok(_) => panic!("connected to a closed port"),
+            Err(e) => e,
+        };
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[wasm_bindgen_test]
+    async fn api_client_reports_an_unreachable_api() {
+        let config = ZapServerConfig::new("127.0.0.1", CLOSED_PORT, CLOSED_PORT);
+        let client = ApiClient::with_tls_config(config, None).unwrap();
+        let result = client.get_json::<serde_json::Value>("/info").await;
+        assert!(matches!(result, Err(ZapError::ProxyConnect(_))), "{:?}", result.err());
+    }
+
+    #[wasm_bindgen_test]
+    fn browser_time_reads_the_wall_clock() {
+        use rustls::time_provider::TimeProvider;
+
+        let now = BrowserTime.current_time().unwrap();
+        // 2024-01-01T00:00:00Z
+        assert!(now.as_secs() > 1_704_067_200);
+    }
+}
@@ -0,0 +1,522 @@
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Instant,
+};
+
+use chrono::Utc;
+use hyper::{header, server::conn::Http, service::service_fn, Body, Method, Request, Response};
+use serde::Serialize;
+use tokio::{net::TcpListener, time::timeout};
+use tracing::{debug, error, field, info, info_span, warn, Instrument};
+use zap_types::{
+    NotaryInfo, SecretsPayload, ATTESTATION_VERSION, SCHEMA_VERSION, SESSION_ID_HEADER,
+    SUPPORTED_CIPHER_SUITES,
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    attestation,
+    auth::ApiCaller,
+    decrypt_data,
+    logging::Sensitive,
+    storage::{SessionRecord, SessionState},
+    ProxyState,
+};
+
+const API_PORT: u16 = 8080;
+
+const SUPPORTED_TLS_VERSIONS: &[&str] = &["TLSv1.3"];
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    /// Why the proxy is not ready, empty when it is.
+    reasons: Vec<String>,
+}
+
+pub async fn run_http_server(state: Arc<ProxyState>) {
+    let listener = match TcpListener::bind(("0.0.0.0", API_PORT)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(port = API_PORT, error = %e, "Unable to start HTTP server");
+            return;
+        }
+    };
+
+    info!(port = API_PORT, tls = state.tls_acceptor.is_some(), "HTTP server listening");
+    loop {
+        let (socket, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!(error = %e, "HTTP server failed to accept a connection");
+                continue;
+            }
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service_state = state.clone();
+            let service = service_fn(move |req| handle_request(req, service_state.clone()));
+
+            let result = match &state.tls_acceptor {
+                Some(acceptor) => {
+                    match timeout(state.config.connect_read_timeout, acceptor.accept(socket)).await {
+                        Ok(Ok(tls_stream)) => Http::new().serve_connection(tls_stream, service).await,
+                        Ok(Err(e)) => {
+                            warn!(error = %e, "TLS handshake on HTTP API failed");
+                            return;
+                        }
+                        Err(_) => {
+                            warn!("TLS handshake on HTTP API timed out");
+                            return;
+                        }
+                    }
+                }
+                None => Http::new().serve_connection(socket, service).await,
+            };
+
+            if let Err(e) = result {
+                warn!(error = %e, "HTTP connection error");
+            }
+        });
+    }
+}
+
+fn text_response(status: u16, body: impl Into<Body>) -> Response<Body> {
+    Response::builder().status(status).body(body.into()).unwrap()
+}
+
+/// The first value of query parameter `name`, undecoded.
+fn query_param<'a>(req: &'a Request<Body>, name: &str) -> Option<&'a str> {
+    req.uri()
+        .query()?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn json_response<T: Serialize>(status: u16, value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(e) => text_response(500, format!("Failed to serialize response: {}", e)),
+    }
+}
+
+/// Why a request was refused, turned into a response by the handler.
+enum ApiError {
+    Unauthorized(String),
+    Status(u16, &'static str),
+}
+
+impl ApiError {
+    fn into_response(self) -> Response<Body> {
+        match self {
+            ApiError::Unauthorized(message) => {
+                let mut response = text_response(401, message);
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    "Basic realm=\"zap\", Bearer realm=\"zap\"".parse().unwrap(),
+                );
+                response
+            }
+            ApiError::Status(status, message) => text_response(status, message),
+        }
+    }
+}
+
+/// Authenticates an API request, with either a stored credential or a session token.
+fn authenticate(req: &Request<Body>, state: &ProxyState) -> Result<ApiCaller, ApiError> {
+    let authorization = req.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    state.authenticator.authenticate_api(authorization).map_err(|e| ApiError::Unauthorized(e.to_string()))
+}
+
+/// Authenticates a request that needs a stored credential, returning the principal. Session
+/// tokens only grant `/proof` and the attestation of their own session.
+fn authenticate_principal(req: &Request<Body>, state: &ProxyState) -> Result<Option<String>, ApiError> {
+    let caller = authenticate(req, state)?;
+    if caller.session_id.is_some() {
+        return Err(ApiError::Status(403, "Session tokens are only valid for proving their session"));
+    }
+    Ok(caller.principal)
+}
+
+/// Looks up a session visible to `principal`. Sessions of other clients are reported as missing.
+fn visible_session(state: &ProxyState, id: &str, principal: Option<&str>) -> Result<SessionRecord, ApiError> {
+    match tokio::task::block_in_place(|| state.store.session(id)) {
+        Ok(Some(session)) if session.is_visible_to(principal) => Ok(session),
+        Ok(_) => Err(ApiError::Status(404, "Unknown session")),
+        Err(e) => {
+            error!(session_id = %id, error = %format!("{:#}", e), "Failed to read session");
+            Err(ApiError::Status(500, "Failed to read session"))
+        }
+    }
+}
+
+/// Looks up a session the caller may prove, which is only its own session for a token.
+fn caller_session(state: &ProxyState, id: &str, caller: &ApiCaller) -> Result<SessionRecord, ApiError> {
+    if !caller.may_access(id) {
+        return Err(ApiError::Status(404, "Unknown session"));
+    }
+    visible_session(state, id, caller.principal.as_deref())
+}
+
+async fn handle_request(req: Request<Body>, state: Arc<ProxyState>) -> Result<Response<Body>, hyper::Error> {
+    let path = req.uri().path().to_string();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (req.method(), segments.as_slice()) {
+        (&Method::GET, ["info"]) => Ok(handle_info()),
+        (&Method::GET, ["keys"]) => Ok(handle_keys(&state)),
+        (&Method::GET, ["healthz"]) => Ok(text_response(200, "ok")),
+        (&Method::GET, ["readyz"]) => Ok(handle_readyz(&state)),
+        (&Method::GET, ["metrics"]) => Ok(handle_metrics(&state)),
+        (&Method::POST, ["proof"]) => {
+            let started = Instant::now();
+            let response =
+                handle_proof(req, &state).instrument(info_span!("proof", session_id = field::Empty)).await;
+            state.metrics.proof_completed(started.elapsed());
+            response
+        }
+        (&Method::GET, ["sessions"]) => Ok(handle_sessions(&req, &state)),
+        (&Method::GET, ["sessions", id]) => Ok(handle_session(&req, &state, id)),
+        (&Method::DELETE, ["sessions", id]) => Ok(handle_delete_session(&req, &state, id)),
+        (&Method::POST, ["sessions", id, "finalize"]) => Ok(handle_finalize(&req, &state, id)),
+        (&Method::GET, ["sessions", id, "attestation"]) => Ok(handle_attestation(&req, &state, id)),
+        _ => Ok(text_response(404, "Not Found")),
+    }
+}
+
+fn handle_info() -> Response<Body> {
+    let info = NotaryInfo::new(
+        env!("CARGO_PKG_VERSION"),
+        SUPPORTED_CIPHER_SUITES.iter().map(|suite| suite.to_string()).collect(),
+        SUPPORTED_TLS_VERSIONS.iter().map(|version| version.to_string()).collect(),
+        vec![ATTESTATION_VERSION],
+        vec![SCHEMA_VERSION],
+    );
+    json_response(200, &info)
+}
+
+/// Ready once the proxy can sign, store sessions and is not shutting down.
+fn handle_readyz(state: &ProxyState) -> Response<Body> {
+    let mut reasons = Vec::new();
+    if state.keyring.get().current().is_none() {
+        reasons.push("No active signing key".to_string());
+    }
+    if let Err(e) = tokio::task::block_in_place(|| state.store.check()) {
+        reasons.push(format!("Session store unavailable: {:#}", e));
+    }
+    if state.draining.load(Ordering::Relaxed) {
+        reasons.push("Draining for shutdown".to_string());
+    }
+
+    let ready = reasons.is_empty();
+    json_response(if ready { 200 } else { 503 }, &Readiness { ready, reasons })
+}
+
+fn handle_metrics(state: &ProxyState) -> Response<Body> {
+    Response::builder()
+        .status(200)
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(state.metrics.render(state.tunnels.len())))
+        .unwrap()
+}
+
+fn handle_keys(state: &ProxyState) -> Response<Body> {
+    match state.keyring.get().public_keys() {
+        Ok(keys) => json_response(200, &keys),
+        Err(e) => text_response(500, format!("Failed to export public keys: {}", e)),
+    }
+}
+
+/// Lists the caller's sessions, oldest first, optionally only those in `?state=`.
+fn handle_sessions(req: &Request<Body>, state: &ProxyState) -> Response<Body> {
+    let principal = match authenticate_principal(req, state) {
+        Ok(principal) => principal,
+        Err(e) => return e.into_response(),
+    };
+    let filter = match query_param(req, "state").map(str::parse::<SessionState>).transpose() {
+        Ok(filter) => filter,
+        Err(e) => return text_response(400, e),
+    };
+
+    match tokio::task::block_in_place(|| state.store.sessions(principal.as_deref(), filter)) {
+        Ok(sessions) => json_response(200, &sessions),
+        Err(e) => {
+            error!(error = %format!("{:#}", e), "Failed to list sessions");
+            text_response(500, "Failed to list sessions")
+        }
+    }
+}
+
+fn handle_session(req: &Request<Body>, state: &ProxyState, id: &str) -> Response<Body> {
+    let session = authenticate_principal(req, state)
+        .and_then(|principal| visible_session(state, id, principal.as_deref()));
+    match session {
+        Ok(session) => json_response(200, &session),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Discards a session with its transcript and attestation, closing its tunnel if still open.
+fn handle_delete_session(req: &Request<Body>, state: &ProxyState, id: &str) -> Response<Body> {
+    let session = authenticate_principal(req, state)
+        .and_then(|principal| visible_session(state, id, principal.as_deref()));
+    if let Err(e) = session {
+        return e.into_response();
+    }
+
+    state.tunnels.close(id);
+    match tokio::task::block_in_place(|| state.store.delete_session(id)) {
+        Ok(true) => {
+            state.transcript_writer.forget(id);
+            info!(session_id = %id, "Deleted session");
+            text_response(204, Body::empty())
+        }
+        Ok(false) => text_response(404, "Unknown session"),
+        Err(e) => {
+            error!(session_id = %id, error = %format!("{:#}", e), "Failed to delete session");
+            text_response(500, "Failed to delete session")
+        }
+    }
+}
+
+/// Closes the session's tunnel so no more traffic is recorded, leaving the transcript ready to
+/// be proven. Finalizing a session that is no longer open changes nothing.
+fn handle_finalize(req: &Request<Body>, state: &ProxyState, id: &str) -> Response<Body> {
+    let session = authenticate_principal(req, state)
+        .and_then(|principal| visible_session(state, id, principal.as_deref()));
+    if let Err(e) = session {
+        return e.into_response();
+    }
+
+    let mut finalize = |session: &mut SessionRecord| {
+        if session.state == SessionState::Open {
+            session.state = SessionState::Closed;
+            session.closed_at = Some(Utc::now());
+        }
+    };
+    // The tunnel records its byte counts as it closes
+    state.tunnels.close(id);
+    let session = tokio::task::block_in_place(|| state.store.update_session(id, &mut finalize));
+    match session {
+        Ok(Some(session)) => json_response(200, &session),
+        Ok(None) => text_response(404, "Unknown session"),
+        Err(e) => {
+            error!(session_id = %id, error = %format!("{:#}", e), "Failed to finalize session");
+            text_response(500, "Failed to finalize session")
+        }
+    }
+}
+
+fn handle_attestation(req: &Request<Body>, state: &ProxyState, id: &str) -> Response<Body> {
+    let caller = match authenticate(req, state) {
+        Ok(caller) => caller,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = caller_session(state, id, &caller) {
+        return e.into_response();
+    }
+
+    match tokio::task::block_in_place(|| state.store.attestation(id)) {
+        Ok(Some(attestation)) => json_response(200, &attestation),
+        Ok(None) => text_response(404, "No attestation issued for this session"),
+        Err(e) => {
+            error!(session_id = %id, error = %format!("{:#}", e), "Failed to read attestation");
+            text_response(500, "Failed to read attestation")
+        }
+    }
+}
+
+async fn handle_proof(req: Request<Body>, state: &ProxyState) -> Result<Response<Body>, hyper::Error> {
+    let caller = match authenticate(&req, state) {
+        Ok(caller) => caller,
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    // Clients predating session ids prove their most recent session
+    let requested = req.headers().get(SESSION_ID_HEADER).and_then(|value| value.to_str().ok());
+    let session_id = match requested.or(caller.session_id.as_deref()) {
+        Some(id) => id.to_string(),
+        None => {
+            match tokio::task::block_in_place(|| state.store.latest_session(caller.principal.as_deref())) {
+                Ok(Some(session)) => session.id,
+                Ok(None) => return Ok(text_response(404, "Unknown session")),
+                Err(e) => {
+                    error!(error = %format!("{:#}", e), "Failed to list sessions");
+                    return Ok(text_response(500, "Failed to list sessions"));
+                }
+            }
+        }
+    };
+    let session = match caller_session(state, &session_id, &caller) {
+        Ok(session) => session,
+        Err(e) => return Ok(e.into_response()),
+    };
+    tracing::Span::current().record("session_id", field::display(&session.id));
+
+    // The body holds the session keys, wipe it along with the parsed payload
+    let body_bytes = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(bytes) => Zeroizing::new(Vec::from(bytes)),
+        Err(_) => return Ok(text_response(400, "Failed to read request body")),
+    };
+
+    let proof_data: SecretsPayload = match serde_json::from_slice(&body_bytes) {
+        Ok(data) => data,
+        Err(_) => return Ok(text_response(400, "Invalid JSON")),
+    };
+    if !(1..=SCHEMA_VERSION).contains(&proof_data.get_version()) {
+        return Ok(text_response(400, format!("Unsupported schema version {}", proof_data.get_version())));
+    }
+
+    debug!(secrets = ?proof_data, "Received session secrets");
+
+    // Keys of other suites have valid lengths too, they would only fail as AES-256-GCM tags
+    for secret in [proof_data.get_rx_secret(), proof_data.get_tx_secret()] {
+        let cipher_suite = secret.get_cipher_suite();
+        if !SUPPORTED_CIPHER_SUITES.contains(&cipher_suite) {
+            let message = format!(
+                "Unsupported cipher suite {:?}, supported: {}",
+                cipher_suite,
+                SUPPORTED_CIPHER_SUITES.join(", ")
+            );
+            state.metrics.decryption_failed(cipher_suite);
+            mark_session(state, &session.id, cipher_suite, SessionState::Failed, Some(message.clone()));
+            return Ok(text_response(422, message));
+        }
+    }
+
+    // Attempt to decrypt the stored transcript and attest to it, including traffic still queued
+    if let Err(e) = state.transcript_writer.flush_session(&session.id).await {
+        error!(error = %format!("{:#}", e), "Transcript is incomplete");
+        return Ok(text_response(500, "Failed to read transcript"));
+    }
+    let transcript = match tokio::task::block_in_place(|| state.store.transcript(&session.id)) {
+        Ok(transcript) => transcript,
+        Err(e) => {
+            error!(error = %format!("{:#}", e), "Failed to read transcript");
+            return Ok(text_response(500, "Failed to read transcript"));
+        }
+    };
+    let decrypted = decrypt_data(&transcript, &proof_data);
+    // Only the suite is needed from here on, the keys are wiped now
+    let cipher_suite = proof_data.get_rx_secret().get_cipher_suite().to_string();
+    drop(proof_data);
+    let decrypted_data = match decrypted {
+        Ok(decrypted_data) => decrypted_data,
+        Err(e) => {
+            warn!(error = %e, "Failed to decrypt transcript");
+            state.metrics.decryption_failed(&cipher_suite);
+            mark_session(
+                state,
+                &session.id,
+                &cipher_suite,
+                SessionState::Failed,
+                Some(format!("Failed to decrypt transcript: {}", e)),
+            );
+            return Ok(text_response(422, format!("Failed to decrypt transcript: {}", e)));
+        }
+    };
+    debug!(records = decrypted_data.len(), data = ?Sensitive(&decrypted_data), "Decrypted transcript");
+
+    let keyring = state.keyring.get();
+    let Some(key) = keyring.current() else {
+        return Ok(text_response(503, "No active notary key"));
+    };
+    let attestation = attestation::attest(key, &transcript, decrypted_data);
+    // Remote and PKCS#11 signers block, keep them off the other tasks on this worker
+    let signing_started = Instant::now();
+    let signed = tokio::task::block_in_place(|| attestation::sign(&attestation, key));
+    state.metrics.attestation_signed(signing_started.elapsed());
+    let signed = match signed {
+        Ok(signed) => signed,
+        Err(e) => {
+            error!(key_id = key.id(), error = %e, "Failed to sign attestation");
+            mark_session(
+                state,
+                &session.id,
+                &cipher_suite,
+                SessionState::Failed,
+                Some(format!("Failed to sign attestation: {}", e)),
+            );
+            return Ok(text_response(500, format!("Failed to sign attestation: {}", e)));
+        }
+    };
+    info!(key_id = key.id(), "Issued attestation");
+
+    // Stored before responding, so a client that timed out can fetch it later
+    if let Err(e) = tokio::task::block_in_place(|| state.store.put_attestation(&session.id, &signed)) {
+        error!(error = %format!("{:#}", e), "Failed to store attestation");
+    } else {
+        mark_session(state, &session.id, &cipher_suite, SessionState::Attested, None);
+        // The session is proven, its token has served its purpose
+        state.authenticator.revoke_session_tokens(&session.id);
+    }
+    Ok(json_response(200, &signed))
+}
+
+/// Records the outcome of a `/proof` request on the session.
+fn mark_session(
+    state: &ProxyState,
+    id: &str,
+    cipher_suite: &str,
+    new_state: SessionState,
+    error: Option<String>,
+) {
+    let mut mark = |session: &mut SessionRecord| {
+        session.state = new_state;
+        session.cipher_suite = Some(cipher_suite.to_string());
+        session.error = error.clone();
+    };
+    if let Err(e) = tokio::task::block_in_place(|| state.store.update_session(id, &mut mark)) {
+        error!(session_id = %id, error = %format!("{:#}", e), "Failed to update session");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::body::to_bytes;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::config::ProxyConfig;
+    use crate::keys::tests::{manifest, remove};
+    use crate::tests::{notary_key_pem, temp_file, test_state};
+
+    async fn readiness(state: &ProxyState) -> (u16, Value) {
+        let response = handle_readyz(state);
+        let status = response.status().as_u16();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn readyz_reports_draining() {
+        let key_file = temp_file("notary.pem", &notary_key_pem());
+        let state = test_state(ProxyConfig::default(), &key_file);
+        std::fs::remove_file(key_file).unwrap();
+        assert_eq!(readiness(&state).await, (200, json!({ "ready": true, "reasons": [] })));
+
+        state.draining.store(true, Ordering::Relaxed);
+        assert_eq!(
+            readiness(&state).await,
+            (503, json!({ "ready": false, "reasons": ["Draining for shutdown"] }))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn readyz_reports_a_missing_signing_key() {
+        let (keyring_file, files) =
+            manifest(&[(None, Some("2020-01-01T00:00:00Z")), (Some("2999-01-01T00:00:00Z"), None)]);
+        let state = test_state(ProxyConfig::default(), &keyring_file);
+        remove(files);
+        assert_eq!(
+            readiness(&state).await,
+            (503, json!({ "ready": false, "reasons": ["No active signing key"] }))
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use openssl::sha::Sha256;
+use zap_types::{Attestation, Proof};
+
+use crate::{keys::NotaryKey, Direction};
+
+/// Statement about a recorded session, to be signed with `key`.
+pub fn attest(key: &NotaryKey, transcript: &[(Direction, Vec<u8>)], decrypted: Vec<String>) -> Attestation {
+    Attestation::new(
+        key.id(),
+        key.algorithm(),
+        Utc::now().to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        hex::encode(transcript_digest(transcript)),
+        decrypted,
+    )
+}
+
+/// Serializes the attestation and signs the serialized bytes.
+pub fn sign(attestation: &Attestation, key: &NotaryKey) -> Result<Proof> {
+    let data = serde_json::to_string(attestation)?;
+    let signature = key.sign(data.as_bytes())?;
+    Ok(Proof::new(&data, &hex::encode(signature), key.id()))
+}
+
+/// Hashes each record as `direction (1 byte) || length (u32, big endian) || bytes`, where
+/// direction is `0` for client to server and `1` for server to client.
+pub fn transcript_digest(transcript: &[(Direction, Vec<u8>)]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for (direction, data) in transcript {
+        let direction = match direction {
+            Direction::ClientToServer => 0u8,
+            Direction::ServerToClient => 1u8,
+        };
+        hasher.update(&[direction]);
+        hasher.update(&(data.len() as u32).to_be_bytes());
+        hasher.update(data);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,511 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use openssl::{
+    base64,
+    error::ErrorStack,
+    hash::{hash, MessageDigest},
+    memcmp,
+    pkcs5::pbkdf2_hmac,
+    rand::rand_bytes,
+};
+
+const SESSION_TOKEN_BYTES: usize = 32;
+
+/// Credentials presented by a client in a `Proxy-Authorization` or `Authorization` header.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credential {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credential::Basic { username, .. } => write!(f, "Basic({:?}, <redacted>)", username),
+            Credential::Bearer(_) => write!(f, "Bearer(<redacted>)"),
+        }
+    }
+}
+
+impl Credential {
+    /// Parses the value of an `Authorization`-style header.
+    pub fn from_header(value: &str) -> Result<Self, AuthError> {
+        let (scheme, params) = value.trim().split_once(' ').ok_or(AuthError::Malformed)?;
+        let params = params.trim();
+
+        if scheme.eq_ignore_ascii_case("Basic") {
+            let decoded = base64::decode_block(params).map_err(|_| AuthError::Malformed)?;
+            let decoded = String::from_utf8(decoded).map_err(|_| AuthError::Malformed)?;
+            let (username, password) = decoded.split_once(':').ok_or(AuthError::Malformed)?;
+            Ok(Credential::Basic { username: username.to_string(), password: password.to_string() })
+        } else if scheme.eq_ignore_ascii_case("Bearer") && !params.is_empty() {
+            Ok(Credential::Bearer(params.to_string()))
+        } else {
+            Err(AuthError::UnsupportedScheme)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    Missing,
+    Malformed,
+    UnsupportedScheme,
+    Rejected,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => write!(f, "No credentials provided"),
+            AuthError::Malformed => write!(f, "Malformed credentials"),
+            AuthError::UnsupportedScheme => write!(f, "Unsupported authentication scheme"),
+            AuthError::Rejected => write!(f, "Invalid credentials"),
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+/// A source of valid client credentials.
+pub trait CredentialStore: Send + Sync {
+    /// Returns the name of the principal the credential belongs to, if it is valid.
+    fn verify(&self, credential: &Credential) -> Option<String>;
+}
+
+/// Credentials loaded from a text file with one entry per line:
+///
+/// ```text
+/// # basic <name> pbkdf2-sha256$<iterations>$<salt hex>$<derived key hex>
+/// # bearer <name> sha256$<digest hex>
+/// basic alice pbkdf2-sha256$100000$9f2c...$5b1d...
+/// bearer ci-runner sha256$c07e...
+/// ```
+///
+/// Entries are generated with `proxy hash-credential [basic|bearer]`, which reads the password
+/// or API key from stdin. API keys are random and long, so a plain digest is enough to store
+/// them and lets a key be looked up without trying every entry.
+pub struct FileCredentialStore {
+    users: HashMap<String, PasswordHash>,
+    api_keys: HashMap<ApiKeyDigest, String>,
+}
+
+impl FileCredentialStore {
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read credentials file {}: {}", path.display(), e))?;
+
+        let mut users = HashMap::new();
+        let mut api_keys = HashMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [kind, name, hash] = fields[..] else {
+                anyhow::bail!("{}:{}: expected `<kind> <name> <hash>`", path.display(), number + 1);
+            };
+            let invalid = |e: String| anyhow::anyhow!("{}:{}: {}", path.display(), number + 1, e);
+
+            match kind {
+                "basic" => {
+                    users.insert(name.to_string(), hash.parse::<PasswordHash>().map_err(invalid)?);
+                }
+                "bearer" => {
+                    api_keys.insert(hash.parse::<ApiKeyDigest>().map_err(invalid)?, name.to_string());
+                }
+                _ => anyhow::bail!("{}:{}: unknown credential kind {:?}", path.display(), number + 1, kind),
+            }
+        }
+
+        Ok(Self { users, api_keys })
+    }
+}
+
+impl CredentialStore for FileCredentialStore {
+    fn verify(&self, credential: &Credential) -> Option<String> {
+        match credential {
+            Credential::Basic { username, password } => match self.users.get(username) {
+                Some(expected) => expected.matches(password).then(|| username.clone()),
+                None => {
+                    // Spend the same time as for a known user, so timing doesn't reveal which exist
+                    dummy_hash().matches(password);
+                    None
+                }
+            },
+            Credential::Bearer(key) => self.api_keys.get(&ApiKeyDigest::of(key)).cloned(),
+        }
+    }
+}
+
+const HASH_SCHEME: &str = "pbkdf2-sha256";
+const HASH_ITERATIONS: usize = 100_000;
+const HASH_SALT_BYTES: usize = 16;
+const HASH_KEY_BYTES: usize = 32;
+
+/// A salted PBKDF2-HMAC-SHA256 hash of a password or API key.
+pub struct PasswordHash {
+    iterations: usize,
+    salt: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl PasswordHash {
+    /// Hashes `secret` with a fresh random salt.
+    pub fn new(secret: &str) -> Result<Self, ErrorStack> {
+        let mut salt = vec![0; HASH_SALT_BYTES];
+        rand_bytes(&mut salt)?;
+        let key = derive_key(secret, &salt, HASH_ITERATIONS)?;
+        Ok(Self { iterations: HASH_ITERATIONS, salt, key })
+    }
+
+    pub fn matches(&self, secret: &str) -> bool {
+        match derive_key(secret, &self.salt, self.iterations) {
+            Ok(key) => memcmp::eq(&self.key, &key),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Hash of a random password, checked for unknown usernames.
+fn dummy_hash() -> &'static PasswordHash {
+    static DUMMY: OnceLock<PasswordHash> = OnceLock::new();
+    DUMMY.get_or_init(|| PasswordHash {
+        iterations: HASH_ITERATIONS,
+        salt: vec![0; HASH_SALT_BYTES],
+        key: vec![0; HASH_KEY_BYTES],
+    })
+}
+
+fn derive_key(secret: &str, salt: &[u8], iterations: usize) -> Result<Vec<u8>, ErrorStack> {
+    let mut key = vec![0; HASH_KEY_BYTES];
+    pbkdf2_hmac(secret.as_bytes(), salt, iterations, MessageDigest::sha256(), &mut key)?;
+    Ok(key)
+}
+
+impl fmt::Display for PasswordHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}${}${}${}",
+            HASH_SCHEME,
+            self.iterations,
+            hex::encode(&self.salt),
+            hex::encode(&self.key)
+        )
+    }
+}
+
+impl FromStr for PasswordHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split('$').collect();
+        let [scheme, iterations, salt, key] = fields[..] else {
+            return Err(format!("expected `{}$<iterations>$<salt>$<key>`", HASH_SCHEME));
+        };
+        if scheme != HASH_SCHEME {
+            return Err(format!("unsupported hash scheme {:?}", scheme));
+        }
+        let iterations = iterations
+            .parse::<usize>()
+            .ok()
+            .filter(|iterations| *iterations > 0)
+            .ok_or_else(|| format!("invalid iteration count {:?}", iterations))?;
+        let salt = hex::decode(salt).ok().filter(|salt| !salt.is_empty()).ok_or("invalid salt")?;
+        let key =
+            hex::decode(key).ok().filter(|key| key.len() == HASH_KEY_BYTES).ok_or("invalid derived key")?;
+        Ok(Self { iterations, salt, key })
+    }
+}
+
+const DIGEST_SCHEME: &str = "sha256";
+
+/// SHA-256 digest of an API key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ApiKeyDigest([u8; 32]);
+
+impl ApiKeyDigest {
+    pub fn of(key: &str) -> Self {
+        let digest = hash(MessageDigest::sha256(), key.as_bytes()).expect("SHA-256 is always available");
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(&digest);
+        Self(bytes)
+    }
+}
+
+impl fmt::Display for ApiKeyDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}${}", DIGEST_SCHEME, hex::encode(self.0))
+    }
+}
+
+impl FromStr for ApiKeyDigest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((DIGEST_SCHEME, digest)) = s.split_once('$') else {
+            return Err(format!(
+                "expected `{}$<digest>`, hash API keys with `proxy hash-credential bearer`",
+                DIGEST_SCHEME
+            ));
+        };
+        let mut bytes = [0; 32];
+        hex::decode_to_slice(digest, &mut bytes).map_err(|_| "invalid digest".to_string())?;
+        Ok(Self(bytes))
+    }
+}
+
+/// A token handed out on CONNECT, proving one session.
+struct SessionToken {
+    principal: String,
+    session_id: String,
+    expires_at: Instant,
+}
+
+/// Who an API request was authenticated as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiCaller {
+    /// Owner of the sessions the caller may access, `None` when authentication is disabled.
+    pub principal: Option<String>,
+    /// Set when the caller presented a session token, which only grants access to this session.
+    pub session_id: Option<String>,
+}
+
+impl ApiCaller {
+    pub fn may_access(&self, session_id: &str) -> bool {
+        self.session_id.as_deref().is_none_or(|id| id == session_id)
+    }
+}
+
+/// Checks client credentials against the configured store and tracks session-bound tokens.
+///
+/// Without a store every client is accepted, which keeps the proxy usable for local development.
+pub struct Authenticator {
+    store: Option<Arc<dyn CredentialStore>>,
+    session_tokens: Mutex<HashMap<String, SessionToken>>,
+    token_ttl: Duration,
+}
+
+impl Authenticator {
+    pub fn new(store: Option<Arc<dyn CredentialStore>>, token_ttl: Duration) -> Self {
+        Self { store, session_tokens: Mutex::new(HashMap::new()), token_ttl }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.store.is_some()
+    }
+
+    /// Authenticates a CONNECT request from its `Proxy-Authorization` header.
+    pub fn authenticate(&self, header: Option<&str>) -> Result<Option<String>, AuthError> {
+        let Some(store) = &self.store else {
+            return Ok(None);
+        };
+
+        let credential = Credential::from_header(header.ok_or(AuthError::Missing)?)?;
+        // Key derivation is deliberately slow, keep it off the other tasks on this worker
+        let principal = tokio::task::block_in_place(|| store.verify(&credential));
+        principal.map(Some).ok_or(AuthError::Rejected)
+    }
+
+    /// Authenticates an API request from its `Authorization` header, accepting either a
+    /// stored credential or a token issued to an authenticated CONNECT session.
+    pub fn authenticate_api(&self, header: Option<&str>) -> Result<ApiCaller, AuthError> {
+        if !self.is_enabled() {
+            return Ok(ApiCaller { principal: None, session_id: None });
+        }
+
+        if let Some(Ok(Credential::Bearer(token))) = header.map(Credential::from_header) {
+            let tokens = self.session_tokens.lock().unwrap();
+            if let Some(session_token) = tokens.get(&token) {
+                if Instant::now() < session_token.expires_at {
+                    return Ok(ApiCaller {
+                        principal: Some(session_token.principal.clone()),
+                        session_id: Some(session_token.session_id.clone()),
+                    });
+                }
+            }
+        }
+
+        let principal = self.authenticate(header)?;
+        Ok(ApiCaller { principal, session_id: None })
+    }
+
+    /// Issues a random token for proving the session `session_id` of `principal`, valid for
+    /// the configured TTL or until the session is attested.
+    pub fn issue_session_token(&self, principal: &str, session_id: &str) -> Result<String, ErrorStack> {
+        let mut bytes = [0; SESSION_TOKEN_BYTES];
+        rand_bytes(&mut bytes)?;
+        let token = hex::encode(bytes);
+
+        let now = Instant::now();
+        let mut tokens = self.session_tokens.lock().unwrap();
+        tokens.retain(|_, session_token| session_token.expires_at > now);
+        tokens.insert(
+            token.clone(),
+            SessionToken {
+                principal: principal.to_string(),
+                session_id: session_id.to_string(),
+                expires_at: now + self.token_ttl,
+            },
+        );
+
+        Ok(token)
+    }
+
+    /// Invalidates the tokens issued for `session_id`.
+    pub fn revoke_session_tokens(&self, session_id: &str) {
+        self.session_tokens.lock().unwrap().retain(|_, session_token| session_token.session_id != session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Store accepting a single Basic credential, without the cost of key derivation.
+    struct StaticStore;
+
+    impl CredentialStore for StaticStore {
+        fn verify(&self, credential: &Credential) -> Option<String> {
+            match credential {
+                Credential::Basic { username, password } if username == "alice" && password == "secret" => {
+                    Some(username.clone())
+                }
+                _ => None,
+            }
+        }
+    }
+
+    fn with_store(ttl: Duration) -> Authenticator {
+        Authenticator::new(Some(Arc::new(StaticStore)), ttl)
+    }
+
+    fn basic(username: &str, password: &str) -> String {
+        format!("Basic {}", base64::encode_block(format!("{}:{}", username, password).as_bytes()))
+    }
+
+    #[test]
+    fn parses_credentials() {
+        let credential = Credential::from_header(&basic("alice", "pa:ss")).unwrap();
+        assert_eq!(
+            credential,
+            Credential::Basic { username: "alice".to_string(), password: "pa:ss".to_string() }
+        );
+        assert_eq!(Credential::from_header("bearer abc").unwrap(), Credential::Bearer("abc".to_string()));
+        assert_eq!(Credential::from_header("Digest abc"), Err(AuthError::UnsupportedScheme));
+        assert_eq!(Credential::from_header("Basic !!!"), Err(AuthError::Malformed));
+    }
+
+    #[test]
+    fn password_hash_round_trips() {
+        let hash = PasswordHash::new("correct horse").unwrap();
+        let parsed: PasswordHash = hash.to_string().parse().unwrap();
+        assert!(parsed.matches("correct horse"));
+        assert!(!parsed.matches("correct horse "));
+
+        // The salt is random, the same secret hashes differently each time
+        assert_ne!(hash.to_string(), PasswordHash::new("correct horse").unwrap().to_string());
+    }
+
+    #[test]
+    fn rejects_malformed_hashes() {
+        for hash in [
+            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b",
+            "bcrypt$10$00$00",
+            "pbkdf2-sha256$0$00$2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b",
+            "pbkdf2-sha256$1000$$2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b",
+            "pbkdf2-sha256$1000$00$2bb8",
+        ] {
+            assert!(hash.parse::<PasswordHash>().is_err(), "{}", hash);
+        }
+    }
+
+    #[test]
+    fn file_store_verifies_hashed_credentials() {
+        let path = std::env::temp_dir().join(format!("zap-credentials-{}", std::process::id()));
+        let contents = format!(
+            "# test credentials\nbasic alice {}\nbearer ci {}\n",
+            PasswordHash::new("secret").unwrap(),
+            ApiKeyDigest::of("api-key")
+        );
+        fs::write(&path, contents).unwrap();
+        let store = FileCredentialStore::load(&path);
+        fs::remove_file(&path).unwrap();
+        let store = store.unwrap();
+
+        let alice = |password: &str| Credential::Basic {
+            username: "alice".to_string(),
+            password: password.to_string(),
+        };
+        assert_eq!(store.verify(&alice("secret")), Some("alice".to_string()));
+        assert_eq!(store.verify(&alice("wrong")), None);
+        assert_eq!(store.verify(&Credential::Bearer("api-key".to_string())), Some("ci".to_string()));
+        assert_eq!(store.verify(&Credential::Bearer("secret".to_string())), None);
+        let bob = Credential::Basic { username: "bob".to_string(), password: "secret".to_string() };
+        assert_eq!(store.verify(&bob), None);
+    }
+
+    #[test]
+    fn api_key_digest_round_trips() {
+        let digest = ApiKeyDigest::of("api-key");
+        assert_eq!(digest.to_string().parse::<ApiKeyDigest>(), Ok(digest.clone()));
+        assert_ne!(ApiKeyDigest::of("api-key "), digest);
+        for value in ["sha256$00", "sha512$00", &PasswordHash::new("api-key").unwrap().to_string()] {
+            assert!(value.parse::<ApiKeyDigest>().is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn session_token_is_bound_to_its_session() {
+        let authenticator = with_store(Duration::from_secs(60));
+        let token = authenticator.issue_session_token("alice", "0123abcd").unwrap();
+
+        let caller = authenticator.authenticate_api(Some(&format!("Bearer {}", token))).unwrap();
+        assert_eq!(caller.principal.as_deref(), Some("alice"));
+        assert!(caller.may_access("0123abcd"));
+        assert!(!caller.may_access("4567ef00"));
+
+        let caller = authenticator.authenticate_api(Some(&basic("alice", "secret"))).unwrap();
+        assert_eq!(caller, ApiCaller { principal: Some("alice".to_string()), session_id: None });
+        assert!(caller.may_access("4567ef00"));
+    }
+
+    #[test]
+    fn revoked_and_expired_tokens_are_rejected() {
+        let authenticator = with_store(Duration::from_secs(60));
+        let token = authenticator.issue_session_token("alice", "0123abcd").unwrap();
+        let other = authenticator.issue_session_token("alice", "4567ef00").unwrap();
+        authenticator.revoke_session_tokens("0123abcd");
+        assert_eq!(
+            authenticator.authenticate_api(Some(&format!("Bearer {}", token))),
+            Err(AuthError::Rejected)
+        );
+        assert!(authenticator.authenticate_api(Some(&format!("Bearer {}", other))).is_ok());
+
+        let expiring = with_store(Duration::ZERO);
+        let token = expiring.issue_session_token("alice", "0123abcd").unwrap();
+        assert_eq!(
+            expiring.authenticate_api(Some(&format!("Bearer {}", token))),
+            Err(AuthError::Rejected)
+        );
+    }
+
+    #[test]
+    fn disabled_authenticator_accepts_everyone() {
+        let authenticator = Authenticator::new(None, Duration::from_secs(60));
+        assert_eq!(authenticator.authenticate(None), Ok(None));
+        assert_eq!(authenticator.authenticate_api(None), Ok(ApiCaller { principal: None, session_id: None }));
+    }
+}
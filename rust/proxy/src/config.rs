@@ -0,0 +1,183 @@
+use std::{env, path::PathBuf, str::FromStr, time::Duration};
+
+use crate::transcript::PayloadEncoding;
+
+const DEFAULT_MAX_CONNECT_REQUEST_BYTES: usize = 8 * 1024;
+const DEFAULT_CONNECT_READ_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_TARGET_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_MAX_SESSION_LIFETIME_SECS: u64 = 600;
+const DEFAULT_MAX_TRANSCRIPT_BYTES: usize = 16 * 1024 * 1024;
+const DEFAULT_MAX_CONCURRENT_SESSIONS: usize = 256;
+const DEFAULT_SESSION_TOKEN_TTL_SECS: u64 = 900;
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 10;
+const DEFAULT_KEYRING_FILE: &str = "utils/private-key.pem";
+const DEFAULT_KEYRING_POLL_INTERVAL_SECS: u64 = 5;
+const DEFAULT_TRANSCRIPT_LOG_FILE: &str = "utils/proxy.log";
+const DEFAULT_TRANSCRIPT_LOG_MAX_BYTES: u64 = 64 * 1024 * 1024;
+const DEFAULT_TRANSCRIPT_LOG_MAX_AGE_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_TRANSCRIPT_LOG_KEEP: usize = 7;
+const DEFAULT_STORAGE_FILE: &str = "utils/sessions.sqlite3";
+const DEFAULT_SESSION_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Runtime limits for the proxy, read from `ZAP_*` environment variables.
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    /// Maximum size of the CONNECT request header block.
+    pub max_connect_request_bytes: usize,
+    /// Time allowed for the client to send its CONNECT request.
+    pub connect_read_timeout: Duration,
+    /// Time allowed to open the TCP connection to the target.
+    pub target_connect_timeout: Duration,
+    /// A tunnel with no traffic in either direction for this long is closed.
+    pub idle_timeout: Duration,
+    /// Hard upper bound on the lifetime of a tunnel.
+    pub max_session_lifetime: Duration,
+    /// Maximum number of bytes recorded for a single session, both directions combined.
+    pub max_transcript_bytes: usize,
+    /// Maximum number of tunnels served at the same time.
+    pub max_concurrent_sessions: usize,
+    /// Time open tunnels are given to finish on shutdown before the proxy exits anyway.
+    pub drain_timeout: Duration,
+    /// Time the HTTP API keeps serving after the drain, so sessions that just closed can still
+    /// be proven.
+    pub shutdown_grace: Duration,
+    /// Credentials file for proxy authentication, authentication is disabled when unset.
+    pub credentials_file: Option<PathBuf>,
+    /// How long a session token issued on CONNECT stays valid for `/proof`.
+    pub session_token_ttl: Duration,
+    /// Certificate chain for TLS on both listeners, TLS is disabled when unset.
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    /// CA used to verify client certificates, enables mTLS when set.
+    pub tls_client_ca_file: Option<PathBuf>,
+    /// Notary signing keys, either a single PEM key or a JSON keyring manifest.
+    pub keyring_file: PathBuf,
+    /// How often the keyring files are checked for changes, in addition to SIGHUP.
+    pub keyring_poll_interval: Duration,
+    /// Levels written to stderr, as a level (off, error, warn, info, debug or trace) or
+    /// per-module directives such as `info,proxy::api=debug`.
+    pub log_filter: String,
+    /// Writes decrypted traffic to the log instead of redacting it. Key material is never logged.
+    pub log_secrets: bool,
+    /// JSON-lines log of tunnel traffic, disabled when set to an empty path.
+    pub transcript_log_file: Option<PathBuf>,
+    /// The transcript log is rotated once it grows past this size or age.
+    pub transcript_log_max_bytes: u64,
+    pub transcript_log_max_age: Duration,
+    /// Number of rotated transcript logs kept next to the current one.
+    pub transcript_log_keep: usize,
+    /// Encoding of tunnel payloads in the transcript log: hex, base64 or none.
+    pub transcript_log_payload: PayloadEncoding,
+    /// SQLite database persisting sessions and attestations, kept in memory when set to an
+    /// empty path.
+    pub storage_file: Option<PathBuf>,
+    /// Closed sessions are deleted with their transcript once this old, never when zero.
+    pub session_retention: Option<Duration>,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            max_connect_request_bytes: DEFAULT_MAX_CONNECT_REQUEST_BYTES,
+            connect_read_timeout: Duration::from_secs(DEFAULT_CONNECT_READ_TIMEOUT_SECS),
+            target_connect_timeout: Duration::from_secs(DEFAULT_TARGET_CONNECT_TIMEOUT_SECS),
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS),
+            max_session_lifetime: Duration::from_secs(DEFAULT_MAX_SESSION_LIFETIME_SECS),
+            max_transcript_bytes: DEFAULT_MAX_TRANSCRIPT_BYTES,
+            max_concurrent_sessions: DEFAULT_MAX_CONCURRENT_SESSIONS,
+            drain_timeout: Duration::from_secs(DEFAULT_DRAIN_TIMEOUT_SECS),
+            shutdown_grace: Duration::from_secs(DEFAULT_SHUTDOWN_GRACE_SECS),
+            credentials_file: None,
+            session_token_ttl: Duration::from_secs(DEFAULT_SESSION_TOKEN_TTL_SECS),
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_client_ca_file: None,
+            keyring_file: PathBuf::from(DEFAULT_KEYRING_FILE),
+            keyring_poll_interval: Duration::from_secs(DEFAULT_KEYRING_POLL_INTERVAL_SECS),
+            log_filter: "info".to_string(),
+            log_secrets: false,
+            transcript_log_file: Some(PathBuf::from(DEFAULT_TRANSCRIPT_LOG_FILE)),
+            transcript_log_max_bytes: DEFAULT_TRANSCRIPT_LOG_MAX_BYTES,
+            transcript_log_max_age: Duration::from_secs(DEFAULT_TRANSCRIPT_LOG_MAX_AGE_SECS),
+            transcript_log_keep: DEFAULT_TRANSCRIPT_LOG_KEEP,
+            transcript_log_payload: PayloadEncoding::Hex,
+            storage_file: Some(PathBuf::from(DEFAULT_STORAGE_FILE)),
+            session_retention: Some(Duration::from_secs(DEFAULT_SESSION_RETENTION_SECS)),
+        }
+    }
+}
+
+impl ProxyConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_connect_request_bytes: env_or(
+                "ZAP_MAX_CONNECT_REQUEST_BYTES",
+                defaults.max_connect_request_bytes,
+            ),
+            connect_read_timeout: env_secs("ZAP_CONNECT_READ_TIMEOUT_SECS", defaults.connect_read_timeout),
+            target_connect_timeout: env_secs(
+                "ZAP_TARGET_CONNECT_TIMEOUT_SECS",
+                defaults.target_connect_timeout,
+            ),
+            idle_timeout: env_secs("ZAP_IDLE_TIMEOUT_SECS", defaults.idle_timeout),
+            max_session_lifetime: env_secs("ZAP_MAX_SESSION_LIFETIME_SECS", defaults.max_session_lifetime),
+            max_transcript_bytes: env_or("ZAP_MAX_TRANSCRIPT_BYTES", defaults.max_transcript_bytes),
+            max_concurrent_sessions: env_or("ZAP_MAX_CONCURRENT_SESSIONS", defaults.max_concurrent_sessions),
+            drain_timeout: env_secs("ZAP_DRAIN_TIMEOUT_SECS", defaults.drain_timeout),
+            shutdown_grace: env_secs("ZAP_SHUTDOWN_GRACE_SECS", defaults.shutdown_grace),
+            credentials_file: env::var_os("ZAP_CREDENTIALS_FILE").map(PathBuf::from),
+            session_token_ttl: env_secs("ZAP_SESSION_TOKEN_TTL_SECS", defaults.session_token_ttl),
+            tls_cert_file: env::var_os("ZAP_TLS_CERT_FILE").map(PathBuf::from),
+            tls_key_file: env::var_os("ZAP_TLS_KEY_FILE").map(PathBuf::from),
+            tls_client_ca_file: env::var_os("ZAP_TLS_CLIENT_CA_FILE").map(PathBuf::from),
+            keyring_file: env::var_os("ZAP_KEYRING_FILE").map(PathBuf::from).unwrap_or(defaults.keyring_file),
+            keyring_poll_interval: env_secs("ZAP_KEYRING_POLL_INTERVAL_SECS", defaults.keyring_poll_interval),
+            log_filter: env::var("ZAP_LOG_LEVEL").unwrap_or(defaults.log_filter),
+            log_secrets: env_or("ZAP_LOG_SECRETS", defaults.log_secrets),
+            transcript_log_file: env_path("ZAP_TRANSCRIPT_LOG_FILE", defaults.transcript_log_file),
+            transcript_log_max_bytes: env_or(
+                "ZAP_TRANSCRIPT_LOG_MAX_BYTES",
+                defaults.transcript_log_max_bytes,
+            ),
+            transcript_log_max_age: env_secs(
+                "ZAP_TRANSCRIPT_LOG_MAX_AGE_SECS",
+                defaults.transcript_log_max_age,
+            ),
+            transcript_log_keep: env_or("ZAP_TRANSCRIPT_LOG_KEEP", defaults.transcript_log_keep),
+            transcript_log_payload: env_or("ZAP_TRANSCRIPT_LOG_PAYLOAD", defaults.transcript_log_payload),
+            storage_file: env_path("ZAP_STORAGE_FILE", defaults.storage_file),
+            session_retention: Some(env_secs(
+                "ZAP_SESSION_RETENTION_SECS",
+                defaults.session_retention.unwrap_or_default(),
+            ))
+            .filter(|retention| !retention.is_zero()),
+        }
+    }
+}
+
+// Read before the subscriber is installed, so problems go straight to stderr
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            eprintln!("Ignoring invalid value for {}: {:?}", name, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+fn env_secs(name: &str, default: Duration) -> Duration {
+    Duration::from_secs(env_or(name, default.as_secs()))
+}
+
+/// Reads an optional path, where an empty value disables the feature.
+fn env_path(name: &str, default: Option<PathBuf>) -> Option<PathBuf> {
+    match env::var_os(name) {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => default,
+    }
+}
@@ -0,0 +1,371 @@
+use std::{error::Error, fmt, net::Ipv6Addr};
+
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+
+const MAX_HEADERS: usize = 64;
+const READ_CHUNK_SIZE: usize = 1024;
+
+/// A parsed HTTP/1.x CONNECT request.
+#[derive(Debug, Clone)]
+pub struct ConnectRequest {
+    /// Target host, without brackets for IPv6 literals.
+    pub host: String,
+    pub port: u16,
+    /// Minor HTTP version, `0` for HTTP/1.0 and `1` for HTTP/1.1.
+    pub version: u8,
+    pub headers: Vec<(String, String)>,
+    /// Bytes the client sent after the header block, to be forwarded to the target.
+    pub pipelined: Vec<u8>,
+}
+
+impl ConnectRequest {
+    /// Returns the first header with the given name, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Returns the target in `host:port` form, bracketing IPv6 literals.
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// A WebSocket upgrade request. The CONNECT request is then sent through the WebSocket.
+#[derive(Debug, Clone)]
+pub struct UpgradeRequest {
+    /// The `Sec-WebSocket-Key` to answer in the handshake.
+    pub key: String,
+    /// Bytes the client sent after the header block, the start of the first frames.
+    pub pipelined: Vec<u8>,
+}
+
+/// The first request on the proxy listener.
+#[derive(Debug, Clone)]
+pub enum ProxyRequest {
+    Connect(ConnectRequest),
+    Upgrade(UpgradeRequest),
+}
+
+#[derive(Debug)]
+pub enum ConnectError {
+    Io(io::Error),
+    /// The client closed the connection before sending anything.
+    Closed,
+    /// The header block exceeded the configured size or header count.
+    TooLarge,
+    Malformed(String),
+    MethodNotAllowed(String),
+    InvalidAuthority(String),
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::Io(e) => write!(f, "I/O error while reading CONNECT request: {}", e),
+            ConnectError::Closed => write!(f, "Client closed the connection"),
+            ConnectError::TooLarge => write!(f, "CONNECT request header block too large"),
+            ConnectError::Malformed(reason) => write!(f, "Malformed CONNECT request: {}", reason),
+            ConnectError::MethodNotAllowed(method) => write!(f, "Unsupported method {:?}", method),
+            ConnectError::InvalidAuthority(authority) => write!(f, "Invalid CONNECT target {:?}", authority),
+        }
+    }
+}
+
+impl Error for ConnectError {}
+
+impl From<io::Error> for ConnectError {
+    fn from(e: io::Error) -> Self {
+        ConnectError::Io(e)
+    }
+}
+
+/// Reads from `reader` until a complete header block has been received. Unless
+/// `allow_upgrade` is set, only CONNECT requests are accepted.
+pub async fn read_request<R>(
+    reader: &mut R,
+    max_len: usize,
+    allow_upgrade: bool,
+) -> Result<ProxyRequest, ConnectError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buffer = Vec::with_capacity(READ_CHUNK_SIZE);
+    let mut chunk = [0; READ_CHUNK_SIZE];
+    loop {
+        // Never read past `max_len`, the header block must fit in it
+        let remaining = max_len.saturating_sub(buffer.len());
+        if remaining == 0 {
+            return Err(ConnectError::TooLarge);
+        }
+        let n = reader.read(&mut chunk[..remaining.min(READ_CHUNK_SIZE)]).await?;
+        if n == 0 {
+            return Err(if buffer.is_empty() {
+                ConnectError::Closed
+            } else {
+                ConnectError::Malformed("connection closed before end of headers".to_string())
+            });
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+
+        if let Some(request) = parse_request(&buffer, allow_upgrade)? {
+            return Ok(request);
+        }
+    }
+}
+
+/// Parses a CONNECT or WebSocket upgrade request, returning `None` if the header block is not
+/// complete yet.
+pub fn parse_request(buffer: &[u8], allow_upgrade: bool) -> Result<Option<ProxyRequest>, ConnectError> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut request = httparse::Request::new(&mut headers);
+    let header_len = match request.parse(buffer) {
+        Ok(httparse::Status::Complete(len)) => len,
+        Ok(httparse::Status::Partial) => return Ok(None),
+        Err(httparse::Error::TooManyHeaders) => return Err(ConnectError::TooLarge),
+        Err(e) => return Err(ConnectError::Malformed(e.to_string())),
+    };
+
+    let headers: Vec<(String, String)> = request
+        .headers
+        .iter()
+        .map(|header| (header.name.to_string(), String::from_utf8_lossy(header.value).into_owned()))
+        .collect();
+    let pipelined = buffer[header_len..].to_vec();
+
+    let method = request.method.unwrap_or_default();
+    if method == "GET" && allow_upgrade && is_websocket_upgrade(&headers) {
+        return parse_upgrade(&headers, pipelined).map(|upgrade| Some(ProxyRequest::Upgrade(upgrade)));
+    }
+    if method != "CONNECT" {
+        return Err(ConnectError::MethodNotAllowed(method.to_string()));
+    }
+
+    let (host, port) = parse_authority(request.path.unwrap_or_default())?;
+    Ok(Some(ProxyRequest::Connect(ConnectRequest {
+        host,
+        port,
+        version: request.version.unwrap_or(1),
+        headers,
+        pipelined,
+    })))
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+}
+
+/// Whether a comma separated header such as `Connection` lists `token`.
+fn has_token(headers: &[(String, String)], name: &str, token: &str) -> bool {
+    find_header(headers, name)
+        .is_some_and(|value| value.split(',').any(|item| item.trim().eq_ignore_ascii_case(token)))
+}
+
+fn is_websocket_upgrade(headers: &[(String, String)]) -> bool {
+    has_token(headers, "Upgrade", "websocket") && has_token(headers, "Connection", "upgrade")
+}
+
+fn parse_upgrade(headers: &[(String, String)], pipelined: Vec<u8>) -> Result<UpgradeRequest, ConnectError> {
+    if find_header(headers, "Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        return Err(ConnectError::Malformed("unsupported WebSocket version".to_string()));
+    }
+    let key = match find_header(headers, "Sec-WebSocket-Key").map(str::trim) {
+        Some(key) if !key.is_empty() => key.to_string(),
+        _ => return Err(ConnectError::Malformed("missing Sec-WebSocket-Key".to_string())),
+    };
+    Ok(UpgradeRequest { key, pipelined })
+}
+
+/// Splits a CONNECT target of the form `host:port` or `[ipv6]:port`.
+fn parse_authority(authority: &str) -> Result<(String, u16), ConnectError> {
+    let invalid = || ConnectError::InvalidAuthority(authority.to_string());
+
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        let (host, port) = rest.split_once("]:").ok_or_else(invalid)?;
+        host.parse::<Ipv6Addr>().map_err(|_| invalid())?;
+        (host, port)
+    } else {
+        let (host, port) = authority.rsplit_once(':').ok_or_else(invalid)?;
+        let valid_host = !host.is_empty()
+            && host.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_'));
+        if !valid_host {
+            return Err(invalid());
+        }
+        (host, port)
+    };
+
+    if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let port = port.parse::<u16>().ok().filter(|port| *port != 0).ok_or_else(invalid)?;
+
+    Ok((host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use tokio::io::ReadBuf;
+
+    use super::*;
+
+    /// Reader handing out one chunk per read, as if each arrived in its own segment.
+    struct ChunkedReader(VecDeque<Vec<u8>>);
+
+    impl ChunkedReader {
+        fn new(chunks: &[&[u8]]) -> Self {
+            ChunkedReader(chunks.iter().map(|chunk| chunk.to_vec()).collect())
+        }
+    }
+
+    impl AsyncRead for ChunkedReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if let Some(mut chunk) = self.0.pop_front() {
+                let n = chunk.len().min(buf.remaining());
+                buf.put_slice(&chunk[..n]);
+                if n < chunk.len() {
+                    self.0.push_front(chunk.split_off(n));
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn connect(buffer: &[u8]) -> Result<ConnectRequest, ConnectError> {
+        match parse_request(buffer, false)? {
+            Some(ProxyRequest::Connect(request)) => Ok(request),
+            other => panic!("expected a CONNECT request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_host_and_port() {
+        let request = connect(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n").unwrap();
+        assert_eq!(request.host, "example.com");
+        assert_eq!(request.port, 443);
+        assert_eq!(request.version, 1);
+        assert_eq!(request.header("host"), Some("example.com:443"));
+        assert!(request.pipelined.is_empty());
+    }
+
+    #[test]
+    fn parses_ipv6_target() {
+        let request = connect(b"CONNECT [::1]:8443 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.host, "::1");
+        assert_eq!(request.port, 8443);
+        assert_eq!(request.authority(), "[::1]:8443");
+    }
+
+    #[test]
+    fn rejects_unbracketed_or_invalid_ipv6() {
+        for target in ["::1:443", "[::1]", "[::1]443", "[not-an-ip]:443"] {
+            let request = format!("CONNECT {} HTTP/1.1\r\n\r\n", target);
+            assert!(
+                matches!(connect(request.as_bytes()), Err(ConnectError::InvalidAuthority(_))),
+                "{}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_missing_or_zero_port() {
+        for target in ["example.com", "example.com:", "example.com:0", "example.com:65536", "example.com:+1"]
+        {
+            let request = format!("CONNECT {} HTTP/1.1\r\n\r\n", target);
+            assert!(
+                matches!(connect(request.as_bytes()), Err(ConnectError::InvalidAuthority(_))),
+                "{}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_absolute_form_target() {
+        let result = connect(b"CONNECT https://example.com:443/ HTTP/1.1\r\n\r\n");
+        assert!(matches!(result, Err(ConnectError::InvalidAuthority(_))));
+    }
+
+    #[test]
+    fn rejects_other_methods() {
+        let result = parse_request(b"GET http://example.com/ HTTP/1.1\r\n\r\n", false);
+        assert!(matches!(result, Err(ConnectError::MethodNotAllowed(method)) if method == "GET"));
+    }
+
+    #[test]
+    fn accepts_http_1_0() {
+        let request = connect(b"CONNECT example.com:443 HTTP/1.0\r\n\r\n").unwrap();
+        assert_eq!(request.version, 0);
+    }
+
+    #[test]
+    fn incomplete_header_block_is_partial() {
+        assert!(parse_request(b"CONNECT example.com:443 HTTP/1.1\r\nHost: exa", false).unwrap().is_none());
+    }
+
+    #[test]
+    fn keeps_pipelined_bytes() {
+        let request = connect(b"CONNECT example.com:443 HTTP/1.1\r\n\r\n\x16\x03\x01\x00\x05hello").unwrap();
+        assert_eq!(request.pipelined, b"\x16\x03\x01\x00\x05hello");
+    }
+
+    #[test]
+    fn parses_websocket_upgrade_only_when_allowed() {
+        let upgrade = b"GET / HTTP/1.1\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\n\
+                        Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+        match parse_request(upgrade, true).unwrap() {
+            Some(ProxyRequest::Upgrade(request)) => assert_eq!(request.key, "dGhlIHNhbXBsZSBub25jZQ=="),
+            other => panic!("expected an upgrade request, got {:?}", other),
+        }
+        assert!(matches!(parse_request(upgrade, false), Err(ConnectError::MethodNotAllowed(_))));
+    }
+
+    #[tokio::test]
+    async fn reads_headers_split_across_reads() {
+        let mut reader = ChunkedReader::new(&[
+            b"CONNECT exam",
+            b"ple.com:443 HTTP/1.1\r",
+            b"\nHost: example.com:443\r\n\r",
+            b"\n\x16\x03",
+        ]);
+        match read_request(&mut reader, 4096, false).await.unwrap() {
+            ProxyRequest::Connect(request) => {
+                assert_eq!(request.authority(), "example.com:443");
+                assert_eq!(request.pipelined, b"\x16\x03");
+            }
+            other => panic!("expected a CONNECT request, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_header_block_over_limit() {
+        let request = format!("CONNECT example.com:443 HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(2048));
+        let mut reader = ChunkedReader::new(&[request.as_bytes()]);
+        assert!(matches!(read_request(&mut reader, 1024, false).await, Err(ConnectError::TooLarge)));
+
+        // A request of exactly the limit is still accepted
+        let request = b"CONNECT example.com:443 HTTP/1.1\r\n\r\n";
+        let mut reader = ChunkedReader::new(&[request]);
+        assert!(read_request(&mut reader, request.len(), false).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn reports_early_close() {
+        let mut reader = ChunkedReader::new(&[]);
+        assert!(matches!(read_request(&mut reader, 1024, false).await, Err(ConnectError::Closed)));
+
+        let mut reader = ChunkedReader::new(&[b"CONNECT example.com:443 HTTP/1.1\r\n"]);
+        assert!(matches!(read_request(&mut reader, 1024, false).await, Err(ConnectError::Malformed(_))));
+    }
+}
@@ -0,0 +1,614 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use openssl::{
+    hash::{hash, MessageDigest},
+    nid::Nid,
+    pkey::{Id, PKey, Public},
+    rsa::Padding,
+    sign::{RsaPssSaltlen, Verifier},
+};
+use serde::{Deserialize, Serialize};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+use zap_types::NotaryPublicKey;
+use zeroize::Zeroizing;
+
+use crate::signer::{
+    pkcs11::{Pkcs11KeyConfig, Pkcs11Signer},
+    remote::RemoteSigner,
+    FileSigner, Signer,
+};
+
+/// Length in bytes of a key ID, taken from the SHA-256 of the public key.
+const KEY_ID_BYTES: usize = 16;
+/// Environment variable holding the PKCS#11 user PIN when a key does not name its own.
+const DEFAULT_PKCS11_PIN_ENV: &str = "ZAP_PKCS11_PIN";
+
+/// Signature algorithms a notary key can be used with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Algorithm {
+    #[serde(rename = "RSA-PKCS1-SHA256")]
+    RsaPkcs1Sha256,
+    #[serde(rename = "RSA-PSS-SHA256")]
+    RsaPssSha256,
+    #[serde(rename = "ECDSA-P256-SHA256")]
+    EcdsaP256Sha256,
+    #[serde(rename = "Ed25519")]
+    Ed25519,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::RsaPkcs1Sha256 => "RSA-PKCS1-SHA256",
+            Algorithm::RsaPssSha256 => "RSA-PSS-SHA256",
+            Algorithm::EcdsaP256Sha256 => "ECDSA-P256-SHA256",
+            Algorithm::Ed25519 => "Ed25519",
+        }
+    }
+
+    /// Default algorithm for a key, RSA keys keep PKCS#1 v1.5 for compatibility.
+    fn default_for(public_key: &PKey<Public>) -> Result<Self> {
+        match public_key.id() {
+            Id::RSA => Ok(Algorithm::RsaPkcs1Sha256),
+            Id::EC => Ok(Algorithm::EcdsaP256Sha256),
+            Id::ED25519 => Ok(Algorithm::Ed25519),
+            id => bail!("Unsupported notary key type {:?}", id),
+        }
+    }
+
+    fn check_key(self, public_key: &PKey<Public>) -> Result<()> {
+        let matches = match self {
+            Algorithm::RsaPkcs1Sha256 | Algorithm::RsaPssSha256 => public_key.id() == Id::RSA,
+            Algorithm::EcdsaP256Sha256 => {
+                public_key.id() == Id::EC
+                    && public_key.ec_key()?.group().curve_name() == Some(Nid::X9_62_PRIME256V1)
+            }
+            Algorithm::Ed25519 => public_key.id() == Id::ED25519,
+        };
+        if !matches {
+            bail!("Key of type {:?} cannot be used with {}", public_key.id(), self.name());
+        }
+        Ok(())
+    }
+}
+
+/// A signing key used to attest sessions, backed by any [`Signer`].
+pub struct NotaryKey {
+    id: String,
+    algorithm: Algorithm,
+    public_key: PKey<Public>,
+    signer: Box<dyn Signer>,
+    not_before: Option<DateTime<Utc>>,
+    not_after: Option<DateTime<Utc>>,
+}
+
+impl NotaryKey {
+    pub fn from_pem(pem: &[u8], algorithm: Option<Algorithm>) -> Result<Self> {
+        let signer = FileSigner::from_pem(pem).context("Failed to load private key")?;
+        Self::new(signer.public_key()?, algorithm, Box::new(signer))
+    }
+
+    pub fn new(
+        public_key: PKey<Public>,
+        algorithm: Option<Algorithm>,
+        signer: Box<dyn Signer>,
+    ) -> Result<Self> {
+        let algorithm = match algorithm {
+            Some(algorithm) => algorithm,
+            None => Algorithm::default_for(&public_key)?,
+        };
+        algorithm.check_key(&public_key)?;
+
+        let public_key_der = public_key.public_key_to_der()?;
+        let digest = hash(MessageDigest::sha256(), &public_key_der)?;
+
+        Ok(Self {
+            id: hex::encode(&digest[..KEY_ID_BYTES]),
+            algorithm,
+            public_key,
+            signer,
+            not_before: None,
+            not_after: None,
+        })
+    }
+
+    /// Restricts the window in which the key is used for new attestations.
+    pub fn with_validity(
+        mut self,
+        not_before: Option<DateTime<Utc>>,
+        not_after: Option<DateTime<Utc>>,
+    ) -> Self {
+        self.not_before = not_before;
+        self.not_after = not_after;
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn algorithm(&self) -> &'static str {
+        self.algorithm.name()
+    }
+
+    pub fn is_active_at(&self, now: DateTime<Utc>) -> bool {
+        self.not_before.is_none_or(|not_before| not_before <= now)
+            && self.not_after.is_none_or(|not_after| now < not_after)
+    }
+
+    /// Signs `data` with the backend, which may block on a socket or a token.
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let signature = self.signer.sign(self.algorithm, data)?;
+        // Catches a backend signing with another key than the one advertised for this ID
+        if !self.verify(data, &signature)? {
+            bail!("Signature from key {} does not verify against its public key", self.id);
+        }
+        Ok(signature)
+    }
+
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool> {
+        let mut verifier = match self.algorithm {
+            Algorithm::Ed25519 => Verifier::new_without_digest(&self.public_key)?,
+            _ => Verifier::new(MessageDigest::sha256(), &self.public_key)?,
+        };
+        if self.algorithm == Algorithm::RsaPssSha256 {
+            verifier.set_rsa_padding(Padding::PKCS1_PSS)?;
+            verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+            verifier.set_rsa_mgf1_md(MessageDigest::sha256())?;
+        }
+        Ok(verifier.verify_oneshot(signature, data)?)
+    }
+
+    /// The public half of the key, as served by `GET /keys`.
+    pub fn public_key_info(&self, current: bool) -> Result<NotaryPublicKey> {
+        let public_key = String::from_utf8(self.public_key.public_key_to_pem()?)?;
+        // Same form as the keyring manifest
+        let timestamp = |time: DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        Ok(NotaryPublicKey::new(
+            &self.id,
+            self.algorithm(),
+            &public_key,
+            current,
+            self.not_before.map(timestamp),
+            self.not_after.map(timestamp),
+        ))
+    }
+}
+
+/// One entry of a keyring manifest. Relative paths are resolved against the manifest.
+///
+/// A key is either a PEM file given by `path`, or held by the backend given by `signer`.
+#[derive(Debug, Deserialize)]
+struct KeyEntry {
+    path: Option<PathBuf>,
+    signer: Option<SignerEntry>,
+    algorithm: Option<Algorithm>,
+    not_before: Option<DateTime<Utc>>,
+    not_after: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SignerEntry {
+    /// A key held by a signing daemon, see [`crate::signer::remote`].
+    Remote { socket: PathBuf, key: String },
+    /// A key on a PKCS#11 token. The PIN is read from the `pin_env` environment variable.
+    Pkcs11 {
+        module: PathBuf,
+        token_label: String,
+        key_label: String,
+        public_key: PathBuf,
+        pin_env: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyringManifest {
+    keys: Vec<KeyEntry>,
+}
+
+impl KeyEntry {
+    /// Loads the key, returning it with the files it was read from.
+    fn load(self, base: &Path) -> Result<(NotaryKey, Vec<PathBuf>)> {
+        let (key, files) = match (self.path, self.signer) {
+            (Some(path), None) => {
+                let path = base.join(path);
+                let pem =
+                    fs::read(&path).with_context(|| format!("Unable to read key file {}", path.display()))?;
+                let key = NotaryKey::from_pem(&pem, self.algorithm)
+                    .with_context(|| format!("Invalid key {}", path.display()))?;
+                (key, vec![path])
+            }
+            (None, Some(SignerEntry::Remote { socket, key })) => {
+                let signer = RemoteSigner::new(&base.join(socket), &key);
+                let public_key = signer
+                    .public_key()
+                    .with_context(|| format!("Unable to fetch public key of remote key {}", key))?;
+                (NotaryKey::new(public_key, self.algorithm, Box::new(signer))?, Vec::new())
+            }
+            (None, Some(SignerEntry::Pkcs11 { module, token_label, key_label, public_key, pin_env })) => {
+                let public_key = base.join(public_key);
+                let pem = fs::read(&public_key)
+                    .with_context(|| format!("Unable to read public key {}", public_key.display()))?;
+                let pin_env = pin_env.as_deref().unwrap_or(DEFAULT_PKCS11_PIN_ENV);
+                let config = Pkcs11KeyConfig {
+                    module,
+                    token_label,
+                    key_label,
+                    pin: Zeroizing::new(
+                        env::var(pin_env).with_context(|| format!("{} is not set", pin_env))?,
+                    ),
+                };
+                let signer = Pkcs11Signer::open(&config)
+                    .with_context(|| format!("Unable to open PKCS#11 key {}", config.key_label))?;
+                let key = NotaryKey::new(PKey::public_key_from_pem(&pem)?, self.algorithm, Box::new(signer))?;
+                (key, vec![public_key])
+            }
+            _ => bail!("A keyring entry needs exactly one of path and signer"),
+        };
+        Ok((key.with_validity(self.not_before, self.not_after), files))
+    }
+}
+
+/// The set of notary keys: the one currently signing plus older keys that verifiers still
+/// need for proofs issued before a rotation.
+///
+/// A keyring is loaded either from a single PEM file or from a JSON manifest:
+///
+/// ```json
+/// {
+///   "keys": [
+///     { "path": "notary-2025.pem", "not_after": "2026-01-01T00:00:00Z" },
+///     {
+///       "signer": { "type": "remote", "socket": "/run/zap-signer.sock", "key": "notary-2026" },
+///       "not_before": "2026-01-01T00:00:00Z"
+///     },
+///     {
+///       "signer": {
+///         "type": "pkcs11",
+///         "module": "/usr/lib/softhsm/libsofthsm2.so",
+///         "token_label": "zap",
+///         "key_label": "notary",
+///         "public_key": "notary.pub.pem"
+///       },
+///       "algorithm": "Ed25519",
+///       "not_before": "2027-01-01T00:00:00Z"
+///     }
+///   ]
+/// }
+/// ```
+pub struct Keyring {
+    keys: Vec<NotaryKey>,
+    /// Files the keyring was loaded from, with their modification times at load.
+    sources: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Keyring {
+    pub fn load(path: &Path) -> Result<Self> {
+        let is_manifest = path.extension().is_some_and(|extension| extension == "json");
+        if !is_manifest {
+            let pem =
+                fs::read(path).with_context(|| format!("Unable to read key file {}", path.display()))?;
+            let key = NotaryKey::from_pem(&pem, None)?;
+            return Ok(Self { keys: vec![key], sources: vec![(path.to_path_buf(), modified(path))] });
+        }
+
+        let manifest =
+            fs::read(path).with_context(|| format!("Unable to read keyring {}", path.display()))?;
+        let manifest: KeyringManifest = serde_json::from_slice(&manifest)
+            .with_context(|| format!("Invalid keyring manifest {}", path.display()))?;
+
+        let base = path.parent().unwrap_or(Path::new("."));
+        let mut sources = vec![(path.to_path_buf(), modified(path))];
+        let mut keys = Vec::with_capacity(manifest.keys.len());
+        for entry in manifest.keys {
+            let (key, files) = entry.load(base)?;
+            sources.extend(files.into_iter().map(|file| {
+                let time = modified(&file);
+                (file, time)
+            }));
+            keys.push(key);
+        }
+
+        if keys.is_empty() {
+            bail!("Keyring {} contains no keys", path.display());
+        }
+        Ok(Self { keys, sources })
+    }
+
+    /// Returns the key used for new attestations: the active key activated most recently.
+    pub fn current(&self) -> Option<&NotaryKey> {
+        let now = Utc::now();
+        self.keys.iter().filter(|key| key.is_active_at(now)).max_by_key(|key| key.not_before)
+    }
+
+    pub fn public_keys(&self) -> Result<Vec<NotaryPublicKey>> {
+        let current_id = self.current().map(NotaryKey::id);
+        self.keys.iter().map(|key| key.public_key_info(Some(key.id()) == current_id)).collect()
+    }
+
+    /// Current modification times of the files the keyring was loaded from.
+    fn source_times(&self) -> Vec<Option<SystemTime>> {
+        self.sources.iter().map(|(path, _)| modified(path)).collect()
+    }
+
+    /// Whether any file the keyring was loaded from has changed since.
+    fn is_stale(&self, times: &[Option<SystemTime>]) -> bool {
+        self.sources.iter().zip(times).any(|((_, loaded), time)| loaded != time)
+    }
+}
+
+/// A keyring that can be swapped out while the proxy is running.
+///
+/// Readers take a snapshot with [`ReloadableKeyring::get`], so a reload never changes the key
+/// in the middle of signing an attestation.
+pub struct ReloadableKeyring {
+    path: PathBuf,
+    keyring: RwLock<Arc<Keyring>>,
+}
+
+impl ReloadableKeyring {
+    pub fn load(path: &Path) -> Result<Self> {
+        let keyring = Keyring::load(path)?;
+        Ok(Self { path: path.to_path_buf(), keyring: RwLock::new(Arc::new(keyring)) })
+    }
+
+    pub fn get(&self) -> Arc<Keyring> {
+        self.keyring.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Loads the keyring again, keeping the previous one if the new files are invalid.
+    pub fn reload(&self) -> Result<()> {
+        let keyring = Keyring::load(&self.path)?;
+        *self.keyring.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(keyring);
+        Ok(())
+    }
+
+    /// Reloads the keyring on SIGHUP and whenever one of its files changes.
+    pub async fn watch(&self, poll_interval: Duration) {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => Some(hangup),
+            Err(e) => {
+                warn!(error = %e, "Unable to listen for SIGHUP, keyring reloads on file changes only");
+                None
+            }
+        };
+        let mut poll = tokio::time::interval(poll_interval);
+        // Files that failed to load are only retried once they change again
+        let mut failed_times = None;
+
+        loop {
+            let reason = tokio::select! {
+                Some(()) = async { hangup.as_mut()?.recv().await } => "SIGHUP",
+                _ = poll.tick() => {
+                    let keyring = self.get();
+                    let times = keyring.source_times();
+                    if !keyring.is_stale(&times) || failed_times.as_ref() == Some(&times) {
+                        continue;
+                    }
+                    "file change"
+                }
+            };
+
+            match self.reload() {
+                Ok(()) => {
+                    failed_times = None;
+                    let keyring = self.get();
+                    info!(
+                        reason,
+                        current_key = keyring.current().map(NotaryKey::id).unwrap_or("none"),
+                        "Reloaded keyring"
+                    );
+                }
+                Err(e) => {
+                    failed_times = Some(self.get().source_times());
+                    error!(reason, error = %format!("{:#}", e), "Failed to reload keyring, keeping previous keys");
+                }
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fs::File;
+
+    use openssl::{pkey::Private, rsa::Rsa};
+
+    use super::*;
+    use crate::tests::{notary_key_pem, temp_file};
+
+    fn time(rfc3339: &str) -> DateTime<Utc> {
+        rfc3339.parse().unwrap()
+    }
+
+    /// Writes a manifest with one new key per entry of `windows`, returning it and the files
+    /// to remove afterwards.
+    pub(crate) fn manifest(windows: &[(Option<&str>, Option<&str>)]) -> (PathBuf, Vec<PathBuf>) {
+        let mut files = Vec::new();
+        let keys: Vec<serde_json::Value> = windows
+            .iter()
+            .map(|(not_before, not_after)| {
+                let path = temp_file("key.pem", &notary_key_pem());
+                let name = path.file_name().unwrap().to_str().unwrap().to_string();
+                files.push(path);
+                serde_json::json!({ "path": name, "not_before": not_before, "not_after": not_after })
+            })
+            .collect();
+        let manifest = serde_json::json!({ "keys": keys }).to_string();
+        let path = temp_file("keyring.json", manifest.as_bytes());
+        files.push(path.clone());
+        (path, files)
+    }
+
+    pub(crate) fn remove(files: Vec<PathBuf>) {
+        for file in files {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    fn from_key(key: &PKey<Private>, algorithm: Option<Algorithm>) -> Result<NotaryKey> {
+        NotaryKey::from_pem(&key.private_key_to_pem_pkcs8().unwrap(), algorithm)
+    }
+
+    #[test]
+    fn ed25519_signatures_round_trip() {
+        let key = from_key(&PKey::generate_ed25519().unwrap(), None).unwrap();
+        assert_eq!(key.algorithm(), "Ed25519");
+
+        let signature = key.sign(b"attestation").unwrap();
+        assert_eq!(signature.len(), 64);
+        assert!(key.verify(b"attestation", &signature).unwrap());
+        assert!(!key.verify(b"attestation!", &signature).unwrap());
+    }
+
+    #[test]
+    fn key_id_is_derived_from_the_public_key() {
+        let private_key = PKey::generate_ed25519().unwrap();
+        let key = from_key(&private_key, None).unwrap();
+        assert_eq!(key.id().len(), KEY_ID_BYTES * 2);
+        assert_eq!(key.id(), from_key(&private_key, None).unwrap().id());
+        assert_ne!(key.id(), from_key(&PKey::generate_ed25519().unwrap(), None).unwrap().id());
+
+        let info = key.public_key_info(true).unwrap();
+        assert_eq!(info.get_key_id(), key.id());
+        assert!(info.get_public_key().starts_with("-----BEGIN PUBLIC KEY-----"));
+    }
+
+    #[test]
+    fn rejects_algorithms_that_do_not_match_the_key() {
+        let ed25519 = PKey::generate_ed25519().unwrap();
+        assert!(from_key(&ed25519, Some(Algorithm::RsaPssSha256)).is_err());
+        assert!(from_key(&ed25519, Some(Algorithm::EcdsaP256Sha256)).is_err());
+    }
+
+    #[test]
+    fn rsa_pss_signatures_round_trip() {
+        let rsa = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let key = from_key(&rsa, Some(Algorithm::RsaPssSha256)).unwrap();
+        assert_eq!(key.algorithm(), "RSA-PSS-SHA256");
+
+        let signature = key.sign(b"attestation").unwrap();
+        assert!(key.verify(b"attestation", &signature).unwrap());
+        assert!(!key.verify(b"attestation!", &signature).unwrap());
+
+        // PSS and PKCS#1 v1.5 signatures do not verify as each other
+        let pkcs1 = from_key(&rsa, None).unwrap();
+        assert_eq!(pkcs1.algorithm(), "RSA-PKCS1-SHA256");
+        assert!(!pkcs1.verify(b"attestation", &signature).unwrap());
+    }
+
+    #[test]
+    fn validity_window_includes_not_before_and_excludes_not_after() {
+        let key = NotaryKey::from_pem(&notary_key_pem(), None)
+            .unwrap()
+            .with_validity(Some(time("2026-01-01T00:00:00Z")), Some(time("2027-01-01T00:00:00Z")));
+        assert!(!key.is_active_at(time("2025-12-31T23:59:59Z")));
+        assert!(key.is_active_at(time("2026-01-01T00:00:00Z")));
+        assert!(key.is_active_at(time("2026-12-31T23:59:59Z")));
+        assert!(!key.is_active_at(time("2027-01-01T00:00:00Z")));
+    }
+
+    #[test]
+    fn current_key_is_the_active_key_activated_last() {
+        let (path, files) = manifest(&[
+            (Some("2020-01-01T00:00:00Z"), None),
+            // Expired, though activated after the first key
+            (Some("2021-01-01T00:00:00Z"), Some("2022-01-01T00:00:00Z")),
+            (Some("2024-01-01T00:00:00Z"), None),
+            // Not valid yet
+            (Some("2999-01-01T00:00:00Z"), None),
+        ]);
+        let keyring = Keyring::load(&path);
+        remove(files);
+        let keyring = keyring.unwrap();
+
+        assert_eq!(keyring.keys.len(), 4);
+        let current = keyring.current().unwrap();
+        assert_eq!(current.id(), keyring.keys[2].id());
+
+        // Every key is still served for verification, only one is current
+        let public_keys = keyring.public_keys().unwrap();
+        let current: Vec<bool> = public_keys.iter().map(|key| key.is_current()).collect();
+        assert_eq!(current, [false, false, true, false]);
+        assert_eq!(public_keys[3].get_not_before(), Some("2999-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn no_current_key_when_none_is_active() {
+        let (path, files) = manifest(&[
+            (None, Some("2020-01-01T00:00:00Z")),
+            (Some("2999-01-01T00:00:00Z"), None),
+        ]);
+        let keyring = Keyring::load(&path);
+        remove(files);
+        assert!(keyring.unwrap().current().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_manifests() {
+        for manifest in [
+            r#"{ "keys": [] }"#,
+            r#"{ "keys": [{ "not_before": "2020-01-01T00:00:00Z" }] }"#,
+            r#"{ "keys": [{ "path": "a.pem", "signer": { "type": "remote", "socket": "s", "key": "k" } }] }"#,
+            r#"{ "keys": [{ "path": "missing-key.pem" }] }"#,
+            r#"{ "keys": [{ "path": "a.pem", "algorithm": "DSA" }] }"#,
+            r#"{ "keys": "#,
+        ] {
+            let path = temp_file("keyring.json", manifest.as_bytes());
+            let keyring = Keyring::load(&path);
+            fs::remove_file(path).unwrap();
+            assert!(keyring.is_err(), "{}", manifest);
+        }
+    }
+
+    #[tokio::test]
+    async fn reloads_when_a_key_file_changes() {
+        let path = temp_file("notary.pem", &notary_key_pem());
+        let keyring = Arc::new(ReloadableKeyring::load(&path).unwrap());
+        let old_id = keyring.get().current().unwrap().id().to_string();
+        assert!(!keyring.get().is_stale(&keyring.get().source_times()));
+
+        let watcher = keyring.clone();
+        let watch = tokio::spawn(async move { watcher.watch(Duration::from_millis(10)).await });
+
+        // A rotated key with a different modification time
+        fs::write(&path, notary_key_pem()).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+
+        let reloaded = tokio::time::timeout(Duration::from_secs(5), async {
+            while keyring.get().current().unwrap().id() == old_id {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        watch.abort();
+        fs::remove_file(&path).unwrap();
+        assert!(reloaded.is_ok(), "keyring was not reloaded");
+    }
+
+    #[test]
+    fn keeps_the_previous_keyring_when_a_reload_fails() {
+        let path = temp_file("notary.pem", &notary_key_pem());
+        let keyring = ReloadableKeyring::load(&path).unwrap();
+        let id = keyring.get().current().unwrap().id().to_string();
+
+        fs::write(&path, b"not a key").unwrap();
+        assert!(keyring.reload().is_err());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(keyring.get().current().unwrap().id(), id);
+    }
+}
@@ -0,0 +1,66 @@
+//! Logging to stderr through `tracing-subscriber`, one line per event prefixed with the
+//! enclosing spans and their fields:
+//!
+//! ```text
+//! 2026-10-19T05:49:30.120512Z  INFO session{id=9f2c41d07a3be815 peer=127.0.0.1:50412}:connect{target=example.com:443}: proxy: Tunnel established principal="alice"
+//! ```
+
+use std::{
+    fmt,
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use tracing_subscriber::EnvFilter;
+
+static REVEAL_SECRETS: AtomicBool = AtomicBool::new(false);
+
+const DEFAULT_FILTER: &str = "info";
+
+/// Installs the stderr subscriber. `filter` takes `EnvFilter` directives, either a single
+/// level such as `debug` or per-module levels such as `info,proxy::api=debug`. Secret-bearing
+/// fields wrapped in [`Sensitive`] are only written out when `reveal_secrets` is set.
+pub fn init(filter: &str, reveal_secrets: bool) {
+    REVEAL_SECRETS.store(reveal_secrets, Ordering::Relaxed);
+    let filter = EnvFilter::try_new(filter).unwrap_or_else(|e| {
+        eprintln!("Ignoring invalid log filter {:?}: {}", filter, e);
+        EnvFilter::new(DEFAULT_FILTER)
+    });
+    let installed = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .try_init();
+    if installed.is_err() {
+        eprintln!("A tracing subscriber is already installed");
+    }
+}
+
+/// Wraps a value holding decrypted traffic, formatting as `[redacted]` unless secrets logging is enabled.
+pub struct Sensitive<T>(pub T);
+
+impl<T: fmt::Debug> fmt::Debug for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if REVEAL_SECRETS.load(Ordering::Relaxed) {
+            self.0.fmt(f)
+        } else {
+            f.write_str("[redacted]")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sensitive_values_are_redacted_unless_revealed() {
+        let records = vec!["HTTP/1.1 200 OK".to_string()];
+        assert_eq!(format!("{:?}", Sensitive(&records)), "[redacted]");
+
+        REVEAL_SECRETS.store(true, Ordering::Relaxed);
+        let revealed = format!("{:?}", Sensitive(&records));
+        REVEAL_SECRETS.store(false, Ordering::Relaxed);
+        assert_eq!(revealed, "[\"HTTP/1.1 200 OK\"]");
+    }
+}
@@ -1,343 +1,870 @@
+mod api;
+mod attestation;
+mod auth;
+mod config;
+mod connect;
+mod keys;
+mod logging;
+mod metrics;
+mod signer;
+mod storage;
+mod tls;
+mod transcript;
+mod websocket;
+
 use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
-use std::fs::{self, OpenOptions};
-use std::sync::Arc;
-use std::io::Write;
-use openssl::pkey::PKey;
-use openssl::sign::Signer;
-use openssl::hash::MessageDigest;
-use hyper::{Body, Response, Server, Method};
-use hyper::service::{make_service_fn, service_fn};
-use serde::{Serialize, Deserialize};
-use chrono;
-use tokio::sync::Mutex;
-use aes_gcm::{aead::{Aead, KeyInit, Payload}, Aes256Gcm, Nonce, Key};
-use hex::{decode, encode};
-use anyhow::{Result, Context};
-use std::str;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{timeout, Instant};
+use tokio_rustls::TlsAcceptor;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use serde::Serialize;
+use tracing::{error, field, info, info_span, warn, Instrument, Span};
+use chrono::Utc;
+use zap_types::{decrypt_records, DecryptError, SecretsPayload, SESSION_ID_HEADER, SESSION_TOKEN_HEADER};
+
+use crate::auth::{ApiKeyDigest, Authenticator, CredentialStore, FileCredentialStore, PasswordHash};
+use crate::config::ProxyConfig;
+use crate::connect::{ConnectError, ConnectRequest, ProxyRequest};
+use crate::keys::ReloadableKeyring;
+use crate::metrics::{ConnectOutcome, Metrics};
+use crate::storage::{
+    memory::MemoryStore, sqlite::SqliteStore, writer::TranscriptWriter, SessionRecord, SessionState, SessionStore,
+};
+use crate::transcript::{SessionTranscript, TranscriptLog};
 
 const LISTEN_PORT: u16 = 55688;
-const LOG_FILE: &str = "utils/proxy.log";
-const PRIVATE_KEY_FILE: &str = "utils/private-key.pem";
 
-#[derive(Clone, Copy)]
+/// State shared by the CONNECT listener and the HTTP API.
+struct ProxyState {
+    config: ProxyConfig,
+    keyring: ReloadableKeyring,
+    store: Arc<dyn SessionStore>,
+    /// Stores tunnel traffic in batches, off the relaying tasks.
+    transcript_writer: TranscriptWriter,
+    tunnels: ActiveTunnels,
+    /// Set on shutdown, new tunnels are refused while the open ones finish.
+    draining: AtomicBool,
+    metrics: Metrics,
+    transcript: TranscriptLog,
+    authenticator: Authenticator,
+    tls_acceptor: Option<TlsAcceptor>,
+}
+
+/// Open tunnels by session id, so the HTTP API can close them.
+#[derive(Default)]
+struct ActiveTunnels {
+    tunnels: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+impl ActiveTunnels {
+    fn register(&self, session_id: &str) -> Arc<Notify> {
+        let close = Arc::new(Notify::new());
+        self.tunnels.lock().unwrap().insert(session_id.to_string(), close.clone());
+        close
+    }
+
+    fn remove(&self, session_id: &str) {
+        self.tunnels.lock().unwrap().remove(session_id);
+    }
+
+    fn len(&self) -> usize {
+        self.tunnels.lock().unwrap().len()
+    }
+
+    /// Asks the tunnel of `session_id` to close, returning `false` if it is not open.
+    fn close(&self, session_id: &str) -> bool {
+        match self.tunnels.lock().unwrap().get(session_id) {
+            Some(close) => {
+                close.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 enum Direction {
     ClientToServer,
     ServerToClient,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct SecretsPayload {
-    rx_sequence_number: u64,
-    tx_sequence_number: u64,
-    rx_secret: SecretData,
-    tx_secret: SecretData,
+/// Error responses sent to the client before the tunnel is established.
+#[derive(Clone, Copy)]
+enum Rejection {
+    BadRequest,
+    ProxyAuthenticationRequired,
+    MethodNotAllowed,
+    RequestTimeout,
+    HeaderFieldsTooLarge,
+    BadGateway,
+    ServiceUnavailable,
+    GatewayTimeout,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct SecretData {
-    cipher_suite: String,
-    key: String,
-    iv: String,
+impl Rejection {
+    fn status_line(self) -> &'static str {
+        match self {
+            Rejection::BadRequest => "400 Bad Request",
+            Rejection::ProxyAuthenticationRequired => "407 Proxy Authentication Required",
+            Rejection::MethodNotAllowed => "405 Method Not Allowed",
+            Rejection::RequestTimeout => "408 Request Timeout",
+            Rejection::HeaderFieldsTooLarge => "431 Request Header Fields Too Large",
+            Rejection::BadGateway => "502 Bad Gateway",
+            Rejection::ServiceUnavailable => "503 Service Unavailable",
+            Rejection::GatewayTimeout => "504 Gateway Timeout",
+        }
+    }
+
+    fn outcome(self) -> ConnectOutcome {
+        match self {
+            Rejection::BadRequest
+            | Rejection::MethodNotAllowed
+            | Rejection::RequestTimeout
+            | Rejection::HeaderFieldsTooLarge => ConnectOutcome::ParseError,
+            Rejection::ProxyAuthenticationRequired => ConnectOutcome::PolicyDenied,
+            Rejection::BadGateway | Rejection::GatewayTimeout => ConnectOutcome::TargetUnreachable,
+            Rejection::ServiceUnavailable => ConnectOutcome::Unavailable,
+        }
+    }
+
+    fn extra_headers(self) -> &'static str {
+        match self {
+            Rejection::ProxyAuthenticationRequired => {
+                "Proxy-Authenticate: Basic realm=\"zap\", Bearer realm=\"zap\"\r\n"
+            }
+            _ => "",
+        }
+    }
+}
+
+async fn reject<S>(client_socket: &mut S, metrics: &Metrics, rejection: Rejection) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    metrics.connect(rejection.outcome());
+    let response = format!(
+        "HTTP/1.1 {}\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n",
+        rejection.status_line(),
+        rejection.extra_headers()
+    );
+    client_socket.write_all(response.as_bytes()).await?;
+    client_socket.shutdown().await
+}
+
+/// Per-session activity and transcript accounting, shared by both tunnel directions.
+struct SessionBudget {
+    started: Instant,
+    last_activity_ms: AtomicU64,
+    /// Bytes recorded in each direction, indexed by `Direction`.
+    recorded_bytes: [AtomicUsize; 2],
+}
+
+impl SessionBudget {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_activity_ms: AtomicU64::new(0),
+            recorded_bytes: [AtomicUsize::new(0), AtomicUsize::new(0)],
+        }
+    }
+
+    fn touch(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.last_activity_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    fn idle_for(&self) -> Duration {
+        let last_activity = Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last_activity)
+    }
+
+    /// Accounts for `n` more transcript bytes, returning `false` once `limit` would be exceeded.
+    fn record(&self, direction: Direction, n: usize, limit: usize) -> bool {
+        self.recorded_bytes[direction as usize].fetch_add(n, Ordering::Relaxed);
+        self.recorded(Direction::ClientToServer) + self.recorded(Direction::ServerToClient) <= limit
+    }
+
+    fn recorded(&self, direction: Direction) -> usize {
+        self.recorded_bytes[direction as usize].load(Ordering::Relaxed)
+    }
 }
 
-async fn handle_client(
-    mut client_socket: TcpStream,
-    private_key: Arc<PKey<openssl::pkey::Private>>,
-    data_log: Arc<Mutex<Vec<(Direction, Vec<u8>)>>>,
+/// Completes the optional TLS handshake on an accepted connection and serves it.
+async fn serve_client(
+    client_socket: TcpStream,
+    permit: Option<OwnedSemaphorePermit>,
+    state: Arc<ProxyState>,
+    session_id: String,
 ) -> io::Result<()> {
-    let mut buffer = vec![0; 1024];
-    let n = client_socket.read(&mut buffer).await?;
-    if n == 0 {
+    let Some(acceptor) = &state.tls_acceptor else {
+        return handle_client(client_socket, permit, &state, &session_id).await;
+    };
+
+    let handshake = timeout(state.config.connect_read_timeout, acceptor.accept(client_socket));
+    match handshake.instrument(info_span!("handshake")).await {
+        Ok(Ok(tls_stream)) => handle_client(tls_stream, permit, &state, &session_id).await,
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out")),
+    }
+}
+
+async fn handle_client<S>(
+    mut client_socket: S,
+    permit: Option<OwnedSemaphorePermit>,
+    state: &ProxyState,
+    session_id: &str,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let Some(_permit) = permit else {
+        warn!("Session limit reached, rejecting client");
+        return reject(&mut client_socket, &state.metrics, Rejection::ServiceUnavailable).await;
+    };
+    let connect_span = info_span!("connect", target = field::Empty);
+
+    let request = match read_request(&mut client_socket, state, &connect_span, true).await? {
+        Some(ProxyRequest::Connect(request)) => request,
+        Some(ProxyRequest::Upgrade(upgrade)) => {
+            info!(parent: &connect_span, "Upgraded to WebSocket");
+            let mut websocket = websocket::accept(client_socket, upgrade).await?;
+            // The CONNECT request follows inside the WebSocket
+            return match read_request(&mut websocket, state, &connect_span, false).await? {
+                Some(ProxyRequest::Connect(request)) => {
+                    handle_connect(websocket, request, state, session_id, connect_span).await
+                }
+                _ => Ok(()),
+            };
+        }
+        None => return Ok(()),
+    };
+    handle_connect(client_socket, request, state, session_id, connect_span).await
+}
+
+/// Reads the client's request, answering it if it is invalid. Returns `None` once the client
+/// has been turned away or has left.
+async fn read_request<S>(
+    client_socket: &mut S,
+    state: &ProxyState,
+    connect_span: &Span,
+    allow_upgrade: bool,
+) -> io::Result<Option<ProxyRequest>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let config = &state.config;
+    let read = connect::read_request(client_socket, config.max_connect_request_bytes, allow_upgrade);
+    let error = match timeout(config.connect_read_timeout, read).instrument(connect_span.clone()).await {
+        Ok(Ok(request)) => return Ok(Some(request)),
         // Client closed the connection
-        return Ok(());
+        Ok(Err(ConnectError::Closed)) => return Ok(None),
+        Ok(Err(ConnectError::Io(e))) => return Err(e),
+        Ok(Err(e)) => e,
+        Err(_) => {
+            warn!(parent: connect_span, "Timed out waiting for CONNECT request");
+            reject(client_socket, &state.metrics, Rejection::RequestTimeout).await?;
+            return Ok(None);
+        }
+    };
+
+    warn!(parent: connect_span, error = %error, "Invalid CONNECT request");
+    let rejection = match error {
+        ConnectError::TooLarge => Rejection::HeaderFieldsTooLarge,
+        ConnectError::MethodNotAllowed(_) => Rejection::MethodNotAllowed,
+        _ => Rejection::BadRequest,
+    };
+    reject(client_socket, &state.metrics, rejection).await?;
+    Ok(None)
+}
+
+/// Opens the tunnel requested by `request` and relays it until either side closes.
+async fn handle_connect<S>(
+    mut client_socket: S,
+    request: ConnectRequest,
+    state: &ProxyState,
+    session_id: &str,
+    connect_span: Span,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let config = &state.config;
+    let target = request.authority();
+    connect_span.record("target", field::display(&target));
+
+    let principal = match state.authenticator.authenticate(request.header("Proxy-Authorization")) {
+        Ok(principal) => principal,
+        Err(e) => {
+            warn!(parent: &connect_span, error = %e, "Rejected CONNECT request");
+            return reject(&mut client_socket, &state.metrics, Rejection::ProxyAuthenticationRequired).await;
+        }
+    };
+
+    let keyring = state.keyring.get();
+    let signature = match keyring.current() {
+        Some(key) => match tokio::task::block_in_place(|| key.sign(format!("CONNECT request to {}", target).as_bytes())) {
+            Ok(signature) => Some((key.id(), signature)),
+            Err(e) => {
+                error!(parent: &connect_span, error = %e, "Failed to sign CONNECT request");
+                None
+            }
+        },
+        None => {
+            warn!(parent: &connect_span, "No active notary key, CONNECT request is not signed");
+            None
+        }
+    };
+    let transcript = state.transcript.session(session_id);
+    transcript.connect(
+        &target,
+        request.header("User-Agent").unwrap_or("-"),
+        signature.as_ref().map(|(key_id, signature)| (*key_id, signature.as_slice())),
+    );
+
+    let record = SessionRecord::new(session_id, principal.clone(), &target);
+    if let Err(e) = tokio::task::block_in_place(|| state.store.create_session(&record)) {
+        error!(parent: &connect_span, error = %format!("{:#}", e), "Failed to store session");
+        return reject(&mut client_socket, &state.metrics, Rejection::ServiceUnavailable).await;
     }
 
-    if let Some((target_host, target_port)) = parse_connect_request(&buffer[..n]) {
-        log_data(&format!("CONNECT request to {}:{}", target_host, target_port));
+    let target_addr = (request.host.as_str(), request.port);
+    let target_connect = timeout(config.target_connect_timeout, TcpStream::connect(target_addr));
+    let target_socket = match target_connect.instrument(connect_span.clone()).await {
+        Ok(Ok(target_socket)) => target_socket,
+        Ok(Err(e)) => {
+            warn!(parent: &connect_span, error = %e, "Failed to connect to target server");
+            close_session(state, session_id, None, Some(format!("Failed to connect to target server: {}", e)));
+            return reject(&mut client_socket, &state.metrics, Rejection::BadGateway).await;
+        }
+        Err(_) => {
+            warn!(parent: &connect_span, "Timed out connecting to target server");
+            close_session(state, session_id, None, Some("Timed out connecting to target server".to_string()));
+            return reject(&mut client_socket, &state.metrics, Rejection::GatewayTimeout).await;
+        }
+    };
+    info!(
+        parent: &connect_span,
+        principal = principal.as_deref(),
+        "Tunnel established"
+    );
+    state.metrics.connect(ConnectOutcome::Ok);
+
+    let mut response = format!("HTTP/1.{} 200 Connection established\r\n", request.version);
+    response.push_str(&format!("{}: {}\r\n", SESSION_ID_HEADER, session_id));
+    if let Some(principal) = &principal {
+        match state.authenticator.issue_session_token(principal, session_id) {
+            Ok(token) => response.push_str(&format!("{}: {}\r\n", SESSION_TOKEN_HEADER, token)),
+            // The client can still prove the session with its own credentials
+            Err(e) => error!(parent: &connect_span, error = %e, "Failed to issue session token"),
+        }
+    }
+    response.push_str("\r\n");
+    client_socket.write_all(response.as_bytes()).await?;
+
+    let budget = SessionBudget::new();
+    let close = state.tunnels.register(session_id);
+    let relay = tunnel(client_socket, target_socket, &request.pipelined, state, session_id, &budget, &transcript);
+    let result = tokio::select! {
+        result = relay => result,
+        _ = close.notified() => {
+            info!("Tunnel closed through the HTTP API");
+            Ok(())
+        }
+    };
+    state.tunnels.remove(session_id);
 
-        let signature = sign_data(
-            &private_key,
-            format!("CONNECT request to {}:{}", target_host, target_port).as_bytes(),
-        );
-        log_data(&format!("Signature: {:?}", signature));
-
-        let target_addr = format!("{}:{}", target_host, target_port);
-        if let Ok(mut target_socket) = TcpStream::connect(target_addr).await {
-            client_socket
-                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
-                .await?;
-
-            let (mut client_reader, mut client_writer) = client_socket.split();
-            let (mut target_reader, mut target_writer) = target_socket.split();
-
-            let data_log_clone1 = data_log.clone();
-            let data_log_clone2 = data_log.clone();
-
-            let client_to_target = log_and_copy(
-                &mut client_reader,
-                &mut target_writer,
-                Direction::ClientToServer,
-                data_log_clone1,
-            );
-            let target_to_client = log_and_copy(
-                &mut target_reader,
-                &mut client_writer,
-                Direction::ServerToClient,
-                data_log_clone2,
-            );
-
-            tokio::try_join!(client_to_target, target_to_client)?;
-        } else {
-            eprintln!("Failed to connect to target server");
+    let error = result.as_ref().err().map(|e| e.to_string());
+    close_session(state, session_id, Some(&budget), error);
+    result
+}
+
+/// Relays traffic between client and target until either side closes the tunnel.
+async fn tunnel<S>(
+    client_socket: S,
+    mut target_socket: TcpStream,
+    pipelined: &[u8],
+    state: &ProxyState,
+    session_id: &str,
+    budget: &SessionBudget,
+    transcript: &SessionTranscript,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let config = &state.config;
+
+    // Bytes pipelined behind the CONNECT header block belong to the tunnel
+    if !pipelined.is_empty() {
+        record_chunk(pipelined, Direction::ClientToServer, state, session_id, budget, transcript).await?;
+        target_socket.write_all(pipelined).await?;
+    }
+
+    let (mut client_reader, mut client_writer) = io::split(client_socket);
+    let (mut target_reader, mut target_writer) = target_socket.split();
+
+    let client_to_target = log_and_copy(
+        &mut client_reader,
+        &mut target_writer,
+        Direction::ClientToServer,
+        state,
+        session_id,
+        budget,
+        transcript,
+    );
+    let target_to_client = log_and_copy(
+        &mut target_reader,
+        &mut client_writer,
+        Direction::ServerToClient,
+        state,
+        session_id,
+        budget,
+        transcript,
+    );
+
+    let tunnel_span = info_span!("tunnel");
+    let tunnel = async { tokio::try_join!(client_to_target, target_to_client) };
+    match timeout(config.max_session_lifetime, tunnel).instrument(tunnel_span.clone()).await {
+        Ok(result) => {
+            result?;
         }
-    } else {
-        eprintln!("Invalid CONNECT request");
+        Err(_) => warn!(parent: &tunnel_span, "Session exceeded maximum lifetime, closing tunnel"),
     }
+    info!(
+        parent: &tunnel_span,
+        bytes_sent = budget.recorded(Direction::ClientToServer),
+        bytes_received = budget.recorded(Direction::ServerToClient),
+        "Tunnel closed"
+    );
 
     Ok(())
 }
 
+/// Records the end of a session's tunnel. The state is left alone if `/proof` or the HTTP API
+/// already moved the session on, which can happen before the proxy notices the tunnel closed.
+fn close_session(state: &ProxyState, session_id: &str, budget: Option<&SessionBudget>, error: Option<String>) {
+    let mut close = |session: &mut SessionRecord| {
+        session.closed_at.get_or_insert_with(Utc::now);
+        if let Some(budget) = budget {
+            session.bytes_sent = budget.recorded(Direction::ClientToServer) as u64;
+            session.bytes_received = budget.recorded(Direction::ServerToClient) as u64;
+        }
+        if session.state == SessionState::Open {
+            session.state = SessionState::Closed;
+            session.error = error.clone();
+        }
+    };
+    if let Err(e) = tokio::task::block_in_place(|| state.store.update_session(session_id, &mut close)) {
+        error!(error = %format!("{:#}", e), "Failed to close session");
+    }
+}
+
 async fn log_and_copy<R, W>(
     reader: &mut R,
     writer: &mut W,
     direction: Direction,
-    data_log: Arc<Mutex<Vec<(Direction, Vec<u8>)>>>,
+    state: &ProxyState,
+    session_id: &str,
+    budget: &SessionBudget,
+    transcript: &SessionTranscript,
 ) -> io::Result<()>
 where
     R: AsyncReadExt + Unpin,
     W: AsyncWriteExt + Unpin,
 {
+    let config = &state.config;
     let mut buffer = [0; 4096];
     loop {
-        let n = reader.read(&mut buffer).await?;
+        let n = match timeout(config.idle_timeout, reader.read(&mut buffer)).await {
+            Ok(n) => n?,
+            // The other direction may still be active, only give up once the whole tunnel is idle
+            Err(_) if budget.idle_for() < config.idle_timeout => continue,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "Tunnel idle timeout")),
+        };
         if n == 0 {
             break;
         }
+        budget.touch();
 
-        log_data(&format!(
-            "[{}] {}",
-            chrono::Utc::now().to_rfc3339(),
-            match direction {
-                Direction::ClientToServer => "Encrypted data sent to server:",
-                Direction::ServerToClient => "Encrypted data received from server:",
-            }
-        ));
-        log_data(&hex::encode(&buffer[..n]));
-
-        {
-            let mut data_log = data_log.lock().await;
-            data_log.push((direction, buffer[..n].to_vec()));
-        }
+        record_chunk(&buffer[..n], direction, state, session_id, budget, transcript).await?;
 
         writer.write_all(&buffer[..n]).await?;
     }
-    Ok(())
+    // Pass the half-close on, WebSocket clients wait for the close frame this sends
+    writer.shutdown().await
 }
 
-fn parse_connect_request(buffer: &[u8]) -> Option<(String, u16)> {
-    let request = String::from_utf8_lossy(buffer);
-    let re = regex::Regex::new(r"^CONNECT\s+([^\s:]+):(\d+)\s+HTTP/1\.1").unwrap();
-    if let Some(captures) = re.captures(&request) {
-        let host = captures.get(1).map_or("", |m| m.as_str()).to_string();
-        let port = captures.get(2).map_or("0", |m| m.as_str()).parse().unwrap_or(0);
-        return Some((host, port));
+/// Appends a chunk of tunnel traffic to the transcript, enforcing the session's size limit.
+async fn record_chunk(
+    data: &[u8],
+    direction: Direction,
+    state: &ProxyState,
+    session_id: &str,
+    budget: &SessionBudget,
+    transcript: &SessionTranscript,
+) -> io::Result<()> {
+    if !budget.record(direction, data.len(), state.config.max_transcript_bytes) {
+        return Err(io::Error::other("Session transcript limit exceeded"));
     }
-    None
-}
 
-fn log_data(data: &str) {
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(LOG_FILE)
-        .expect("Unable to open log file");
-    writeln!(file, "{}", data).expect("Unable to write to log file");
-}
+    transcript.data(direction, data);
+    state.metrics.relayed(direction, data.len());
 
-fn sign_data(private_key: &PKey<openssl::pkey::Private>, data: &[u8]) -> Vec<u8> {
-    let mut signer = Signer::new(MessageDigest::sha256(), private_key).expect("Failed to create signer");
-    signer.update(data).expect("Failed to update signer with data");
-    signer.sign_to_vec().expect("Failed to sign data")
+    state
+        .transcript_writer
+        .append(session_id, direction, data)
+        .await
+        .map_err(|e| io::Error::other(format!("Failed to store transcript: {:#}", e)))
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let private_key_pem = fs::read(PRIVATE_KEY_FILE).expect("Unable to read private key file");
-    let private_key = PKey::private_key_from_pem(&private_key_pem).expect("Failed to load private key");
-    let private_key = Arc::new(private_key);
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("hash-credential") {
+        return hash_credential(args.get(2).map(String::as_str));
+    }
 
-    let data_log = Arc::new(Mutex::new(Vec::<(Direction, Vec<u8>)>::new()));
-    let secrets = Arc::new(Mutex::new(None));
+    let config = ProxyConfig::from_env();
+    logging::init(&config.log_filter, config.log_secrets);
+    let keyring = ReloadableKeyring::load(&config.keyring_file).expect("Failed to load notary keyring");
 
-    let private_key_clone = private_key.clone();
-    let data_log_clone = data_log.clone();
-    let secrets_clone = secrets.clone();
+    let store: Option<Arc<dyn CredentialStore>> = match &config.credentials_file {
+        Some(path) => {
+            let store = FileCredentialStore::load(path).expect("Failed to load credentials file");
+            Some(Arc::new(store))
+        }
+        None => {
+            warn!("No credentials file configured, proxy authentication is disabled");
+            None
+        }
+    };
+    let authenticator = Authenticator::new(store, config.session_token_ttl);
+    let tls_acceptor = tls::acceptor(&config).expect("Failed to load TLS configuration");
+    let transcript = TranscriptLog::start(&config).expect("Failed to open transcript log");
+    let session_store: Arc<dyn SessionStore> = match &config.storage_file {
+        Some(path) => Arc::new(SqliteStore::open(path).expect("Failed to open session store")),
+        None => {
+            warn!("No session database configured, sessions are kept in memory only");
+            Arc::new(MemoryStore::new())
+        }
+    };
+    let transcript_writer =
+        TranscriptWriter::start(session_store.clone()).expect("Failed to start transcript writer");
+    if let Some(retention) = config.session_retention {
+        tokio::spawn(storage::prune_periodically(session_store.clone(), retention));
+    }
 
+    let sessions = Arc::new(Semaphore::new(config.max_concurrent_sessions));
+    let state = Arc::new(ProxyState {
+        config,
+        keyring,
+        store: session_store,
+        transcript_writer,
+        tunnels: ActiveTunnels::default(),
+        draining: AtomicBool::new(false),
+        metrics: Metrics::new(),
+        transcript,
+        authenticator,
+        tls_acceptor,
+    });
+
+    tokio::spawn(api::run_http_server(state.clone()));
+
+    let keyring_state = state.clone();
     tokio::spawn(async move {
-        run_http_server(private_key_clone, secrets_clone, data_log_clone).await;
+        keyring_state.keyring.watch(keyring_state.config.keyring_poll_interval).await;
     });
 
     let listener = TcpListener::bind(("0.0.0.0", LISTEN_PORT)).await?;
-    println!("Proxy server listening on port {}", LISTEN_PORT);
+    info!(port = LISTEN_PORT, tls = state.tls_acceptor.is_some(), "Proxy server listening");
 
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
     loop {
-        let (client_socket, _) = listener.accept().await?;
-        let private_key = private_key.clone();
-        let data_log = data_log.clone();
-
-        tokio::spawn(async move {
-            if let Err(e) = handle_client(client_socket, private_key, data_log).await {
-                eprintln!("Failed to handle client: {}", e);
+        let (client_socket, peer) = tokio::select! {
+            connection = listener.accept() => connection?,
+            _ = &mut shutdown => break,
+        };
+        let permit = sessions.clone().try_acquire_owned().ok();
+        let state = state.clone();
+
+        let session_id = session_id();
+        let session_span = info_span!("session", id = %session_id, %peer);
+        tokio::spawn(
+            async move {
+                if let Err(e) = serve_client(client_socket, permit, state, session_id).await {
+                    warn!(error = %e, "Failed to handle client");
+                }
             }
-        });
+            .instrument(session_span),
+        );
+    }
+
+    // Stop accepting tunnels and report not ready, the HTTP API keeps serving until the end
+    state.draining.store(true, Ordering::Relaxed);
+    drop(listener);
+    let open = state.tunnels.len();
+    info!(open_tunnels = open, timeout_secs = state.config.drain_timeout.as_secs(), "Draining");
+
+    drain(&state, &sessions).await;
+    if let Err(e) = state.transcript_writer.flush().await {
+        error!(error = %format!("{:#}", e), "Failed to store the remaining transcripts");
+    }
+
+    // Clients whose tunnel just closed may still be about to ask for a proof, a second signal
+    // skips the wait
+    let grace = state.config.shutdown_grace;
+    if !grace.is_zero() {
+        info!(grace_secs = grace.as_secs(), "Serving the HTTP API before shutting down");
+        let _ = timeout(grace, shutdown_signal()).await;
     }
+    info!("Shutting down");
+    Ok(())
 }
 
-async fn run_http_server(
-    private_key: Arc<PKey<openssl::pkey::Private>>,
-    secrets: Arc<Mutex<Option<SecretsPayload>>>,
-    data_log: Arc<Mutex<Vec<(Direction, Vec<u8>)>>>,
-) {
-    let make_svc = make_service_fn(|_conn| {
-        let secrets = secrets.clone();
-        let data_log = data_log.clone();
-
-        async {
-            Ok::<_, hyper::Error>(service_fn(move |req| {
-                let secrets = secrets.clone();
-                let data_log = data_log.clone();
-
-                async move {
-                    match (req.method(), req.uri().path()) {
-                        (&Method::POST, "/proof") => {
-                            let body_bytes = match hyper::body::to_bytes(req.into_body()).await {
-                                Ok(bytes) => bytes,
-                                Err(_) => {
-                                    return Ok::<_, hyper::Error>(
-                                        Response::builder()
-                                            .status(400)
-                                            .body(Body::from("Failed to read request body"))
-                                            .unwrap(),
-                                    );
-                                }
-                            };
-
-                            let proof_data: SecretsPayload = match serde_json::from_slice(&body_bytes) {
-                                Ok(data) => data,
-                                Err(_) => {
-                                    return Ok::<_, hyper::Error>(
-                                        Response::builder()
-                                            .status(400)
-                                            .body(Body::from("Invalid JSON"))
-                                            .unwrap(),
-                                    );
-                                }
-                            };
-
-                            // Log and store the received secrets
-                            println!("Received proof data: {:#?}", proof_data);
-
-                            // Attempt to decrypt the logged data
-                            {
-                                let data_log = data_log.lock().await;
-                                let decrypted_data = decrypt_data(&data_log, &proof_data);
-                                println!("Decrypted data: {:?}", decrypted_data);
-                            }
-
-                            Ok::<_, hyper::Error>(
-                                Response::new(Body::from("Proof data received")),
-                            )
-                        }
-                        _ => Ok::<_, hyper::Error>(
-                            Response::builder()
-                                .status(404)
-                                .body(Body::from("Not Found"))
-                                .unwrap(),
-                        ),
-                    }
-                }
-            }))
+/// Waits for the open sessions to end, at most `drain_timeout`. Returns whether they all did.
+async fn drain(state: &ProxyState, sessions: &Semaphore) -> bool {
+    // Every session holds a permit until it is done
+    let all_permits = u32::try_from(state.config.max_concurrent_sessions).unwrap_or(u32::MAX);
+    match timeout(state.config.drain_timeout, sessions.acquire_many(all_permits)).await {
+        Ok(_) => {
+            info!("All tunnels closed");
+            true
         }
-    });
+        Err(_) => {
+            warn!(open_tunnels = state.tunnels.len(), "Drain timeout reached");
+            false
+        }
+    }
+}
+
+/// Minimum length of an API key, which is only hashed with SHA-256.
+const MIN_API_KEY_LEN: usize = 32;
+
+/// Prints the credentials file hash of the password (`basic`, the default) or API key
+/// (`bearer`) read from stdin.
+fn hash_credential(kind: Option<&str>) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut secret = String::new();
+    std::io::stdin().read_line(&mut secret)?;
+    let secret = secret.trim_end_matches(['\r', '\n']);
+    if secret.is_empty() {
+        return Err(invalid("Expected the secret on stdin".to_string()));
+    }
+    match kind.unwrap_or("basic") {
+        "basic" => println!("{}", PasswordHash::new(secret).map_err(io::Error::other)?),
+        "bearer" if secret.len() < MIN_API_KEY_LEN => {
+            return Err(invalid(format!("API keys must be at least {} characters", MIN_API_KEY_LEN)));
+        }
+        "bearer" => println!("{}", ApiKeyDigest::of(secret)),
+        kind => return Err(invalid(format!("Unknown credential kind {:?}, expected basic or bearer", kind))),
+    }
+    Ok(())
+}
 
-    let addr = ([0, 0, 0, 0], 8080).into();
-    let server = Server::bind(&addr).serve(make_svc);
+/// Resolves on SIGTERM or Ctrl-C.
+async fn shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => Some(terminate),
+        Err(e) => {
+            warn!(error = %e, "Unable to listen for SIGTERM, only Ctrl-C shuts down gracefully");
+            None
+        }
+    };
+    tokio::select! {
+        Some(()) = async { terminate.as_mut()?.recv().await } => info!("Received SIGTERM"),
+        Ok(()) = tokio::signal::ctrl_c() => info!("Received Ctrl-C"),
+        else => std::future::pending().await,
+    }
+}
 
-    println!("HTTP server listening on port 8080");
-    if let Err(e) = server.await {
-        eprintln!("HTTP server error: {}", e);
+/// Random identifier correlating the log lines of one session.
+fn session_id() -> String {
+    let mut id = [0u8; 8];
+    match openssl::rand::rand_bytes(&mut id) {
+        Ok(()) => hex::encode(id),
+        Err(_) => String::from("unknown"),
     }
 }
 
+/// Decrypts the records the target sent in the last chunk of the transcript.
 fn decrypt_data(
     data_log: &[(Direction, Vec<u8>)],
     secrets: &SecretsPayload,
-) -> Result<Vec<String>> {
-    let mut decrypted_strings: Vec<String> = Vec::new();
-
+) -> Result<Vec<String>, DecryptError> {
     // Issue: Fix Generalization for RX Direction TLS Decryption
     //
     // TODO: Generalize for every chunk of data
     //
     // Do not use only last, use decrypt in all possible chunks of data
     //
-    if let Some((_direction, data)) = data_log.last() {
-        let hex_data: String = data.iter().map(|byte| format!("{:02x}", byte)).collect();
-
-        // Search for and split by the sequence '170303' in the hex data
-        let chunks: Vec<&str> = hex_data.split("170303").collect();
-
-        let mut sequence_number: u64 = 2;
-
-        let _tx_iv = decode(&secrets.tx_secret.iv).context("Failed to decode tx iv")?;
-        let _tx_key = decode(&secrets.tx_secret.key).context("Failed to decode tx key")?;
+    let Some((_direction, data)) = data_log.last() else {
+        warn!("data_log is empty.");
+        return Ok(Vec::new());
+    };
+    decrypt_records(data, secrets.get_rx_secret(), 2)
+}
 
-        let rx_iv = decode(&secrets.rx_secret.iv).context("Failed to decode rx iv")?;
-        let rx_key = decode(&secrets.rx_secret.key).context("Failed to decode rx key")?;
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        nid::Nid,
+        pkey::PKey,
+    };
+    use tokio::io::duplex;
+
+    use super::*;
+
+    /// Writes `contents` to a file in the temp directory that no other test uses.
+    pub(crate) fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("zap-{}-{}-{}", std::process::id(), n, name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
 
-        for chunk in chunks.iter().filter(|&&chunk| !chunk.is_empty()) {
-            if chunk.len() > 2 * 2 {  // Each byte is represented by 2 hex characters
-                let cyphertext_hex = &chunk[2 * 2..];
-                let aad_hex = format!("170303{}", &chunk[..2 * 2]);
+    /// PEM of a new P-256 notary key.
+    pub(crate) fn notary_key_pem() -> Vec<u8> {
+        let key = EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap();
+        PKey::from_ec_key(key).unwrap().private_key_to_pem_pkcs8().unwrap()
+    }
 
-                let aad = hex::decode(&aad_hex)
-                    .context("Failed to decode AAD hex to bytes")?;
-                let ciphertext = hex::decode(cyphertext_hex)
-                    .context("Failed to decode ciphertext hex to bytes")?;
+    /// Proxy state with the keyring at `keyring_file`, sessions in memory and no transcript log.
+    pub(crate) fn test_state(mut config: ProxyConfig, keyring_file: &Path) -> ProxyState {
+        config.transcript_log_file = None;
+        config.storage_file = None;
+        let store: Arc<dyn SessionStore> = Arc::new(MemoryStore::new());
+        ProxyState {
+            keyring: ReloadableKeyring::load(keyring_file).unwrap(),
+            transcript_writer: TranscriptWriter::start(store.clone()).unwrap(),
+            store,
+            tunnels: ActiveTunnels::default(),
+            draining: AtomicBool::new(false),
+            metrics: Metrics::new(),
+            transcript: TranscriptLog::start(&config).unwrap(),
+            authenticator: Authenticator::new(None, config.session_token_ttl),
+            tls_acceptor: None,
+            config,
+        }
+    }
 
-                let payload = Payload {
-                    msg: &ciphertext[..],
-                    aad: &aad[..],
-                };
+    fn state_with_key(config: ProxyConfig) -> ProxyState {
+        let key_file = temp_file("notary.pem", &notary_key_pem());
+        let state = test_state(config, &key_file);
+        std::fs::remove_file(key_file).unwrap();
+        state
+    }
 
-                let mut nonce = vec![0u8; 12];
-                let seq_num_bytes = sequence_number.to_be_bytes();
+    #[tokio::test]
+    async fn rejects_clients_over_the_session_limit() {
+        let state = state_with_key(ProxyConfig::default());
+        let (mut client, proxy_side) = duplex(1024);
 
-                nonce[4..12].copy_from_slice(&seq_num_bytes);
+        handle_client(proxy_side, None, &state, "0123abcd").await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", response);
+    }
 
-                for i in 0..12 {
-                    nonce[i] ^= rx_iv[i];
-                }
+    #[tokio::test]
+    async fn stops_relaying_once_the_transcript_budget_is_spent() {
+        let config = ProxyConfig { max_transcript_bytes: 10, ..ProxyConfig::default() };
+        let state = state_with_key(config);
+        state.store.create_session(&SessionRecord::new("0123abcd", None, "example.com:443")).unwrap();
+        let budget = SessionBudget::new();
+        let transcript = state.transcript.session("0123abcd");
+
+        // Read in two chunks of 6 bytes, the second one goes over the limit
+        let mut reader = (&b"012345"[..]).chain(&b"6789ab"[..]);
+        let mut relayed = Vec::new();
+        let direction = Direction::ClientToServer;
+        let result =
+            log_and_copy(&mut reader, &mut relayed, direction, &state, "0123abcd", &budget, &transcript).await;
+
+        assert_eq!(result.unwrap_err().to_string(), "Session transcript limit exceeded");
+        assert_eq!(relayed, b"012345");
+        state.transcript_writer.flush().await.unwrap();
+        assert_eq!(state.store.transcript("0123abcd").unwrap(), [(direction, b"012345".to_vec())]);
+    }
 
-                let nonce = Nonce::from_slice(&nonce);
-                let key = Key::<Aes256Gcm>::from_slice(&rx_key[..]);
+    #[tokio::test]
+    async fn closes_idle_tunnels() {
+        let config = ProxyConfig { idle_timeout: Duration::from_millis(50), ..ProxyConfig::default() };
+        let state = state_with_key(config);
+        let budget = SessionBudget::new();
+        let transcript = state.transcript.session("0123abcd");
+        // Neither side sends anything, the peer stays open
+        let (_peer, mut reader) = duplex(1024);
+
+        let started = Instant::now();
+        let mut relayed = Vec::new();
+        let result = log_and_copy(
+            &mut reader,
+            &mut relayed,
+            Direction::ServerToClient,
+            &state,
+            "0123abcd",
+            &budget,
+            &transcript,
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
 
-                let cipher = Aes256Gcm::new(&key);
-                let decrypted = cipher.decrypt(&nonce, payload).map_err(|e| anyhow::anyhow!("Decryption failed: {:?}", e))?;
+    #[tokio::test]
+    async fn drain_waits_for_sessions_to_release_their_permits() {
+        let config = ProxyConfig {
+            max_concurrent_sessions: 4,
+            drain_timeout: Duration::from_secs(5),
+            ..ProxyConfig::default()
+        };
+        let state = state_with_key(config);
+        let sessions = Arc::new(Semaphore::new(4));
+        let permits = sessions.clone().acquire_many_owned(2).await.unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(permits);
+        });
 
-                if let Ok(readable_text) = std::str::from_utf8(&decrypted) {
-                    decrypted_strings.push(readable_text.to_string());
-                } else {
-                    decrypted_strings.push("Decryption error".to_string());
-                }
+        let started = Instant::now();
+        assert!(drain(&state, &sessions).await);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
 
-                sequence_number += 1;
-            }
-        }
-    }  else {
-        println!("data_log is empty.");
+    #[tokio::test]
+    async fn drain_gives_up_after_the_timeout() {
+        let config = ProxyConfig {
+            max_concurrent_sessions: 4,
+            drain_timeout: Duration::from_millis(50),
+            ..ProxyConfig::default()
+        };
+        let state = state_with_key(config);
+        let sessions = Semaphore::new(4);
+        let _open_session = sessions.try_acquire().unwrap();
+
+        assert!(!drain(&state, &sessions).await);
     }
 
-    Ok(decrypted_strings)
+    #[test]
+    fn budget_counts_both_directions() {
+        let budget = SessionBudget::new();
+        assert!(budget.record(Direction::ClientToServer, 6, 10));
+        assert!(budget.record(Direction::ServerToClient, 4, 10));
+        assert!(!budget.record(Direction::ServerToClient, 1, 10));
+        assert_eq!(budget.recorded(Direction::ClientToServer), 6);
+        assert_eq!(budget.recorded(Direction::ServerToClient), 5);
+    }
 }
@@ -0,0 +1,242 @@
+//! Counters exposed on `/metrics` in the Prometheus text format.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use crate::Direction;
+
+/// Upper bounds in seconds of the latency histogram buckets.
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Cipher suite labels of `zap_decryption_failures_total`, the TLS 1.3 suites clients name in
+/// their secrets. Any other value is counted as `other`.
+const CIPHER_SUITE_LABELS: &[&str] = &["Aes128Gcm", "Aes256Gcm", "Chacha20Poly1305"];
+
+/// How a CONNECT request was answered.
+#[derive(Debug, Clone, Copy)]
+pub enum ConnectOutcome {
+    Ok,
+    /// The client was not allowed to open a tunnel.
+    PolicyDenied,
+    /// The target could not be reached or did not answer in time.
+    TargetUnreachable,
+    /// The request was malformed, too large or not sent in time.
+    ParseError,
+    /// The proxy was at its session limit or could not store the session.
+    Unavailable,
+}
+
+impl ConnectOutcome {
+    const ALL: [ConnectOutcome; 5] = [
+        ConnectOutcome::Ok,
+        ConnectOutcome::PolicyDenied,
+        ConnectOutcome::TargetUnreachable,
+        ConnectOutcome::ParseError,
+        ConnectOutcome::Unavailable,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ConnectOutcome::Ok => "ok",
+            ConnectOutcome::PolicyDenied => "policy_denied",
+            ConnectOutcome::TargetUnreachable => "target_unreachable",
+            ConnectOutcome::ParseError => "parse_error",
+            ConnectOutcome::Unavailable => "unavailable",
+        }
+    }
+}
+
+/// Cumulative latency histogram with the fixed `LATENCY_BUCKETS`.
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self { buckets: Default::default(), count: AtomicU64::new(0), sum_micros: AtomicU64::new(0) }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+pub struct Metrics {
+    connects: [AtomicU64; ConnectOutcome::ALL.len()],
+    /// Bytes relayed in each direction, indexed by `Direction`.
+    relayed_bytes: [AtomicU64; 2],
+    proof_duration: Histogram,
+    signing_duration: Histogram,
+    /// Keyed by a label of `CIPHER_SUITE_LABELS` or `other`.
+    decryption_failures: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            connects: Default::default(),
+            relayed_bytes: Default::default(),
+            proof_duration: Histogram::new(),
+            signing_duration: Histogram::new(),
+            decryption_failures: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn connect(&self, outcome: ConnectOutcome) {
+        self.connects[outcome as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn relayed(&self, direction: Direction, bytes: usize) {
+        self.relayed_bytes[direction as usize].fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn proof_completed(&self, duration: Duration) {
+        self.proof_duration.observe(duration);
+    }
+
+    pub fn attestation_signed(&self, duration: Duration) {
+        self.signing_duration.observe(duration);
+    }
+
+    /// Counts a transcript that could not be decrypted with secrets of `cipher_suite`, as sent
+    /// by the client.
+    pub fn decryption_failed(&self, cipher_suite: &str) {
+        let label = CIPHER_SUITE_LABELS.iter().find(|label| **label == cipher_suite).unwrap_or(&"other");
+        *self.decryption_failures.lock().unwrap().entry(label).or_default() += 1;
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self, active_tunnels: usize) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP zap_active_tunnels Tunnels currently open.\n");
+        out.push_str("# TYPE zap_active_tunnels gauge\n");
+        let _ = writeln!(out, "zap_active_tunnels {}", active_tunnels);
+
+        out.push_str("# HELP zap_connect_requests_total CONNECT requests by outcome.\n");
+        out.push_str("# TYPE zap_connect_requests_total counter\n");
+        for outcome in ConnectOutcome::ALL {
+            let count = self.connects[outcome as usize].load(Ordering::Relaxed);
+            let _ = writeln!(out, "zap_connect_requests_total{{outcome=\"{}\"}} {}", outcome.label(), count);
+        }
+
+        out.push_str("# HELP zap_relayed_bytes_total Tunnel bytes relayed by direction.\n");
+        out.push_str("# TYPE zap_relayed_bytes_total counter\n");
+        for (direction, label) in
+            [(Direction::ClientToServer, "client_to_server"), (Direction::ServerToClient, "server_to_client")]
+        {
+            let bytes = self.relayed_bytes[direction as usize].load(Ordering::Relaxed);
+            let _ = writeln!(out, "zap_relayed_bytes_total{{direction=\"{}\"}} {}", label, bytes);
+        }
+
+        self.proof_duration.render(&mut out, "zap_proof_duration_seconds", "Time taken to answer /proof.");
+        self.signing_duration.render(
+            &mut out,
+            "zap_signing_duration_seconds",
+            "Time taken to sign an attestation.",
+        );
+
+        out.push_str(
+            "# HELP zap_decryption_failures_total Transcripts that failed to decrypt by cipher suite.\n",
+        );
+        out.push_str("# TYPE zap_decryption_failures_total counter\n");
+        for (cipher_suite, count) in self.decryption_failures.lock().unwrap().iter() {
+            let _ =
+                writeln!(out, "zap_decryption_failures_total{{cipher_suite=\"{}\"}} {}", cipher_suite, count);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_cumulative_histograms() {
+        let metrics = Metrics::new();
+        metrics.connect(ConnectOutcome::Ok);
+        metrics.connect(ConnectOutcome::Ok);
+        metrics.connect(ConnectOutcome::PolicyDenied);
+        metrics.relayed(Direction::ServerToClient, 1500);
+        metrics.proof_completed(Duration::from_millis(3));
+        metrics.proof_completed(Duration::from_millis(40));
+        metrics.proof_completed(Duration::from_secs(30));
+
+        let out = metrics.render(2);
+        let lines: Vec<&str> = out.lines().collect();
+        for expected in [
+            "# TYPE zap_active_tunnels gauge",
+            "zap_active_tunnels 2",
+            "# TYPE zap_connect_requests_total counter",
+            "zap_connect_requests_total{outcome=\"ok\"} 2",
+            "zap_connect_requests_total{outcome=\"policy_denied\"} 1",
+            "zap_connect_requests_total{outcome=\"unavailable\"} 0",
+            "zap_relayed_bytes_total{direction=\"client_to_server\"} 0",
+            "zap_relayed_bytes_total{direction=\"server_to_client\"} 1500",
+            "# TYPE zap_proof_duration_seconds histogram",
+            "zap_proof_duration_seconds_bucket{le=\"0.005\"} 1",
+            "zap_proof_duration_seconds_bucket{le=\"0.025\"} 1",
+            "zap_proof_duration_seconds_bucket{le=\"0.05\"} 2",
+            "zap_proof_duration_seconds_bucket{le=\"10\"} 2",
+            "zap_proof_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "zap_proof_duration_seconds_sum 30.043",
+            "zap_proof_duration_seconds_count 3",
+            "zap_signing_duration_seconds_bucket{le=\"+Inf\"} 0",
+            "zap_signing_duration_seconds_count 0",
+            "# TYPE zap_decryption_failures_total counter",
+        ] {
+            assert!(lines.contains(&expected), "missing {:?} in:\n{}", expected, out);
+        }
+    }
+
+    #[test]
+    fn bounds_cipher_suite_labels() {
+        let metrics = Metrics::new();
+        metrics.decryption_failed("Aes256Gcm");
+        metrics.decryption_failed("Chacha20Poly1305");
+        metrics.decryption_failed("Aes256Gcm\"} 1\nzap_fake 1");
+        metrics.decryption_failed("Unknown(4865)");
+
+        let out = metrics.render(0);
+        let failures: Vec<&str> =
+            out.lines().filter(|line| line.starts_with("zap_decryption_failures_total{")).collect();
+        assert_eq!(
+            failures,
+            [
+                "zap_decryption_failures_total{cipher_suite=\"Aes256Gcm\"} 1",
+                "zap_decryption_failures_total{cipher_suite=\"Chacha20Poly1305\"} 1",
+                "zap_decryption_failures_total{cipher_suite=\"other\"} 2",
+            ]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Backends that hold notary private keys and produce signatures with them.
+//!
+//! The attestation path only sees [`Signer`], so the private key may live in a PEM file, a
+//! separate signing daemon ([`remote`]) or a hardware token ([`pkcs11`]).
+
+pub mod pkcs11;
+pub mod remote;
+
+use anyhow::Result;
+use openssl::{
+    hash::MessageDigest,
+    pkey::{PKey, Private, Public},
+    rsa::Padding,
+    sign::{RsaPssSaltlen, Signer as OpensslSigner},
+};
+
+use crate::keys::Algorithm;
+
+/// Produces signatures with a private key held by the backend.
+///
+/// Signatures use the encoding verifiers expect for the key's [`Algorithm`]: DER for ECDSA,
+/// raw bytes for RSA and Ed25519. Implementations may block, e.g. on a socket or a token.
+pub trait Signer: Send + Sync {
+    fn sign(&self, algorithm: Algorithm, data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Signs with a private key loaded from a PEM file.
+pub struct FileSigner {
+    private_key: PKey<Private>,
+}
+
+impl FileSigner {
+    pub fn from_pem(pem: &[u8]) -> Result<Self> {
+        Ok(Self { private_key: PKey::private_key_from_pem(pem)? })
+    }
+
+    pub fn public_key(&self) -> Result<PKey<Public>> {
+        Ok(PKey::public_key_from_der(&self.private_key.public_key_to_der()?)?)
+    }
+}
+
+impl Signer for FileSigner {
+    fn sign(&self, algorithm: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+        let mut signer = match algorithm {
+            Algorithm::Ed25519 => OpensslSigner::new_without_digest(&self.private_key)?,
+            _ => OpensslSigner::new(MessageDigest::sha256(), &self.private_key)?,
+        };
+        if algorithm == Algorithm::RsaPssSha256 {
+            signer.set_rsa_padding(Padding::PKCS1_PSS)?;
+            signer.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+            signer.set_rsa_mgf1_md(MessageDigest::sha256())?;
+        }
+        Ok(signer.sign_oneshot_to_vec(data)?)
+    }
+}
@@ -0,0 +1,455 @@
+//! Signing with a private key stored on a PKCS#11 token, such as an HSM or SoftHSM.
+//!
+//! Only the handful of Cryptoki calls needed to find a key and sign with it are bound here.
+//! The private key never leaves the token; its public half is read from a PEM file
+//! configured next to it, since tokens differ in how they expose public key objects.
+//!
+//! To try it locally with SoftHSM:
+//!
+//! ```text
+//! softhsm2-util --init-token --free --label zap --pin 1234 --so-pin 1234
+//! openssl genpkey -algorithm ed25519 -out notary.pem
+//! openssl pkey -in notary.pem -pubout -out notary.pub.pem
+//! softhsm2-util --import notary.pem --token zap --label notary --id 01 --pin 1234
+//! ```
+//!
+//! The signer is then tested against that token with
+//!
+//! ```text
+//! ZAP_TEST_PKCS11_MODULE=/usr/lib/softhsm/libsofthsm2.so ZAP_TEST_PKCS11_PUBLIC_KEY=notary.pub.pem \
+//!     cargo test -p proxy pkcs11 -- --ignored
+//! ```
+
+use std::{ffi::c_void, fmt, os::raw::c_ulong, path::PathBuf, ptr, sync::Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+use libloading::Library;
+use openssl::{bn::BigNum, ecdsa::EcdsaSig};
+use zeroize::Zeroizing;
+
+use super::Signer;
+use crate::keys::Algorithm;
+
+type CkUlong = c_ulong;
+type CkRv = CkUlong;
+type CkSlotId = CkUlong;
+type CkSessionHandle = CkUlong;
+type CkObjectHandle = CkUlong;
+
+const CKR_OK: CkRv = 0x000;
+const CKR_USER_ALREADY_LOGGED_IN: CkRv = 0x100;
+const CKR_CRYPTOKI_ALREADY_INITIALIZED: CkRv = 0x191;
+
+const CKF_OS_LOCKING_OK: CkUlong = 0x02;
+const CKF_SERIAL_SESSION: CkUlong = 0x04;
+const CKU_USER: CkUlong = 1;
+
+const CKA_CLASS: CkUlong = 0x000;
+const CKA_LABEL: CkUlong = 0x003;
+const CKO_PRIVATE_KEY: CkUlong = 0x003;
+
+const CKM_SHA256_RSA_PKCS: CkUlong = 0x040;
+const CKM_SHA256_RSA_PKCS_PSS: CkUlong = 0x043;
+const CKM_SHA256: CkUlong = 0x250;
+const CKM_ECDSA_SHA256: CkUlong = 0x1044;
+const CKM_EDDSA: CkUlong = 0x1057;
+const CKG_MGF1_SHA256: CkUlong = 0x02;
+const SHA256_LEN: CkUlong = 32;
+
+#[allow(dead_code)]
+#[repr(C)]
+struct CkVersion {
+    major: u8,
+    minor: u8,
+}
+
+#[repr(C)]
+struct CkInitializeArgs {
+    create_mutex: *const c_void,
+    destroy_mutex: *const c_void,
+    lock_mutex: *const c_void,
+    unlock_mutex: *const c_void,
+    flags: CkUlong,
+    reserved: *mut c_void,
+}
+
+// Filled in by the module, only the label is read
+#[allow(dead_code)]
+#[repr(C)]
+struct CkTokenInfo {
+    label: [u8; 32],
+    manufacturer_id: [u8; 32],
+    model: [u8; 16],
+    serial_number: [u8; 16],
+    flags: CkUlong,
+    max_session_count: CkUlong,
+    session_count: CkUlong,
+    max_rw_session_count: CkUlong,
+    rw_session_count: CkUlong,
+    max_pin_len: CkUlong,
+    min_pin_len: CkUlong,
+    total_public_memory: CkUlong,
+    free_public_memory: CkUlong,
+    total_private_memory: CkUlong,
+    free_private_memory: CkUlong,
+    hardware_version: CkVersion,
+    firmware_version: CkVersion,
+    utc_time: [u8; 16],
+}
+
+#[repr(C)]
+struct CkAttribute {
+    kind: CkUlong,
+    value: *const c_void,
+    value_len: CkUlong,
+}
+
+#[repr(C)]
+struct CkMechanism {
+    mechanism: CkUlong,
+    parameter: *const c_void,
+    parameter_len: CkUlong,
+}
+
+#[repr(C)]
+struct CkRsaPkcsPssParams {
+    hash_alg: CkUlong,
+    mgf: CkUlong,
+    salt_len: CkUlong,
+}
+
+type Unused = Option<unsafe extern "C" fn()>;
+
+/// Leading part of `CK_FUNCTION_LIST`, up to `C_Sign`. The entries are in the order of the
+/// PKCS#11 specification; the ones this module does not call are left untyped.
+#[allow(dead_code)]
+#[repr(C)]
+struct CkFunctionList {
+    version: CkVersion,
+    initialize: unsafe extern "C" fn(*mut c_void) -> CkRv,
+    _finalize: Unused,
+    _get_info: Unused,
+    _get_function_list: Unused,
+    get_slot_list: unsafe extern "C" fn(u8, *mut CkSlotId, *mut CkUlong) -> CkRv,
+    _get_slot_info: Unused,
+    get_token_info: unsafe extern "C" fn(CkSlotId, *mut CkTokenInfo) -> CkRv,
+    _get_mechanism_list: Unused,
+    _get_mechanism_info: Unused,
+    _init_token: Unused,
+    _init_pin: Unused,
+    _set_pin: Unused,
+    open_session:
+        unsafe extern "C" fn(CkSlotId, CkUlong, *mut c_void, *const c_void, *mut CkSessionHandle) -> CkRv,
+    close_session: unsafe extern "C" fn(CkSessionHandle) -> CkRv,
+    _close_all_sessions: Unused,
+    _get_session_info: Unused,
+    _get_operation_state: Unused,
+    _set_operation_state: Unused,
+    login: unsafe extern "C" fn(CkSessionHandle, CkUlong, *const u8, CkUlong) -> CkRv,
+    _logout: Unused,
+    _create_object: Unused,
+    _copy_object: Unused,
+    _destroy_object: Unused,
+    _get_object_size: Unused,
+    _get_attribute_value: Unused,
+    _set_attribute_value: Unused,
+    find_objects_init: unsafe extern "C" fn(CkSessionHandle, *const CkAttribute, CkUlong) -> CkRv,
+    find_objects: unsafe extern "C" fn(CkSessionHandle, *mut CkObjectHandle, CkUlong, *mut CkUlong) -> CkRv,
+    find_objects_final: unsafe extern "C" fn(CkSessionHandle) -> CkRv,
+    _encrypt_init: Unused,
+    _encrypt: Unused,
+    _encrypt_update: Unused,
+    _encrypt_final: Unused,
+    _decrypt_init: Unused,
+    _decrypt: Unused,
+    _decrypt_update: Unused,
+    _decrypt_final: Unused,
+    _digest_init: Unused,
+    _digest: Unused,
+    _digest_update: Unused,
+    _digest_key: Unused,
+    _digest_final: Unused,
+    sign_init: unsafe extern "C" fn(CkSessionHandle, *const CkMechanism, CkObjectHandle) -> CkRv,
+    sign: unsafe extern "C" fn(CkSessionHandle, *const u8, CkUlong, *mut u8, *mut CkUlong) -> CkRv,
+}
+
+type GetFunctionList = unsafe extern "C" fn(*mut *const CkFunctionList) -> CkRv;
+
+fn check(rv: CkRv, call: &str) -> Result<()> {
+    if rv != CKR_OK {
+        bail!("{} failed with CKR 0x{:x}", call, rv);
+    }
+    Ok(())
+}
+
+/// Where to find a key on a PKCS#11 token.
+pub struct Pkcs11KeyConfig {
+    /// Path of the PKCS#11 module, e.g. `/usr/lib/softhsm/libsofthsm2.so`.
+    pub module: PathBuf,
+    pub token_label: String,
+    pub key_label: String,
+    /// User PIN of the token, wiped once the config is dropped.
+    pub pin: Zeroizing<String>,
+}
+
+impl fmt::Debug for Pkcs11KeyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pkcs11KeyConfig")
+            .field("module", &self.module)
+            .field("token_label", &self.token_label)
+            .field("key_label", &self.key_label)
+            .field("pin", &"<redacted>")
+            .finish()
+    }
+}
+
+/// A private key on a PKCS#11 token, signing through one logged-in session.
+pub struct Pkcs11Signer {
+    functions: *const CkFunctionList,
+    session: Mutex<CkSessionHandle>,
+    key: CkObjectHandle,
+    // Keeps the module loaded while the function list is in use
+    _library: Library,
+}
+
+// Cryptoki sessions must not be used concurrently, every call on `session` holds its lock.
+// The module is initialized with `CKF_OS_LOCKING_OK`, so using it from any thread is allowed.
+unsafe impl Send for Pkcs11Signer {}
+unsafe impl Sync for Pkcs11Signer {}
+
+impl Pkcs11Signer {
+    pub fn open(config: &Pkcs11KeyConfig) -> Result<Self> {
+        // SAFETY: loading a PKCS#11 module runs its initializers, which is the point of
+        // configuring one. The function list stays valid while `library` is loaded.
+        unsafe {
+            let library = Library::new(&config.module)
+                .with_context(|| format!("Unable to load PKCS#11 module {}", config.module.display()))?;
+            let mut functions = ptr::null();
+            {
+                let get_function_list = library.get::<GetFunctionList>(b"C_GetFunctionList\0")?;
+                check(get_function_list(&mut functions), "C_GetFunctionList")?;
+            }
+            if functions.is_null() {
+                bail!("PKCS#11 module returned no function list");
+            }
+            let f = &*functions;
+
+            let mut args = CkInitializeArgs {
+                create_mutex: ptr::null(),
+                destroy_mutex: ptr::null(),
+                lock_mutex: ptr::null(),
+                unlock_mutex: ptr::null(),
+                flags: CKF_OS_LOCKING_OK,
+                reserved: ptr::null_mut(),
+            };
+            // Another keyring, e.g. the one being replaced on reload, may have initialized it
+            let rv = (f.initialize)(&mut args as *mut _ as *mut c_void);
+            if rv != CKR_CRYPTOKI_ALREADY_INITIALIZED {
+                check(rv, "C_Initialize")?;
+            }
+
+            let slot = find_slot(f, &config.token_label)?;
+            let mut session = 0;
+            check(
+                (f.open_session)(slot, CKF_SERIAL_SESSION, ptr::null_mut(), ptr::null(), &mut session),
+                "C_OpenSession",
+            )?;
+            // From here on, dropping `signer` closes the session
+            let mut signer = Self { functions, session: Mutex::new(session), key: 0, _library: library };
+
+            let rv = (f.login)(session, CKU_USER, config.pin.as_ptr(), config.pin.len() as CkUlong);
+            if rv != CKR_USER_ALREADY_LOGGED_IN {
+                check(rv, "C_Login")?;
+            }
+
+            signer.key = signer.find_private_key(&config.key_label)?;
+            Ok(signer)
+        }
+    }
+
+    unsafe fn find_private_key(&self, label: &str) -> Result<CkObjectHandle> {
+        let f = &*self.functions;
+        let session = *self.session.lock().unwrap_or_else(|e| e.into_inner());
+        let class = CKO_PRIVATE_KEY;
+        let template = [
+            CkAttribute {
+                kind: CKA_CLASS,
+                value: &class as *const _ as *const c_void,
+                value_len: std::mem::size_of::<CkUlong>() as CkUlong,
+            },
+            CkAttribute {
+                kind: CKA_LABEL,
+                value: label.as_ptr() as *const c_void,
+                value_len: label.len() as CkUlong,
+            },
+        ];
+
+        check(
+            (f.find_objects_init)(session, template.as_ptr(), template.len() as CkUlong),
+            "C_FindObjectsInit",
+        )?;
+        let mut objects = [0; 2];
+        let mut count = 0;
+        let rv = (f.find_objects)(session, objects.as_mut_ptr(), objects.len() as CkUlong, &mut count);
+        check((f.find_objects_final)(session), "C_FindObjectsFinal")?;
+        check(rv, "C_FindObjects")?;
+
+        match count {
+            0 => bail!("No private key labelled {:?} on the token", label),
+            1 => Ok(objects[0]),
+            _ => bail!("Several private keys are labelled {:?} on the token", label),
+        }
+    }
+}
+
+unsafe fn find_slot(f: &CkFunctionList, token_label: &str) -> Result<CkSlotId> {
+    let mut count = 0;
+    check((f.get_slot_list)(1, ptr::null_mut(), &mut count), "C_GetSlotList")?;
+    let mut slots = vec![0; count as usize];
+    check((f.get_slot_list)(1, slots.as_mut_ptr(), &mut count), "C_GetSlotList")?;
+    slots.truncate(count as usize);
+
+    for slot in slots {
+        let mut info = std::mem::zeroed::<CkTokenInfo>();
+        check((f.get_token_info)(slot, &mut info), "C_GetTokenInfo")?;
+        // Labels are padded with spaces to 32 bytes
+        if String::from_utf8_lossy(&info.label).trim_end() == token_label {
+            return Ok(slot);
+        }
+    }
+    Err(anyhow!("No token labelled {:?} found", token_label))
+}
+
+impl Signer for Pkcs11Signer {
+    fn sign(&self, algorithm: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+        let pss_params =
+            CkRsaPkcsPssParams { hash_alg: CKM_SHA256, mgf: CKG_MGF1_SHA256, salt_len: SHA256_LEN };
+        let mechanism = match algorithm {
+            Algorithm::RsaPkcs1Sha256 => {
+                CkMechanism { mechanism: CKM_SHA256_RSA_PKCS, parameter: ptr::null(), parameter_len: 0 }
+            }
+            Algorithm::RsaPssSha256 => CkMechanism {
+                mechanism: CKM_SHA256_RSA_PKCS_PSS,
+                parameter: &pss_params as *const _ as *const c_void,
+                parameter_len: std::mem::size_of::<CkRsaPkcsPssParams>() as CkUlong,
+            },
+            Algorithm::EcdsaP256Sha256 => {
+                CkMechanism { mechanism: CKM_ECDSA_SHA256, parameter: ptr::null(), parameter_len: 0 }
+            }
+            Algorithm::Ed25519 => {
+                CkMechanism { mechanism: CKM_EDDSA, parameter: ptr::null(), parameter_len: 0 }
+            }
+        };
+
+        let signature = {
+            let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+            // SAFETY: all pointers passed below outlive the calls, and the session is locked
+            unsafe {
+                let f = &*self.functions;
+                check((f.sign_init)(*session, &mechanism, self.key), "C_SignInit")?;
+                let mut len = 0;
+                check(
+                    (f.sign)(*session, data.as_ptr(), data.len() as CkUlong, ptr::null_mut(), &mut len),
+                    "C_Sign",
+                )?;
+                let mut signature = vec![0; len as usize];
+                check(
+                    (f.sign)(
+                        *session,
+                        data.as_ptr(),
+                        data.len() as CkUlong,
+                        signature.as_mut_ptr(),
+                        &mut len,
+                    ),
+                    "C_Sign",
+                )?;
+                signature.truncate(len as usize);
+                signature
+            }
+        };
+
+        match algorithm {
+            // Cryptoki returns ECDSA signatures as r || s, verifiers expect DER
+            Algorithm::EcdsaP256Sha256 => {
+                let (r, s) = signature.split_at(signature.len() / 2);
+                let signature =
+                    EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?;
+                Ok(signature.to_der()?)
+            }
+            _ => Ok(signature),
+        }
+    }
+}
+
+impl Drop for Pkcs11Signer {
+    fn drop(&mut self) {
+        // The module is not finalized: a reloaded keyring may still be using it
+        let session = *self.session.get_mut().unwrap_or_else(|e| e.into_inner());
+        unsafe {
+            ((*self.functions).close_session)(session);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use openssl::pkey::{Id, PKey};
+
+    use super::*;
+    use crate::keys::NotaryKey;
+
+    fn env_or(name: &str, default: &str) -> String {
+        env::var(name).unwrap_or_else(|_| default.to_string())
+    }
+
+    /// Signs through a SoftHSM token set up as in the module docs. The defaults match the
+    /// commands there, the module and public key paths must be given.
+    #[test]
+    #[ignore = "needs a SoftHSM token, see the module docs"]
+    fn signs_and_verifies_with_softhsm() {
+        let module = env::var("ZAP_TEST_PKCS11_MODULE").expect("ZAP_TEST_PKCS11_MODULE is not set");
+        let public_key =
+            env::var("ZAP_TEST_PKCS11_PUBLIC_KEY").expect("ZAP_TEST_PKCS11_PUBLIC_KEY is not set");
+        let public_key = PKey::public_key_from_pem(&fs::read(public_key).unwrap()).unwrap();
+        let config = Pkcs11KeyConfig {
+            module: PathBuf::from(module),
+            token_label: env_or("ZAP_TEST_PKCS11_TOKEN", "zap"),
+            key_label: env_or("ZAP_TEST_PKCS11_KEY", "notary"),
+            pin: Zeroizing::new(env_or("ZAP_TEST_PKCS11_PIN", "1234")),
+        };
+        assert!(!format!("{:?}", config).contains(config.pin.as_str()));
+
+        let mut algorithms = vec![None];
+        if public_key.id() == Id::RSA {
+            algorithms.push(Some(Algorithm::RsaPssSha256));
+        }
+        for algorithm in algorithms {
+            let signer = Pkcs11Signer::open(&config).unwrap();
+            // `NotaryKey::sign` verifies every signature against the public key
+            let key = NotaryKey::new(public_key.clone(), algorithm, Box::new(signer)).unwrap();
+            let signature = key.sign(b"attestation").unwrap();
+            assert!(!signature.is_empty());
+        }
+
+        // A second signer on the same token, as when the keyring is reloaded
+        let first = Pkcs11Signer::open(&config).unwrap();
+        let second = Pkcs11Signer::open(&config).unwrap();
+        drop(first);
+        let key = NotaryKey::new(public_key, None, Box::new(second)).unwrap();
+        key.sign(b"after reload").unwrap();
+    }
+
+    #[test]
+    fn reports_missing_module() {
+        let config = Pkcs11KeyConfig {
+            module: PathBuf::from("/nonexistent/libpkcs11.so"),
+            token_label: "zap".to_string(),
+            key_label: "notary".to_string(),
+            pin: Zeroizing::new("1234".to_string()),
+        };
+        let error = Pkcs11Signer::open(&config).err().expect("loading a missing module must fail");
+        assert!(format!("{:#}", error).contains("Unable to load PKCS#11 module"));
+    }
+}
@@ -0,0 +1,175 @@
+//! Signing through a separate signing daemon listening on a Unix socket.
+//!
+//! Each request opens a connection, writes one JSON object terminated by a newline and reads
+//! one JSON line back. Binary values are hex-encoded.
+//!
+//! ```text
+//! > {"method":"public_key","key":"notary-2026"}
+//! < {"public_key":"-----BEGIN PUBLIC KEY-----\n..."}
+//!
+//! > {"method":"sign","key":"notary-2026","algorithm":"Ed25519","data":"434f4e4e454354..."}
+//! < {"signature":"9a8f..."}
+//!
+//! < {"error":"unknown key"}
+//! ```
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use openssl::pkey::{PKey, Public};
+use serde::{Deserialize, Serialize};
+
+use super::Signer;
+use crate::keys::Algorithm;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Upper bound on a response line, generous for a PEM RSA-4096 key.
+const MAX_RESPONSE_BYTES: u64 = 64 * 1024;
+
+#[derive(Serialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum Request<'a> {
+    PublicKey { key: &'a str },
+    Sign { key: &'a str, algorithm: &'static str, data: String },
+}
+
+#[derive(Deserialize)]
+struct Response {
+    public_key: Option<String>,
+    signature: Option<String>,
+    error: Option<String>,
+}
+
+/// A key held by a signing daemon, addressed by its name on the daemon.
+pub struct RemoteSigner {
+    socket: PathBuf,
+    key: String,
+}
+
+impl RemoteSigner {
+    pub fn new(socket: &Path, key: &str) -> Self {
+        Self { socket: socket.to_path_buf(), key: key.to_string() }
+    }
+
+    pub fn public_key(&self) -> Result<PKey<Public>> {
+        let response = self.call(&Request::PublicKey { key: &self.key })?;
+        let pem = response.public_key.ok_or_else(|| anyhow!("Signer response has no public key"))?;
+        Ok(PKey::public_key_from_pem(pem.as_bytes())?)
+    }
+
+    fn call(&self, request: &Request) -> Result<Response> {
+        let mut stream = UnixStream::connect(&self.socket)
+            .with_context(|| format!("Unable to connect to signer at {}", self.socket.display()))?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        stream.write_all(&line)?;
+
+        let mut response = String::new();
+        BufReader::new(stream.take(MAX_RESPONSE_BYTES)).read_line(&mut response)?;
+        let response: Response = serde_json::from_str(&response).context("Invalid signer response")?;
+        if let Some(error) = response.error {
+            bail!("Signer rejected request for key {}: {}", self.key, error);
+        }
+        Ok(response)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn sign(&self, algorithm: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+        let request = Request::Sign { key: &self.key, algorithm: algorithm.name(), data: hex::encode(data) };
+        let response = self.call(&request)?;
+        let signature = response.signature.ok_or_else(|| anyhow!("Signer response has no signature"))?;
+        hex::decode(signature).context("Signer returned an invalid signature encoding")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::{pkey::Private, sign::Verifier};
+    use serde_json::{json, Value};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader as AsyncBufReader},
+        net::UnixListener,
+    };
+
+    use super::*;
+    use crate::signer::FileSigner;
+
+    /// Serves the signer protocol on a fresh socket, answering each request with `respond`.
+    fn fake_daemon(name: &str, respond: impl Fn(Value) -> Value + Send + 'static) -> PathBuf {
+        let socket = std::env::temp_dir().join(format!("zap-signer-{}-{}.sock", std::process::id(), name));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (reader, mut writer) = stream.into_split();
+                let mut line = String::new();
+                AsyncBufReader::new(reader).read_line(&mut line).await.unwrap();
+                let mut response = respond(serde_json::from_str(&line).unwrap()).to_string();
+                response.push('\n');
+                writer.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        socket
+    }
+
+    fn daemon_key(private_key: &PKey<Private>) -> impl Fn(Value) -> Value {
+        let pem = private_key.private_key_to_pem_pkcs8().unwrap();
+        let public_pem = String::from_utf8(private_key.public_key_to_pem().unwrap()).unwrap();
+        let signer = FileSigner::from_pem(&pem).unwrap();
+        move |request| {
+            if request["key"] != "notary-2026" {
+                return json!({ "error": "unknown key" });
+            }
+            match request["method"].as_str() {
+                Some("public_key") => json!({ "public_key": public_pem }),
+                Some("sign") => {
+                    assert_eq!(request["algorithm"], "Ed25519");
+                    let data = hex::decode(request["data"].as_str().unwrap()).unwrap();
+                    json!({ "signature": hex::encode(signer.sign(Algorithm::Ed25519, &data).unwrap()) })
+                }
+                _ => json!({ "error": "unknown method" }),
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signs_through_the_daemon() {
+        let private_key = PKey::generate_ed25519().unwrap();
+        let socket = fake_daemon("round-trip", daemon_key(&private_key));
+
+        let signer = RemoteSigner::new(&socket, "notary-2026");
+        let (public_key, signature) = tokio::task::spawn_blocking(move || {
+            (signer.public_key().unwrap(), signer.sign(Algorithm::Ed25519, b"attestation").unwrap())
+        })
+        .await
+        .unwrap();
+        std::fs::remove_file(&socket).unwrap();
+
+        assert!(public_key.public_eq(&private_key));
+        let mut verifier = Verifier::new_without_digest(&public_key).unwrap();
+        assert!(verifier.verify_oneshot(&signature, b"attestation").unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn surfaces_daemon_errors() {
+        let socket = fake_daemon("error", daemon_key(&PKey::generate_ed25519().unwrap()));
+
+        let signer = RemoteSigner::new(&socket, "notary-2025");
+        let error = tokio::task::spawn_blocking(move || signer.sign(Algorithm::Ed25519, b"attestation"))
+            .await
+            .unwrap()
+            .unwrap_err();
+        std::fs::remove_file(&socket).unwrap();
+
+        assert_eq!(error.to_string(), "Signer rejected request for key notary-2025: unknown key");
+    }
+}
@@ -0,0 +1,123 @@
+//! Session store kept in memory, for proxies running without a session database. Sessions
+//! are lost on restart.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use zap_types::Proof;
+
+use super::{SessionRecord, SessionState, SessionStore, TranscriptChunk};
+use crate::Direction;
+
+struct StoredSession {
+    record: SessionRecord,
+    transcript: Vec<(Direction, Vec<u8>)>,
+    attestation: Option<Proof>,
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<String, StoredSession>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn create_session(&self, session: &SessionRecord) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.contains_key(&session.id) {
+            bail!("Session {} already exists", session.id);
+        }
+        let stored = StoredSession { record: session.clone(), transcript: Vec::new(), attestation: None };
+        sessions.insert(session.id.clone(), stored);
+        Ok(())
+    }
+
+    fn update_session(
+        &self,
+        id: &str,
+        update: &mut dyn FnMut(&mut SessionRecord),
+    ) -> Result<Option<SessionRecord>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        Ok(sessions.get_mut(id).map(|stored| {
+            update(&mut stored.record);
+            stored.record.clone()
+        }))
+    }
+
+    fn session(&self, id: &str) -> Result<Option<SessionRecord>> {
+        Ok(self.sessions.lock().unwrap().get(id).map(|stored| stored.record.clone()))
+    }
+
+    fn sessions(&self, principal: Option<&str>, state: Option<SessionState>) -> Result<Vec<SessionRecord>> {
+        let mut sessions: Vec<SessionRecord> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|stored| &stored.record)
+            .filter(|session| session.is_visible_to(principal))
+            .filter(|session| state.is_none_or(|state| session.state == state))
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| session.created_at);
+        Ok(sessions)
+    }
+
+    fn latest_session(&self, principal: Option<&str>) -> Result<Option<SessionRecord>> {
+        let sessions = self.sessions.lock().unwrap();
+        let visible =
+            sessions.values().map(|stored| &stored.record).filter(|session| session.is_visible_to(principal));
+        Ok(visible.max_by_key(|session| session.created_at).cloned())
+    }
+
+    fn delete_session(&self, id: &str) -> Result<bool> {
+        Ok(self.sessions.lock().unwrap().remove(id).is_some())
+    }
+
+    fn prune(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, stored| {
+            stored.record.state == SessionState::Open || stored.record.created_at >= cutoff
+        });
+        Ok(before - sessions.len())
+    }
+
+    fn append_transcript(&self, chunks: &[TranscriptChunk]) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        for chunk in chunks {
+            if let Some(stored) = sessions.get_mut(&chunk.session_id) {
+                stored.transcript.push((chunk.direction, chunk.data.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn transcript(&self, id: &str) -> Result<Vec<(Direction, Vec<u8>)>> {
+        Ok(self.sessions.lock().unwrap().get(id).map(|stored| stored.transcript.clone()).unwrap_or_default())
+    }
+
+    fn put_attestation(&self, id: &str, attestation: &Proof) -> Result<()> {
+        match self.sessions.lock().unwrap().get_mut(id) {
+            Some(stored) => {
+                stored.attestation = Some(attestation.clone());
+                Ok(())
+            }
+            None => bail!("Unknown session {}", id),
+        }
+    }
+
+    fn attestation(&self, id: &str) -> Result<Option<Proof>> {
+        Ok(self.sessions.lock().unwrap().get(id).and_then(|stored| stored.attestation.clone()))
+    }
+}
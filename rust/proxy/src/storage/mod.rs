@@ -0,0 +1,342 @@
+//! Persistence of sessions, their ciphertext transcripts and the attestations issued for them.
+//!
+//! The proxy only sees [`SessionStore`]. Sessions are kept in an SQLite database by
+//! [`sqlite::SqliteStore`], or in memory by [`memory::MemoryStore`] when no database is
+//! configured. Tunnels hand their traffic to [`writer::TranscriptWriter`], which stores it in
+//! batches from its own thread.
+
+pub mod memory;
+pub mod sqlite;
+pub mod writer;
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use zap_types::Proof;
+
+use crate::Direction;
+
+/// How often sessions past their retention are deleted, at most.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    /// The tunnel is still open.
+    Open,
+    /// The tunnel is closed and no attestation has been issued yet.
+    Closed,
+    /// An attestation was issued for the session.
+    Attested,
+    /// The last attempt to attest the session failed, see `error`.
+    Failed,
+}
+
+impl SessionState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SessionState::Open => "open",
+            SessionState::Closed => "closed",
+            SessionState::Attested => "attested",
+            SessionState::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for SessionState {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "open" => Ok(SessionState::Open),
+            "closed" => Ok(SessionState::Closed),
+            "attested" => Ok(SessionState::Attested),
+            "failed" => Ok(SessionState::Failed),
+            _ => Err(format!("Unknown session state {:?}", value)),
+        }
+    }
+}
+
+/// A tunnel through the proxy, from CONNECT to attestation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub id: String,
+    /// The authenticated client, `None` when authentication is disabled.
+    pub principal: Option<String>,
+    pub target: String,
+    pub state: SessionState,
+    pub created_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    /// Bytes sent by the client to the target.
+    pub bytes_sent: u64,
+    /// Bytes sent by the target to the client.
+    pub bytes_received: u64,
+    /// The cipher suite of the target connection, known once the client asks for a proof.
+    #[serde(default)]
+    pub cipher_suite: Option<String>,
+    pub error: Option<String>,
+}
+
+impl SessionRecord {
+    pub fn new(id: &str, principal: Option<String>, target: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            principal,
+            target: target.to_string(),
+            state: SessionState::Open,
+            created_at: Utc::now(),
+            closed_at: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+            cipher_suite: None,
+            error: None,
+        }
+    }
+
+    /// Whether `principal`, as returned by the authenticator, may see this session. Sessions
+    /// opened while authentication was disabled are only visible while it still is.
+    pub fn is_visible_to(&self, principal: Option<&str>) -> bool {
+        self.principal.as_deref() == principal
+    }
+}
+
+/// A piece of tunnel traffic, as relayed in one read.
+#[derive(Debug, Clone)]
+pub struct TranscriptChunk {
+    pub session_id: String,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// Storage for sessions. Implementations may block on I/O.
+pub trait SessionStore: Send + Sync {
+    /// Verifies the backend can currently be written to, for readiness probes.
+    fn check(&self) -> Result<()>;
+
+    fn create_session(&self, session: &SessionRecord) -> Result<()>;
+
+    /// Applies `update` to the session atomically, returning the updated record or `None` if
+    /// there is no such session.
+    fn update_session(
+        &self,
+        id: &str,
+        update: &mut dyn FnMut(&mut SessionRecord),
+    ) -> Result<Option<SessionRecord>>;
+
+    fn session(&self, id: &str) -> Result<Option<SessionRecord>>;
+
+    /// Sessions visible to `principal`, oldest first, only those in `state` if set.
+    fn sessions(&self, principal: Option<&str>, state: Option<SessionState>) -> Result<Vec<SessionRecord>>;
+
+    /// The most recent session visible to `principal`.
+    fn latest_session(&self, principal: Option<&str>) -> Result<Option<SessionRecord>>;
+
+    /// Removes the session with its transcript and attestation, returning `false` if there is
+    /// no such session.
+    fn delete_session(&self, id: &str) -> Result<bool>;
+
+    /// Deletes the sessions created before `cutoff` whose tunnel is closed, returning how many
+    /// were deleted.
+    fn prune(&self, cutoff: DateTime<Utc>) -> Result<usize>;
+
+    /// Appends chunks of tunnel traffic, possibly of several sessions, in order. Chunks of
+    /// sessions that no longer exist are skipped.
+    fn append_transcript(&self, chunks: &[TranscriptChunk]) -> Result<()>;
+
+    fn transcript(&self, id: &str) -> Result<Vec<(Direction, Vec<u8>)>>;
+
+    fn put_attestation(&self, id: &str, attestation: &Proof) -> Result<()>;
+
+    fn attestation(&self, id: &str) -> Result<Option<Proof>>;
+}
+
+/// Deletes sessions once they are older than `retention`, for as long as the proxy runs.
+pub async fn prune_periodically(store: Arc<dyn SessionStore>, retention: Duration) {
+    let mut interval = tokio::time::interval(retention.min(PRUNE_INTERVAL));
+    loop {
+        interval.tick().await;
+        let Some(cutoff) =
+            chrono::Duration::from_std(retention).ok().and_then(|age| Utc::now().checked_sub_signed(age))
+        else {
+            continue;
+        };
+        let store = store.clone();
+        match tokio::task::spawn_blocking(move || store.prune(cutoff)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(count)) => info!(count, retention_secs = retention.as_secs(), "Deleted expired sessions"),
+            Ok(Err(e)) => error!(error = %format!("{:#}", e), "Failed to delete expired sessions"),
+            Err(e) => error!(error = %e, "Session pruning panicked"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{memory::MemoryStore, sqlite::SqliteStore, writer::TranscriptWriter, *};
+
+    fn stores() -> Vec<(&'static str, Arc<dyn SessionStore>)> {
+        vec![
+            ("memory", Arc::new(MemoryStore::new())),
+            ("sqlite", Arc::new(SqliteStore::open_in_memory().unwrap())),
+        ]
+    }
+
+    fn session(id: &str, principal: Option<&str>, age_secs: i64) -> SessionRecord {
+        let mut session = SessionRecord::new(id, principal.map(str::to_string), "example.com:443");
+        session.created_at = Utc::now() - chrono::Duration::seconds(age_secs);
+        session
+    }
+
+    fn chunk(session_id: &str, direction: Direction, data: &[u8]) -> TranscriptChunk {
+        TranscriptChunk { session_id: session_id.to_string(), direction, data: data.to_vec() }
+    }
+
+    fn ids(sessions: &[SessionRecord]) -> Vec<&str> {
+        sessions.iter().map(|session| session.id.as_str()).collect()
+    }
+
+    #[test]
+    fn updates_and_reads_sessions() {
+        for (name, store) in stores() {
+            store.check().unwrap();
+            store.create_session(&session("a", Some("alice"), 0)).unwrap();
+            assert!(store.create_session(&session("a", None, 0)).is_err(), "{}", name);
+
+            let updated = store
+                .update_session("a", &mut |session| {
+                    session.state = SessionState::Closed;
+                    session.bytes_sent = 42;
+                    session.cipher_suite = Some("TLS_AES_128_GCM_SHA256".to_string());
+                })
+                .unwrap()
+                .unwrap();
+            let stored = store.session("a").unwrap().unwrap();
+            assert_eq!(stored.state, SessionState::Closed, "{}", name);
+            assert_eq!(stored.bytes_sent, 42, "{}", name);
+            assert_eq!(stored.cipher_suite, updated.cipher_suite, "{}", name);
+            assert_eq!(stored.created_at, updated.created_at, "{}", name);
+
+            assert!(store.update_session("missing", &mut |_| {}).unwrap().is_none(), "{}", name);
+            assert!(store.session("missing").unwrap().is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn lists_sessions_by_principal_and_state() {
+        for (name, store) in stores() {
+            store.create_session(&session("old", Some("alice"), 30)).unwrap();
+            store.create_session(&session("shared", None, 20)).unwrap();
+            store.create_session(&session("bob", Some("bob"), 10)).unwrap();
+            store.update_session("shared", &mut |session| session.state = SessionState::Attested).unwrap();
+
+            assert_eq!(ids(&store.sessions(Some("alice"), None).unwrap()), ["old"], "{}", name);
+            assert_eq!(ids(&store.sessions(None, None).unwrap()), ["shared"], "{}", name);
+            assert_eq!(
+                ids(&store.sessions(None, Some(SessionState::Attested)).unwrap()),
+                ["shared"],
+                "{}",
+                name
+            );
+            assert_eq!(
+                ids(&store.sessions(Some("bob"), Some(SessionState::Open)).unwrap()),
+                ["bob"],
+                "{}",
+                name
+            );
+            assert_eq!(store.latest_session(Some("alice")).unwrap().unwrap().id, "old", "{}", name);
+            assert_eq!(store.latest_session(Some("bob")).unwrap().unwrap().id, "bob", "{}", name);
+            assert_eq!(store.latest_session(None).unwrap().unwrap().id, "shared", "{}", name);
+            // Sessions opened without authentication are not shared once it is enabled
+            assert!(store.latest_session(Some("carol")).unwrap().is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn keeps_transcripts_in_order_and_skips_deleted_sessions() {
+        for (name, store) in stores() {
+            store.create_session(&session("a", None, 0)).unwrap();
+            store.create_session(&session("b", None, 0)).unwrap();
+            store
+                .append_transcript(&[
+                    chunk("a", Direction::ClientToServer, b"hello"),
+                    chunk("b", Direction::ClientToServer, b"other"),
+                    chunk("gone", Direction::ClientToServer, b"lost"),
+                    chunk("a", Direction::ServerToClient, b"world"),
+                ])
+                .unwrap();
+
+            assert_eq!(
+                store.transcript("a").unwrap(),
+                [
+                    (Direction::ClientToServer, b"hello".to_vec()),
+                    (Direction::ServerToClient, b"world".to_vec())
+                ],
+                "{}",
+                name
+            );
+            assert!(store.transcript("gone").unwrap().is_empty(), "{}", name);
+
+            assert!(store.delete_session("a").unwrap(), "{}", name);
+            assert!(!store.delete_session("a").unwrap(), "{}", name);
+            assert!(store.transcript("a").unwrap().is_empty(), "{}", name);
+            assert_eq!(store.transcript("b").unwrap().len(), 1, "{}", name);
+        }
+    }
+
+    #[test]
+    fn prunes_closed_sessions_past_the_cutoff() {
+        for (name, store) in stores() {
+            store.create_session(&session("expired", None, 120)).unwrap();
+            store.create_session(&session("still-open", None, 120)).unwrap();
+            store.create_session(&session("recent", None, 0)).unwrap();
+            for id in ["expired", "recent"] {
+                store.update_session(id, &mut |session| session.state = SessionState::Closed).unwrap();
+            }
+            store.append_transcript(&[chunk("expired", Direction::ClientToServer, b"data")]).unwrap();
+
+            let cutoff = Utc::now() - chrono::Duration::seconds(60);
+            assert_eq!(store.prune(cutoff).unwrap(), 1, "{}", name);
+            assert!(store.session("expired").unwrap().is_none(), "{}", name);
+            assert!(store.transcript("expired").unwrap().is_empty(), "{}", name);
+            assert_eq!(ids(&store.sessions(None, None).unwrap()), ["still-open", "recent"], "{}", name);
+        }
+    }
+
+    #[test]
+    fn marks_sessions_left_open_as_closed_on_reopen() {
+        let dir = std::env::temp_dir().join(format!("zap-sqlite-{}", std::process::id()));
+        let path = dir.join("sessions.sqlite3");
+        {
+            let store = SqliteStore::open(&path).unwrap();
+            store.create_session(&session("a", None, 0)).unwrap();
+            store.append_transcript(&[chunk("a", Direction::ClientToServer, b"hello")]).unwrap();
+        }
+
+        let store = SqliteStore::open(&path).unwrap();
+        let session = store.session("a").unwrap().unwrap();
+        assert_eq!(session.state, SessionState::Closed);
+        assert!(session.error.is_some());
+        assert_eq!(store.transcript("a").unwrap().len(), 1);
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writer_stores_queued_chunks_before_flush_returns() {
+        let store: Arc<dyn SessionStore> = Arc::new(SqliteStore::open_in_memory().unwrap());
+        store.create_session(&session("a", None, 0)).unwrap();
+        let writer = TranscriptWriter::start(store.clone()).unwrap();
+
+        for i in 0..1000u32 {
+            writer.append("a", Direction::ClientToServer, &i.to_be_bytes()).await.unwrap();
+        }
+        writer.flush_session("a").await.unwrap();
+
+        let transcript = store.transcript("a").unwrap();
+        assert_eq!(transcript.len(), 1000);
+        assert!(transcript.iter().enumerate().all(|(i, (_, data))| data[..] == (i as u32).to_be_bytes()));
+    }
+}
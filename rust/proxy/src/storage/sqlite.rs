@@ -0,0 +1,284 @@
+//! Session store in an SQLite database:
+//!
+//! ```text
+//! sessions      one row per SessionRecord
+//! transcripts   ciphertext chunks of a session, in relay order
+//! attestations  the signed attestation of a session, once issued
+//! ```
+//!
+//! Transcripts and attestations are deleted along with their session.
+
+use std::{fs, path::Path, sync::Mutex, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use zap_types::Proof;
+
+use super::{SessionRecord, SessionState, SessionStore, TranscriptChunk};
+use crate::Direction;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS sessions (
+        id TEXT PRIMARY KEY,
+        principal TEXT,
+        target TEXT NOT NULL,
+        state TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        closed_at TEXT,
+        bytes_sent INTEGER NOT NULL,
+        bytes_received INTEGER NOT NULL,
+        cipher_suite TEXT,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS sessions_by_creation ON sessions (created_at);
+    CREATE TABLE IF NOT EXISTS transcripts (
+        id INTEGER PRIMARY KEY,
+        session_id TEXT NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        direction INTEGER NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS transcripts_by_session ON transcripts (session_id, id);
+    CREATE TABLE IF NOT EXISTS attestations (
+        session_id TEXT PRIMARY KEY REFERENCES sessions (id) ON DELETE CASCADE,
+        proof TEXT NOT NULL
+    );
+";
+
+const SESSION_COLUMNS: &str =
+    "id, principal, target, state, created_at, closed_at, bytes_sent, bytes_received, cipher_suite, error";
+
+/// Time a statement waits for another connection holding the database, e.g. a backup.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Parameters `?1` to `?10` in the order of `SESSION_COLUMNS`.
+macro_rules! session_params {
+    ($session:expr) => {
+        params![
+            $session.id,
+            $session.principal,
+            $session.target,
+            $session.state.as_str(),
+            $session.created_at,
+            $session.closed_at,
+            $session.bytes_sent,
+            $session.bytes_received,
+            $session.cipher_suite,
+            $session.error
+        ]
+    };
+}
+
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens the database, creating it if needed. Sessions left open by a previous run are
+    /// marked as closed, their transcripts end where the proxy stopped.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).with_context(|| format!("Unable to create {}", parent.display()))?;
+        }
+        let connection = Connection::open(path)
+            .with_context(|| format!("Unable to open session database {}", path.display()))?;
+        Self::init(connection)
+    }
+
+    /// A database that only lives as long as the store, for tests.
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self> {
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        // WAL lets the API read while the transcript writer appends
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        connection.execute_batch(SCHEMA).context("Unable to create the session tables")?;
+        connection.execute(
+            "UPDATE sessions SET state = ?1, error = ?2 WHERE state = ?3",
+            params![
+                SessionState::Closed.as_str(),
+                "Proxy stopped while the tunnel was open",
+                SessionState::Open.as_str()
+            ],
+        )?;
+        Ok(Self { connection: Mutex::new(connection) })
+    }
+}
+
+impl SessionStore for SqliteStore {
+    fn check(&self) -> Result<()> {
+        // Takes the write lock without changing anything
+        self.connection.lock().unwrap().execute_batch("BEGIN IMMEDIATE; ROLLBACK;")?;
+        Ok(())
+    }
+
+    fn create_session(&self, session: &SessionRecord) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(
+                &format!(
+                    "INSERT INTO sessions ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    SESSION_COLUMNS
+                ),
+                session_params!(session),
+            )
+            .with_context(|| format!("Unable to store session {}", session.id))?;
+        Ok(())
+    }
+
+    fn update_session(
+        &self,
+        id: &str,
+        update: &mut dyn FnMut(&mut SessionRecord),
+    ) -> Result<Option<SessionRecord>> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let Some(mut session) = select_session(&transaction, id)? else {
+            return Ok(None);
+        };
+        update(&mut session);
+        transaction.execute(
+            "UPDATE sessions SET principal = ?2, target = ?3, state = ?4, created_at = ?5, closed_at = ?6,
+                bytes_sent = ?7, bytes_received = ?8, cipher_suite = ?9, error = ?10
+             WHERE id = ?1",
+            session_params!(session),
+        )?;
+        transaction.commit()?;
+        Ok(Some(session))
+    }
+
+    fn session(&self, id: &str) -> Result<Option<SessionRecord>> {
+        select_session(&self.connection.lock().unwrap(), id)
+    }
+
+    fn sessions(&self, principal: Option<&str>, state: Option<SessionState>) -> Result<Vec<SessionRecord>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(&format!(
+            "SELECT {} FROM sessions
+             WHERE principal IS ?1 AND (?2 IS NULL OR state = ?2)
+             ORDER BY created_at",
+            SESSION_COLUMNS
+        ))?;
+        let sessions = statement
+            .query_map(params![principal, state.map(SessionState::as_str)], session_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(sessions)
+    }
+
+    fn latest_session(&self, principal: Option<&str>) -> Result<Option<SessionRecord>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(&format!(
+            "SELECT {} FROM sessions WHERE principal IS ?1
+             ORDER BY created_at DESC LIMIT 1",
+            SESSION_COLUMNS
+        ))?;
+        Ok(statement.query_row(params![principal], session_from_row).optional()?)
+    }
+
+    fn delete_session(&self, id: &str) -> Result<bool> {
+        let deleted =
+            self.connection.lock().unwrap().execute("DELETE FROM sessions WHERE id = ?1", params![id])?;
+        Ok(deleted > 0)
+    }
+
+    fn prune(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let deleted = self.connection.lock().unwrap().execute(
+            "DELETE FROM sessions WHERE state != ?1 AND created_at < ?2",
+            params![SessionState::Open.as_str(), cutoff],
+        )?;
+        Ok(deleted)
+    }
+
+    fn append_transcript(&self, chunks: &[TranscriptChunk]) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            // Sessions deleted through the API while their tunnel was open are skipped
+            let mut insert = transaction.prepare_cached(
+                "INSERT INTO transcripts (session_id, direction, data)
+                 SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM sessions WHERE id = ?1)",
+            )?;
+            for chunk in chunks {
+                insert.execute(params![chunk.session_id, chunk.direction as u8, chunk.data])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn transcript(&self, id: &str) -> Result<Vec<(Direction, Vec<u8>)>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare_cached("SELECT direction, data FROM transcripts WHERE session_id = ?1 ORDER BY id")?;
+        let mut rows = statement.query(params![id])?;
+        let mut records = Vec::new();
+        while let Some(row) = rows.next()? {
+            let direction = match row.get::<_, u8>(0)? {
+                0 => Direction::ClientToServer,
+                1 => Direction::ServerToClient,
+                other => bail!("Invalid direction {} in the transcript of session {}", other, id),
+            };
+            records.push((direction, row.get(1)?));
+        }
+        Ok(records)
+    }
+
+    fn put_attestation(&self, id: &str, attestation: &Proof) -> Result<()> {
+        let proof = serde_json::to_string(attestation)?;
+        let stored = self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO attestations (session_id, proof)
+             SELECT ?1, ?2 WHERE EXISTS (SELECT 1 FROM sessions WHERE id = ?1)",
+            params![id, proof],
+        )?;
+        if stored == 0 {
+            bail!("Unknown session {}", id);
+        }
+        Ok(())
+    }
+
+    fn attestation(&self, id: &str) -> Result<Option<Proof>> {
+        let connection = self.connection.lock().unwrap();
+        let proof: Option<String> = connection
+            .query_row("SELECT proof FROM attestations WHERE session_id = ?1", params![id], |row| row.get(0))
+            .optional()?;
+        match proof {
+            Some(proof) => {
+                let proof = serde_json::from_str(&proof)
+                    .with_context(|| format!("Invalid attestation stored for session {}", id))?;
+                Ok(Some(proof))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+fn select_session(connection: &Connection, id: &str) -> Result<Option<SessionRecord>> {
+    let mut statement =
+        connection.prepare_cached(&format!("SELECT {} FROM sessions WHERE id = ?1", SESSION_COLUMNS))?;
+    Ok(statement.query_row(params![id], session_from_row).optional()?)
+}
+
+/// Reads a row selected with `SESSION_COLUMNS`.
+fn session_from_row(row: &Row<'_>) -> rusqlite::Result<SessionRecord> {
+    let state: String = row.get(3)?;
+    let state = state
+        .parse()
+        .map_err(|e: String| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, e.into()))?;
+    Ok(SessionRecord {
+        id: row.get(0)?,
+        principal: row.get(1)?,
+        target: row.get(2)?,
+        state,
+        created_at: row.get(4)?,
+        closed_at: row.get(5)?,
+        bytes_sent: row.get(6)?,
+        bytes_received: row.get(7)?,
+        cipher_suite: row.get(8)?,
+        error: row.get(9)?,
+    })
+}
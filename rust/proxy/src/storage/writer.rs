@@ -0,0 +1,125 @@
+//! Stores tunnel traffic from a dedicated thread, so relaying never waits on the database.
+//!
+//! Tunnels queue chunks through a bounded channel, and wait for room when the writer falls
+//! behind rather than dropping traffic a proof depends on. The writer drains whatever is queued
+//! and appends it in a single [`SessionStore::append_transcript`] call.
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::sync::{
+    mpsc::{self, error::TryRecvError},
+    oneshot,
+};
+use tracing::error;
+
+use super::{SessionStore, TranscriptChunk};
+use crate::Direction;
+
+/// Chunks queued between the tunnels and the writer before tunnels wait.
+const CHANNEL_CAPACITY: usize = 1024;
+/// Chunks appended in one batch at most.
+const MAX_BATCH: usize = 256;
+
+enum Message {
+    Chunk(TranscriptChunk),
+    /// Answered once everything queued before it is stored.
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle to the transcript writer, shared by all tunnels and the HTTP API.
+pub struct TranscriptWriter {
+    sender: mpsc::Sender<Message>,
+    /// Sessions that lost traffic to a failed write, their transcripts can't be proven.
+    failed: Arc<Mutex<HashSet<String>>>,
+}
+
+impl TranscriptWriter {
+    pub fn start(store: Arc<dyn SessionStore>) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let failed = Arc::new(Mutex::new(HashSet::new()));
+        let writer_failed = failed.clone();
+        thread::Builder::new()
+            .name("transcript-writer".to_string())
+            .spawn(move || write_chunks(receiver, store.as_ref(), &writer_failed))
+            .context("Unable to start the transcript writer")?;
+        Ok(Self { sender, failed })
+    }
+
+    /// Queues a chunk of the session's traffic, failing once a previous chunk could not be
+    /// stored.
+    pub async fn append(&self, session_id: &str, direction: Direction, data: &[u8]) -> Result<()> {
+        self.ensure_complete(session_id)?;
+        let chunk = TranscriptChunk { session_id: session_id.to_string(), direction, data: data.to_vec() };
+        self.sender.send(Message::Chunk(chunk)).await.map_err(|_| anyhow!("Transcript writer stopped"))
+    }
+
+    /// Waits until everything queued so far is stored.
+    pub async fn flush(&self) -> Result<()> {
+        let (done, stored) = oneshot::channel();
+        self.sender.send(Message::Flush(done)).await.map_err(|_| anyhow!("Transcript writer stopped"))?;
+        stored.await.map_err(|_| anyhow!("Transcript writer stopped"))
+    }
+
+    /// Waits until the session's queued traffic is stored, failing if any of it was lost.
+    pub async fn flush_session(&self, session_id: &str) -> Result<()> {
+        self.flush().await?;
+        self.ensure_complete(session_id)
+    }
+
+    /// Drops what is known about a deleted session.
+    pub fn forget(&self, session_id: &str) {
+        self.failed.lock().unwrap().remove(session_id);
+    }
+
+    fn ensure_complete(&self, session_id: &str) -> Result<()> {
+        if self.failed.lock().unwrap().contains(session_id) {
+            bail!("Part of the transcript could not be stored");
+        }
+        Ok(())
+    }
+}
+
+fn write_chunks(
+    mut receiver: mpsc::Receiver<Message>,
+    store: &dyn SessionStore,
+    failed: &Mutex<HashSet<String>>,
+) {
+    let mut chunks = Vec::with_capacity(MAX_BATCH);
+    let mut flushes = Vec::new();
+    loop {
+        // Wait for the first message, then take what else is already queued
+        match receiver.blocking_recv() {
+            Some(message) => queue(message, &mut chunks, &mut flushes),
+            None => return,
+        }
+        while chunks.len() < MAX_BATCH {
+            match receiver.try_recv() {
+                Ok(message) => queue(message, &mut chunks, &mut flushes),
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => break,
+            }
+        }
+
+        if !chunks.is_empty() {
+            if let Err(e) = store.append_transcript(&chunks) {
+                error!(chunks = chunks.len(), error = %format!("{:#}", e), "Failed to store transcript");
+                failed.lock().unwrap().extend(chunks.iter().map(|chunk| chunk.session_id.clone()));
+            }
+            chunks.clear();
+        }
+        for done in flushes.drain(..) {
+            let _ = done.send(());
+        }
+    }
+}
+
+fn queue(message: Message, chunks: &mut Vec<TranscriptChunk>, flushes: &mut Vec<oneshot::Sender<()>>) {
+    match message {
+        Message::Chunk(chunk) => chunks.push(chunk),
+        Message::Flush(done) => flushes.push(done),
+    }
+}
@@ -0,0 +1,54 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use tokio_rustls::{
+    rustls::{
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        server::WebPkiClientVerifier,
+        RootCertStore, ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+use crate::config::ProxyConfig;
+
+/// Builds the TLS acceptor shared by the CONNECT listener and the HTTP API, if configured.
+///
+/// When a client CA is set, clients must present a certificate issued by it (mTLS).
+pub fn acceptor(config: &ProxyConfig) -> Result<Option<TlsAcceptor>> {
+    let (cert_file, key_file) = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert_file), Some(key_file)) => (cert_file, key_file),
+        (None, None) => return Ok(None),
+        _ => bail!("ZAP_TLS_CERT_FILE and ZAP_TLS_KEY_FILE must be set together"),
+    };
+
+    let certs = load_certs(cert_file)?;
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .with_context(|| format!("Failed to load TLS key from {}", key_file.display()))?;
+
+    let builder = ServerConfig::builder();
+    let builder = match &config.tls_client_ca_file {
+        Some(ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_file)? {
+                roots.add(cert).context("Invalid client CA certificate")?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots)).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let server_config = builder.with_single_cert(certs, key).context("Invalid TLS certificate or key")?;
+
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to load certificates from {}", path.display()))?;
+    if certs.is_empty() {
+        bail!("No certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
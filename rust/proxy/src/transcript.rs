@@ -0,0 +1,452 @@
+//! Transcript log of tunnel traffic, written as JSON lines by a dedicated thread.
+//!
+//! Sessions hand records to the writer through a bounded channel and never touch the file
+//! themselves. If the writer falls behind, records are dropped and a `dropped` record notes
+//! how many were lost.
+//!
+//! ```text
+//! {"session_id":"9f2c41d07a3be815","timestamp":"2026-10-19T05:49:30.120Z","type":"connect","target":"example.com:443","user_agent":"-","key_id":"3b1f...","signature":"30450221..."}
+//! {"session_id":"9f2c41d07a3be815","timestamp":"2026-10-19T05:49:30.131Z","type":"data","direction":"client_to_server","offset":0,"length":517,"payload":"160301..."}
+//! ```
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use tracing::warn;
+
+use crate::{config::ProxyConfig, Direction};
+
+/// Records buffered between the sessions and the writer before new ones are dropped.
+const CHANNEL_CAPACITY: usize = 4096;
+
+/// How tunnel payloads are written to the transcript log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadEncoding {
+    Hex,
+    Base64,
+    /// Only offsets and lengths are logged.
+    None,
+}
+
+impl FromStr for PayloadEncoding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "hex" => Ok(PayloadEncoding::Hex),
+            "base64" => Ok(PayloadEncoding::Base64),
+            "none" => Ok(PayloadEncoding::None),
+            _ => Err(format!("Unknown payload encoding {:?}", value)),
+        }
+    }
+}
+
+impl PayloadEncoding {
+    fn encode(self, data: &[u8]) -> Option<String> {
+        match self {
+            PayloadEncoding::Hex => Some(hex::encode(data)),
+            PayloadEncoding::Base64 => Some(BASE64.encode(data)),
+            PayloadEncoding::None => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TranscriptRecord {
+    session_id: String,
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    event: TranscriptEvent,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TranscriptEvent {
+    Connect {
+        target: String,
+        user_agent: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        key_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+    Data {
+        direction: Direction,
+        offset: u64,
+        length: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        payload: Option<String>,
+    },
+    Dropped {
+        count: u64,
+    },
+}
+
+/// Handle to the transcript writer, shared by all sessions.
+pub struct TranscriptLog {
+    sender: Option<mpsc::Sender<TranscriptRecord>>,
+    payload_encoding: PayloadEncoding,
+    dropped: Arc<AtomicU64>,
+}
+
+impl TranscriptLog {
+    /// Opens the log file and starts the writer thread. Logging is disabled when no file is
+    /// configured.
+    pub fn start(config: &ProxyConfig) -> Result<Self> {
+        let dropped = Arc::new(AtomicU64::new(0));
+        let Some(path) = &config.transcript_log_file else {
+            return Ok(Self { sender: None, payload_encoding: config.transcript_log_payload, dropped });
+        };
+
+        let file = RotatingFile::open(
+            path,
+            config.transcript_log_max_bytes,
+            config.transcript_log_max_age,
+            config.transcript_log_keep,
+        )?;
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let writer_dropped = dropped.clone();
+        thread::Builder::new()
+            .name("transcript-log".to_string())
+            .spawn(move || write_records(receiver, file, &writer_dropped))
+            .context("Unable to start the transcript log writer")?;
+
+        Ok(Self { sender: Some(sender), payload_encoding: config.transcript_log_payload, dropped })
+    }
+
+    pub fn session(&self, session_id: &str) -> SessionTranscript {
+        SessionTranscript {
+            sender: self.sender.clone(),
+            payload_encoding: self.payload_encoding,
+            dropped: self.dropped.clone(),
+            session_id: session_id.to_string(),
+            offsets: [AtomicU64::new(0), AtomicU64::new(0)],
+        }
+    }
+}
+
+/// Records the events of a single session, tracking the stream offset of each direction.
+pub struct SessionTranscript {
+    sender: Option<mpsc::Sender<TranscriptRecord>>,
+    payload_encoding: PayloadEncoding,
+    dropped: Arc<AtomicU64>,
+    session_id: String,
+    offsets: [AtomicU64; 2],
+}
+
+impl SessionTranscript {
+    pub fn connect(&self, target: &str, user_agent: &str, signature: Option<(&str, &[u8])>) {
+        self.send(TranscriptEvent::Connect {
+            target: target.to_string(),
+            user_agent: user_agent.to_string(),
+            key_id: signature.map(|(key_id, _)| key_id.to_string()),
+            signature: signature.map(|(_, signature)| hex::encode(signature)),
+        });
+    }
+
+    pub fn data(&self, direction: Direction, data: &[u8]) {
+        let offset = self.offsets[direction as usize].fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.send(TranscriptEvent::Data {
+            direction,
+            offset,
+            length: data.len(),
+            payload: self.payload_encoding.encode(data),
+        });
+    }
+
+    fn send(&self, event: TranscriptEvent) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let record = TranscriptRecord { session_id: self.session_id.clone(), timestamp: Utc::now(), event };
+        if sender.try_send(record).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn write_records(
+    mut receiver: mpsc::Receiver<TranscriptRecord>,
+    mut file: RotatingFile,
+    dropped: &AtomicU64,
+) {
+    loop {
+        let record = match receiver.try_recv() {
+            Ok(record) => record,
+            Err(TryRecvError::Empty) => {
+                // Idle, make what was written so far visible before waiting
+                if let Err(e) = file.flush() {
+                    warn!(error = %e, "Failed to flush transcript log");
+                }
+                match receiver.blocking_recv() {
+                    Some(record) => record,
+                    None => return,
+                }
+            }
+            Err(TryRecvError::Disconnected) => return,
+        };
+
+        let lost = dropped.swap(0, Ordering::Relaxed);
+        if lost > 0 {
+            warn!(count = lost, "Transcript log fell behind, records were dropped");
+            let record = TranscriptRecord {
+                session_id: "-".to_string(),
+                timestamp: Utc::now(),
+                event: TranscriptEvent::Dropped { count: lost },
+            };
+            write_record(&mut file, &record);
+        }
+        write_record(&mut file, &record);
+    }
+}
+
+fn write_record(file: &mut RotatingFile, record: &TranscriptRecord) {
+    let mut line = match serde_json::to_vec(record) {
+        Ok(line) => line,
+        Err(e) => {
+            warn!(error = %e, "Failed to serialize transcript record");
+            return;
+        }
+    };
+    line.push(b'\n');
+    if let Err(e) = file.write_line(&line) {
+        warn!(error = %e, "Failed to write transcript log");
+    }
+}
+
+/// Timestamp in the names of rotated files, sorts lexically.
+const ROTATED_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// Append-only file rotated once it reaches `max_bytes` or `max_age`. Rotated files are
+/// renamed to `<stem>.<timestamp>.<extension>` and only the newest `keep` are retained.
+struct RotatingFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    written: u64,
+    opened_at: Instant,
+    max_bytes: u64,
+    max_age: Duration,
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_bytes: u64, max_age: Duration, keep: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Unable to open transcript log {}", path.display()))?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: BufWriter::new(file),
+            written,
+            opened_at: Instant::now(),
+            max_bytes,
+            max_age,
+            keep,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let full = self.written > 0 && self.written + line.len() as u64 > self.max_bytes;
+        if full || self.opened_at.elapsed() >= self.max_age {
+            if let Err(e) = self.rotate() {
+                warn!(error = %e, "Failed to rotate transcript log, appending to the current file");
+            }
+        }
+
+        self.file.write_all(line)?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let rotated = self.rotated_path(&Utc::now().format(ROTATED_TIMESTAMP_FORMAT).to_string());
+        fs::rename(&self.path, rotated)?;
+
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.file = BufWriter::new(file);
+        self.written = 0;
+        self.opened_at = Instant::now();
+        self.prune()
+    }
+
+    fn rotated_path(&self, suffix: &str) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(extension) => format!("{}.{}.{}", stem, suffix, extension.to_string_lossy()),
+            None => format!("{}.{}", stem, suffix),
+        };
+        self.path.with_file_name(name)
+    }
+
+    /// Whether `name` is the name of a file rotated from this one, as written by `rotate`.
+    /// Other files sharing the stem, such as `proxy.old.log`, are left alone.
+    fn is_rotated_name(&self, name: &str) -> bool {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let Some(rest) = name.strip_prefix(stem.as_ref()).and_then(|rest| rest.strip_prefix('.')) else {
+            return false;
+        };
+        let timestamp = match self.path.extension() {
+            Some(extension) => {
+                let suffix = format!(".{}", extension.to_string_lossy());
+                match rest.strip_suffix(suffix.as_str()) {
+                    Some(timestamp) => timestamp,
+                    None => return false,
+                }
+            }
+            None => rest,
+        };
+        NaiveDateTime::parse_from_str(timestamp, ROTATED_TIMESTAMP_FORMAT)
+            .is_ok_and(|time| time.format(ROTATED_TIMESTAMP_FORMAT).to_string() == timestamp)
+    }
+
+    /// Deletes the oldest rotated files beyond `keep`. Timestamps sort lexically.
+    fn prune(&self) -> io::Result<()> {
+        let directory = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        let mut rotated: Vec<PathBuf> = fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name().and_then(|name| name.to_str()).is_some_and(|name| self.is_rotated_name(name))
+            })
+            .collect();
+        rotated.sort();
+
+        let excess = rotated.len().saturating_sub(self.keep);
+        for path in &rotated[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    /// An empty directory no other test uses.
+    fn temp_dir() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("zap-transcript-{}-{}", std::process::id(), n));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Waits past the current millisecond, so the next rotation gets a new name.
+    fn next_timestamp() {
+        thread::sleep(Duration::from_millis(2));
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let dir = temp_dir();
+        let path = dir.join("proxy.log");
+        let mut file = RotatingFile::open(&path, 20, Duration::from_secs(3600), 5).unwrap();
+        for line in ["first line\n", "second line\n", "third line\n"] {
+            file.write_line(line.as_bytes()).unwrap();
+            next_timestamp();
+        }
+        file.flush().unwrap();
+
+        let names = names(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert!(names.iter().all(|name| name == "proxy.log" || file.is_rotated_name(name)), "{:?}", names);
+        assert_eq!(file.written, "third line\n".len() as u64);
+    }
+
+    #[test]
+    fn rotates_by_age() {
+        let dir = temp_dir();
+        let path = dir.join("proxy.log");
+        let mut file = RotatingFile::open(&path, u64::MAX, Duration::from_millis(50), 5).unwrap();
+        file.write_line(b"old\n").unwrap();
+        file.write_line(b"still young\n").unwrap();
+        thread::sleep(Duration::from_millis(60));
+        file.write_line(b"new\n").unwrap();
+        file.flush().unwrap();
+
+        let current = fs::read_to_string(&path).unwrap();
+        let names = names(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(current, "new\n");
+        assert_eq!(names.len(), 2, "{:?}", names);
+    }
+
+    #[test]
+    fn prunes_only_rotated_files_beyond_keep() {
+        let dir = temp_dir();
+        let unrelated = [
+            "proxy.old.log",
+            "proxy.log.bak",
+            "proxy.20260101T000000.000Z.txt",
+            "proxy.20260101T000000Z.log",
+            "proxyx.20260101T000000.000Z.log",
+        ];
+        let rotated = [
+            "proxy.20260101T000000.000Z.log",
+            "proxy.20260102T000000.000Z.log",
+            "proxy.20260103T000000.000Z.log",
+        ];
+        for name in unrelated.iter().chain(&rotated) {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let file = RotatingFile::open(&dir.join("proxy.log"), 1024, Duration::from_secs(3600), 1).unwrap();
+        file.prune().unwrap();
+
+        let names = names(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let mut expected: Vec<&str> = unrelated.to_vec();
+        expected.extend(["proxy.20260103T000000.000Z.log", "proxy.log"]);
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn matches_rotated_names_without_extension() {
+        let dir = temp_dir();
+        let file = RotatingFile::open(&dir.join("transcript"), 1024, Duration::from_secs(3600), 1).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(file.is_rotated_name("transcript.20260101T000000.000Z"));
+        assert!(!file.is_rotated_name("transcript.old"));
+        assert!(!file.is_rotated_name("transcript"));
+    }
+}
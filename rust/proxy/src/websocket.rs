@@ -0,0 +1,500 @@
+//! Server side of RFC 6455 WebSockets, so browser clients can reach the proxy.
+//!
+//! After the upgrade, [`WebSocketStream`] turns the WebSocket into a byte stream: the payloads
+//! of text and binary messages are read in order and everything written is sent as binary
+//! messages. The CONNECT request, its response and the tunnel then run over that stream exactly
+//! as over TCP, so clients still do the TLS handshake with the target themselves.
+//!
+//! The framing is implemented here rather than with tokio-tungstenite: the upgrade arrives on
+//! the CONNECT listener, already parsed along with any pipelined bytes, and the tunnel needs a
+//! byte stream rather than messages, which tungstenite would only provide behind an adapter.
+
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use tokio::io::{self, AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use crate::connect::UpgradeRequest;
+
+/// Appended to the client's key before hashing, as defined by RFC 6455.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const READ_CHUNK_SIZE: usize = 4096;
+/// Control frames may not carry more than this.
+const MAX_CONTROL_PAYLOAD: u64 = 125;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Status sent when closing the connection normally.
+const CLOSE_NORMAL: u16 = 1000;
+
+/// Answers the upgrade request and returns the WebSocket as a byte stream.
+pub async fn accept<S>(mut stream: S, upgrade: UpgradeRequest) -> io::Result<WebSocketStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&upgrade.key)
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(WebSocketStream::new(stream, upgrade.pipelined))
+}
+
+fn accept_key(key: &str) -> String {
+    BASE64.encode(openssl::sha::sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()))
+}
+
+/// Header of a frame sent by the client.
+struct FrameHeader {
+    opcode: u8,
+    payload_len: u64,
+    mask: [u8; 4],
+    /// Size of the header itself.
+    len: usize,
+}
+
+/// Parses a frame header, returning `None` if `buffer` does not hold all of it yet.
+fn parse_frame_header(buffer: &[u8]) -> io::Result<Option<FrameHeader>> {
+    let [first, second, ..] = *buffer else {
+        return Ok(None);
+    };
+    if first & 0x70 != 0 {
+        return Err(invalid_data("WebSocket frame uses reserved bits"));
+    }
+    // Clients must mask every frame they send
+    if second & 0x80 == 0 {
+        return Err(invalid_data("Unmasked WebSocket frame from client"));
+    }
+
+    let (payload_len, mut len) = match second & 0x7f {
+        126 => match buffer.get(2..4) {
+            Some(bytes) => (u16::from_be_bytes([bytes[0], bytes[1]]) as u64, 4),
+            None => return Ok(None),
+        },
+        127 => match buffer.get(2..10) {
+            Some(bytes) => (u64::from_be_bytes(bytes.try_into().unwrap()), 10),
+            None => return Ok(None),
+        },
+        short => (short as u64, 2),
+    };
+    let Some(mask) = buffer.get(len..len + 4) else {
+        return Ok(None);
+    };
+    let mask = mask.try_into().unwrap();
+    len += 4;
+
+    Ok(Some(FrameHeader { opcode: first & 0x0f, payload_len, mask, len }))
+}
+
+/// Appends a final, unmasked frame as sent by the server.
+fn encode_frame(buffer: &mut Vec<u8>, opcode: u8, payload: &[u8]) {
+    buffer.push(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => buffer.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            buffer.push(126);
+            buffer.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            buffer.push(127);
+            buffer.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    buffer.extend_from_slice(payload);
+}
+
+fn unmask(data: &mut [u8], mask: [u8; 4], offset: u64) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[((offset + i as u64) % 4) as usize];
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// The data frame currently being read.
+struct DataFrame {
+    remaining: u64,
+    mask: [u8; 4],
+    /// Payload bytes already read, for the position in the mask.
+    offset: u64,
+}
+
+/// A WebSocket read and written as a plain byte stream, see the module documentation.
+pub struct WebSocketStream<S> {
+    inner: S,
+    /// Bytes read from `inner` and not parsed yet.
+    read_buffer: Vec<u8>,
+    frame: Option<DataFrame>,
+    /// Frames not written to `inner` yet, including replies to pings.
+    write_buffer: Vec<u8>,
+    /// The client sent a close frame, reads return end of file.
+    close_received: bool,
+    close_sent: bool,
+}
+
+impl<S> WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn new(inner: S, pipelined: Vec<u8>) -> Self {
+        Self {
+            inner,
+            read_buffer: pipelined,
+            frame: None,
+            write_buffer: Vec::new(),
+            close_received: false,
+            close_sent: false,
+        }
+    }
+
+    fn send_close(&mut self, status: u16) {
+        if !self.close_sent {
+            encode_frame(&mut self.write_buffer, OPCODE_CLOSE, &status.to_be_bytes());
+            self.close_sent = true;
+        }
+    }
+
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_buffer.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.write_buffer))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_buffer.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Handles the next frame header in `read_buffer`, returning `false` if more bytes are
+    /// needed first.
+    fn process_frame(&mut self) -> io::Result<bool> {
+        let Some(header) = parse_frame_header(&self.read_buffer)? else {
+            return Ok(false);
+        };
+
+        match header.opcode {
+            OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY => {
+                self.read_buffer.drain(..header.len);
+                self.frame = Some(DataFrame { remaining: header.payload_len, mask: header.mask, offset: 0 });
+                Ok(true)
+            }
+            OPCODE_CLOSE | OPCODE_PING | OPCODE_PONG => {
+                if header.payload_len > MAX_CONTROL_PAYLOAD {
+                    return Err(invalid_data("WebSocket control frame too large"));
+                }
+                let end = header.len + header.payload_len as usize;
+                if self.read_buffer.len() < end {
+                    return Ok(false);
+                }
+                let mut payload: Vec<u8> = self.read_buffer.drain(..end).skip(header.len).collect();
+                unmask(&mut payload, header.mask, 0);
+
+                match header.opcode {
+                    OPCODE_PING if !self.close_sent => {
+                        encode_frame(&mut self.write_buffer, OPCODE_PONG, &payload)
+                    }
+                    OPCODE_CLOSE => {
+                        self.close_received = true;
+                        self.send_close(CLOSE_NORMAL);
+                    }
+                    _ => {}
+                }
+                Ok(true)
+            }
+            _ => Err(invalid_data("Unknown WebSocket opcode")),
+        }
+    }
+}
+
+impl<S> AsyncRead for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            // Pongs and the close reply go out as soon as the socket accepts them
+            if let Poll::Ready(Err(e)) = this.poll_write_buffer(cx) {
+                return Poll::Ready(Err(e));
+            }
+            if this.close_received {
+                return Poll::Ready(Ok(()));
+            }
+
+            match &mut this.frame {
+                Some(frame) if frame.remaining == 0 => {
+                    // The next frame may already be buffered
+                    this.frame = None;
+                    continue;
+                }
+                Some(frame) if !this.read_buffer.is_empty() => {
+                    let n = this.read_buffer.len().min(buf.remaining()).min(frame.remaining as usize);
+                    let mut data: Vec<u8> = this.read_buffer.drain(..n).collect();
+                    unmask(&mut data, frame.mask, frame.offset);
+                    frame.offset += n as u64;
+                    frame.remaining -= n as u64;
+                    buf.put_slice(&data);
+                    return Poll::Ready(Ok(()));
+                }
+                Some(_) => {}
+                None => {
+                    if this.process_frame()? {
+                        continue;
+                    }
+                }
+            }
+
+            let mut chunk = [0; READ_CHUNK_SIZE];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                // The client went away without a close frame
+                return Poll::Ready(Ok(()));
+            }
+            this.read_buffer.extend_from_slice(chunk_buf.filled());
+        }
+    }
+}
+
+impl<S> AsyncWrite for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        // Only buffer one message at a time
+        ready!(this.poll_write_buffer(cx))?;
+        if this.close_sent {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        encode_frame(&mut this.write_buffer, OPCODE_BINARY, data);
+        if let Poll::Ready(Err(e)) = this.poll_write_buffer(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(data.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_buffer(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.send_close(CLOSE_NORMAL);
+        ready!(self.poll_write_buffer(cx))?;
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    const MASK: [u8; 4] = [0x37, 0xfa, 0x21, 0x3d];
+
+    /// Client side of the socket: hands out one chunk per read, then end of file, and keeps
+    /// what the server writes.
+    #[derive(Default)]
+    struct MockSocket {
+        chunks: VecDeque<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl AsyncRead for MockSocket {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if let Some(mut chunk) = self.chunks.pop_front() {
+                let n = chunk.len().min(buf.remaining());
+                buf.put_slice(&chunk[..n]);
+                if n < chunk.len() {
+                    self.chunks.push_front(chunk.split_off(n));
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncWrite for MockSocket {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            data: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.written.extend_from_slice(data);
+            Poll::Ready(Ok(data.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// A masked frame as sent by a client.
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        encode_frame(&mut frame, opcode, payload);
+        if !fin {
+            frame[0] &= 0x7f;
+        }
+        frame[1] |= 0x80;
+        let header_len = frame.len() - payload.len();
+        frame.splice(header_len..header_len, MASK);
+        unmask(&mut frame[header_len + 4..], MASK, 0);
+        frame
+    }
+
+    fn stream(chunks: &[&[u8]]) -> WebSocketStream<MockSocket> {
+        let socket =
+            MockSocket { chunks: chunks.iter().map(|chunk| chunk.to_vec()).collect(), ..Default::default() };
+        WebSocketStream::new(socket, Vec::new())
+    }
+
+    async fn read_all(stream: &mut WebSocketStream<MockSocket>) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    fn server_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        encode_frame(&mut frame, opcode, payload);
+        frame
+    }
+
+    #[test]
+    fn computes_the_accept_key() {
+        // Example from RFC 6455, section 1.3
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[tokio::test]
+    async fn reads_several_frames_from_one_read() {
+        let chunk = [client_frame(true, OPCODE_BINARY, b"hello"), client_frame(true, OPCODE_TEXT, b" world")]
+            .concat();
+        let mut stream = stream(&[&chunk]);
+        assert_eq!(read_all(&mut stream).await.unwrap(), b"hello world");
+    }
+
+    #[tokio::test]
+    async fn reads_pipelined_frames() {
+        let socket = MockSocket::default();
+        let mut stream = WebSocketStream::new(socket, client_frame(true, OPCODE_BINARY, b"early"));
+        assert_eq!(read_all(&mut stream).await.unwrap(), b"early");
+    }
+
+    #[tokio::test]
+    async fn skips_zero_length_frames() {
+        let chunk = [
+            client_frame(true, OPCODE_BINARY, b""),
+            client_frame(true, OPCODE_BINARY, b"data"),
+            client_frame(true, OPCODE_BINARY, b""),
+        ]
+        .concat();
+        let mut stream = stream(&[&chunk, &client_frame(true, OPCODE_BINARY, b"!")]);
+        assert_eq!(read_all(&mut stream).await.unwrap(), b"data!");
+    }
+
+    #[tokio::test]
+    async fn answers_a_ping_between_fragments() {
+        let chunk = [
+            client_frame(false, OPCODE_TEXT, b"hel"),
+            client_frame(true, OPCODE_PING, b"are you there"),
+            client_frame(true, OPCODE_CONTINUATION, b"lo"),
+        ]
+        .concat();
+        let mut stream = stream(&[&chunk]);
+        assert_eq!(read_all(&mut stream).await.unwrap(), b"hello");
+        assert_eq!(stream.inner.written, server_frame(OPCODE_PONG, b"are you there"));
+    }
+
+    #[tokio::test]
+    async fn unmasks_frames_split_across_reads() {
+        let payload: Vec<u8> = (0..=255).collect();
+        let frame = client_frame(true, OPCODE_BINARY, &payload);
+        // Split inside the extended length, inside the mask and at odd payload offsets
+        let chunks: Vec<&[u8]> = vec![&frame[..3], &frame[3..6], &frame[6..9], &frame[9..100], &frame[100..]];
+        let mut stream = stream(&chunks);
+
+        // Small reads, so the payload is also unmasked in pieces
+        let mut data = Vec::new();
+        let mut buffer = [0; 7];
+        loop {
+            let n = stream.read(&mut buffer).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buffer[..n]);
+        }
+        assert_eq!(data, payload);
+    }
+
+    #[tokio::test]
+    async fn stops_reading_at_a_close_frame_and_replies() {
+        let chunk = [
+            client_frame(true, OPCODE_BINARY, b"last"),
+            client_frame(true, OPCODE_CLOSE, &1001u16.to_be_bytes()),
+            client_frame(true, OPCODE_BINARY, b"ignored"),
+        ]
+        .concat();
+        let mut stream = stream(&[&chunk]);
+        assert_eq!(read_all(&mut stream).await.unwrap(), b"last");
+        assert_eq!(stream.inner.written, server_frame(OPCODE_CLOSE, &CLOSE_NORMAL.to_be_bytes()));
+
+        // The close handshake is done, nothing more may be sent
+        let error = stream.write_all(b"late").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        stream.shutdown().await.unwrap();
+        assert_eq!(stream.inner.written, server_frame(OPCODE_CLOSE, &CLOSE_NORMAL.to_be_bytes()));
+    }
+
+    #[tokio::test]
+    async fn sends_a_close_frame_on_shutdown() {
+        let mut stream = stream(&[]);
+        stream.write_all(b"reply").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let expected =
+            [server_frame(OPCODE_BINARY, b"reply"), server_frame(OPCODE_CLOSE, &CLOSE_NORMAL.to_be_bytes())]
+                .concat();
+        assert_eq!(stream.inner.written, expected);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_frames() {
+        let mut unmasked = client_frame(true, OPCODE_BINARY, b"data");
+        unmasked[1] &= 0x7f;
+        let mut large_ping = client_frame(true, OPCODE_PING, &[0; 126]);
+        large_ping.truncate(8);
+        let unknown = client_frame(true, 0x3, b"");
+
+        for frame in [unmasked, large_ping, unknown] {
+            let error = read_all(&mut stream(&[&frame])).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,519 @@
+//! Python bindings for checking Zap proofs in bulk, e.g. from notebooks. Build the
+//! `zap_verify` module with `maturin develop` or `maturin build` in this directory.
+//!
+//! ```python
+//! import zap_verify
+//!
+//! keys = zap_verify.parse_keys(keys_json)  # body of GET /keys
+//! attestation = zap_verify.verify_proof(proof_json, keys)
+//! for record, (start, end) in zip(attestation.disclosed, attestation.disclosed_ranges()):
+//!     ...
+//! response = attestation.http_response()
+//!
+//! # Records of a transcript, with the secrets the client sent to `/proof`
+//! rx_secret, tx_secret = zap_verify.parse_secrets(secrets_json)
+//! records = zap_verify.decrypt_records(ciphertext, rx_secret)
+//! ```
+
+// pyo3 0.22's macros expand to conversions clippy flags on every `PyResult` method
+#![allow(clippy::useless_conversion)]
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+use zap_types::{Attestation, HttpResponse, NotaryPublicKey, Proof, SecretData, SecretsPayload};
+
+use exceptions::{DecryptionError, VerificationError};
+
+mod exceptions {
+    // `create_exception!` checks a `gil-refs` feature this crate does not declare
+    #![allow(unexpected_cfgs)]
+
+    use pyo3::{create_exception, exceptions::PyValueError};
+
+    create_exception!(
+        zap_verify,
+        VerificationError,
+        PyValueError,
+        "The proof is not signed by any of the given notary keys."
+    );
+    create_exception!(
+        zap_verify,
+        DecryptionError,
+        PyValueError,
+        "The records could not be decrypted with the given secret."
+    );
+}
+
+fn invalid(what: &str, e: serde_json::Error) -> PyErr {
+    PyValueError::new_err(format!("Invalid {}: {}", what, e))
+}
+
+/// A notary signing key, as served by `GET /keys`.
+#[pyclass(name = "NotaryKey", module = "zap_verify", frozen)]
+#[derive(Clone)]
+struct PyNotaryKey(NotaryPublicKey);
+
+#[pymethods]
+impl PyNotaryKey {
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        serde_json::from_str(json).map(Self).map_err(|e| invalid("notary key", e))
+    }
+
+    #[getter]
+    fn key_id(&self) -> &str {
+        self.0.get_key_id()
+    }
+
+    #[getter]
+    fn algorithm(&self) -> &str {
+        self.0.get_algorithm()
+    }
+
+    /// PEM-encoded SubjectPublicKeyInfo.
+    #[getter]
+    fn public_key(&self) -> &str {
+        self.0.get_public_key()
+    }
+
+    #[getter]
+    fn current(&self) -> bool {
+        self.0.is_current()
+    }
+
+    #[getter]
+    fn not_before(&self) -> Option<&str> {
+        self.0.get_not_before()
+    }
+
+    #[getter]
+    fn not_after(&self) -> Option<&str> {
+        self.0.get_not_after()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("NotaryKey(key_id={:?}, algorithm={:?})", self.0.get_key_id(), self.0.get_algorithm())
+    }
+}
+
+/// Parses the JSON array served by `GET /keys`.
+#[pyfunction]
+fn parse_keys(json: &str) -> PyResult<Vec<PyNotaryKey>> {
+    let keys: Vec<NotaryPublicKey> = serde_json::from_str(json).map_err(|e| invalid("notary keys", e))?;
+    Ok(keys.into_iter().map(PyNotaryKey).collect())
+}
+
+/// An attestation signed by the notary, as returned by `/proof`.
+#[pyclass(name = "Proof", module = "zap_verify", frozen)]
+struct PyProof(Proof);
+
+#[pymethods]
+impl PyProof {
+    #[new]
+    fn new(data: &str, signature: &str, key_id: &str) -> Self {
+        Self(Proof::new(data, signature, key_id))
+    }
+
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        serde_json::from_str(json).map(Self).map_err(|e| invalid("proof", e))
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.0).map_err(|e| invalid("proof", e))
+    }
+
+    #[getter]
+    fn key_id(&self) -> &str {
+        self.0.get_key_id()
+    }
+
+    /// The attestation as serialized JSON, exactly the signed bytes.
+    #[getter]
+    fn data(&self) -> &str {
+        self.0.get_data()
+    }
+
+    /// Hex signature over `data`.
+    #[getter]
+    fn signature(&self) -> &str {
+        self.0.get_signature()
+    }
+
+    /// Whether the proof is signed by the key from `keys` with its key id.
+    fn verify(&self, py: Python<'_>, keys: Vec<PyRef<'_, PyNotaryKey>>) -> bool {
+        let keys: Vec<NotaryPublicKey> = keys.iter().map(|key| key.0.clone()).collect();
+        py.allow_threads(|| self.0.verify(&keys))
+    }
+
+    /// Parses the signed data without verifying it, call `verify` first.
+    fn attestation(&self) -> PyResult<PyAttestation> {
+        self.0.attestation().map(PyAttestation).map_err(|e| invalid("attestation", e))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Proof(key_id={:?})", self.0.get_key_id())
+    }
+}
+
+/// The statement the notary signs about a session.
+#[pyclass(name = "Attestation", module = "zap_verify", frozen)]
+struct PyAttestation(Attestation);
+
+#[pymethods]
+impl PyAttestation {
+    #[getter]
+    fn version(&self) -> u32 {
+        self.0.get_version()
+    }
+
+    #[getter]
+    fn key_id(&self) -> &str {
+        self.0.get_key_id()
+    }
+
+    #[getter]
+    fn algorithm(&self) -> &str {
+        self.0.get_algorithm()
+    }
+
+    /// RFC 3339 timestamp of when the notary signed the attestation.
+    #[getter]
+    fn issued_at(&self) -> &str {
+        self.0.get_issued_at()
+    }
+
+    /// Hex SHA-256 over the ciphertext the proxy recorded for the session.
+    #[getter]
+    fn transcript_sha256(&self) -> &str {
+        self.0.get_transcript_sha256()
+    }
+
+    /// The records the notary decrypted and disclosed, in transcript order.
+    #[getter]
+    fn disclosed(&self) -> Vec<String> {
+        self.0.get_decrypted().to_vec()
+    }
+
+    /// The disclosed records joined, the text `http_response` parses.
+    #[getter]
+    fn disclosed_text(&self) -> String {
+        self.0.get_decrypted().concat()
+    }
+
+    /// `(start, end)` of each disclosed record in `disclosed_text`, in characters so they can
+    /// be used to slice it.
+    fn disclosed_ranges(&self) -> Vec<(usize, usize)> {
+        disclosed_ranges(self.0.get_decrypted())
+    }
+
+    /// The disclosed records parsed as the target's HTTP response, `None` if they do not start
+    /// with a complete response head.
+    fn http_response(&self) -> Option<PyHttpResponse> {
+        self.0.get_http_response().map(PyHttpResponse)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Attestation(key_id={:?}, issued_at={:?})", self.0.get_key_id(), self.0.get_issued_at())
+    }
+}
+
+fn disclosed_ranges(records: &[String]) -> Vec<(usize, usize)> {
+    let mut start = 0;
+    records
+        .iter()
+        .map(|record| {
+            let end = start + record.chars().count();
+            let range = (start, end);
+            start = end;
+            range
+        })
+        .collect()
+}
+
+/// An HTTP response recovered from the disclosed records of an attestation.
+#[pyclass(name = "HttpResponse", module = "zap_verify", frozen)]
+struct PyHttpResponse(HttpResponse);
+
+#[pymethods]
+impl PyHttpResponse {
+    #[getter]
+    fn status(&self) -> u16 {
+        self.0.get_status()
+    }
+
+    #[getter]
+    fn reason(&self) -> &str {
+        self.0.get_reason()
+    }
+
+    /// `(name, value)` pairs in the order the target sent them.
+    #[getter]
+    fn headers(&self) -> Vec<(String, String)> {
+        self.0.get_headers().to_vec()
+    }
+
+    /// The first header with the given name, compared case-insensitively.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.0.get_header(name)
+    }
+
+    /// The body as sent by the target, chunked transfer coding is not removed.
+    #[getter]
+    fn body(&self) -> &str {
+        self.0.get_body()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("HttpResponse(status={}, reason={:?})", self.0.get_status(), self.0.get_reason())
+    }
+}
+
+/// The traffic secret of one direction of a TLS session, as sent by clients to `/proof`.
+#[pyclass(name = "SecretData", module = "zap_verify", frozen)]
+struct PySecretData(SecretData);
+
+#[pymethods]
+impl PySecretData {
+    #[new]
+    fn new(cipher_suite: &str, key: &[u8], iv: &[u8]) -> Self {
+        Self(SecretData::new(cipher_suite, key, iv))
+    }
+
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        serde_json::from_str(json).map(Self).map_err(|e| invalid("secret", e))
+    }
+
+    #[getter]
+    fn cipher_suite(&self) -> &str {
+        self.0.get_cipher_suite()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SecretData(cipher_suite={:?})", self.0.get_cipher_suite())
+    }
+}
+
+/// Parses the body of `POST /proof`, returning the secrets for the records sent by the target
+/// and by the client.
+#[pyfunction]
+fn parse_secrets(json: &str) -> PyResult<(PySecretData, PySecretData)> {
+    let payload: SecretsPayload = serde_json::from_str(json).map_err(|e| invalid("secrets", e))?;
+    let copy = |secret: &SecretData| {
+        PySecretData(SecretData::new(secret.get_cipher_suite(), secret.get_key(), secret.get_iv()))
+    };
+    Ok((copy(payload.get_rx_secret()), copy(payload.get_tx_secret())))
+}
+
+/// Decrypts the TLS 1.3 application data records in `data`, sent in the direction of `secret`,
+/// the way the notary does. The notary numbers the records the target sent from 2.
+#[pyfunction]
+#[pyo3(signature = (data, secret, first_sequence_number = 2))]
+fn decrypt_records(
+    py: Python<'_>,
+    data: &[u8],
+    secret: &PySecretData,
+    first_sequence_number: u64,
+) -> PyResult<Vec<String>> {
+    py.allow_threads(|| zap_types::decrypt_records(data, &secret.0, first_sequence_number))
+        .map_err(|e| DecryptionError::new_err(e.to_string()))
+}
+
+/// Parses `proof_json` and checks it is signed by one of `keys`, returning its attestation.
+/// Raises `VerificationError` if it is not, and `ValueError` if the proof is malformed.
+#[pyfunction]
+fn verify_proof(
+    py: Python<'_>,
+    proof_json: &str,
+    keys: Vec<PyRef<'_, PyNotaryKey>>,
+) -> PyResult<PyAttestation> {
+    let proof = PyProof::from_json(proof_json)?;
+    if !proof.verify(py, keys) {
+        let key_id = proof.0.get_key_id();
+        return Err(VerificationError::new_err(format!(
+            "Proof is not signed by a given key (key id {})",
+            key_id
+        )));
+    }
+    proof.attestation()
+}
+
+#[pymodule]
+fn zap_verify(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("VerificationError", m.py().get_type_bound::<VerificationError>())?;
+    m.add("DecryptionError", m.py().get_type_bound::<DecryptionError>())?;
+    m.add_class::<PyNotaryKey>()?;
+    m.add_class::<PyProof>()?;
+    m.add_class::<PyAttestation>()?;
+    m.add_class::<PyHttpResponse>()?;
+    m.add_class::<PySecretData>()?;
+    m.add_function(wrap_pyfunction!(parse_keys, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_proof, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_secrets, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt_records, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::PKey,
+        sign::Signer,
+        symm::{encrypt_aead, Cipher},
+    };
+    use pyo3::types::{PyBytes, PyDict};
+
+    use super::*;
+
+    const SCRIPT: &str = r#"
+keys = zap_verify.parse_keys(keys_json)
+assert [key.key_id for key in keys] == ["k1"]
+
+attestation = zap_verify.verify_proof(proof_json, keys)
+assert attestation.key_id == "k1"
+assert attestation.disclosed == records
+text = attestation.disclosed_text
+assert [text[start:end] for start, end in attestation.disclosed_ranges()] == records
+
+response = attestation.http_response()
+assert response.status == 200
+assert response.reason == "OK"
+assert response.header("content-type") == "application/json"
+assert response.body == '{"name":"Zoë"}'
+
+proof = zap_verify.Proof.from_json(proof_json)
+assert proof.verify(keys)
+tampered = zap_verify.Proof(proof.data.replace("Zoë", "Eve"), proof.signature, proof.key_id)
+assert not tampered.verify(keys)
+try:
+    zap_verify.verify_proof(tampered.to_json(), keys)
+    raise AssertionError("tampered proof verified")
+except zap_verify.VerificationError:
+    pass
+try:
+    zap_verify.verify_proof("{}", keys)
+    raise AssertionError("malformed proof accepted")
+except ValueError as e:
+    assert not isinstance(e, zap_verify.VerificationError)
+"#;
+
+    /// A proof over `records`, with the `GET /keys` body of the key that signed it.
+    fn signed_proof(records: &[&str]) -> (String, String) {
+        let key = EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap();
+        let key = PKey::from_ec_key(key).unwrap();
+        let public_key = String::from_utf8(key.public_key_to_pem().unwrap()).unwrap();
+        let notary_key = NotaryPublicKey::new("k1", "ECDSA-P256-SHA256", &public_key, true, None, None);
+
+        let decrypted = records.iter().map(|record| record.to_string()).collect();
+        let attestation = Attestation::new(
+            "k1",
+            "ECDSA-P256-SHA256",
+            "2026-10-19T06:00:00Z".to_string(),
+            "00".repeat(32),
+            decrypted,
+        );
+        let data = serde_json::to_string(&attestation).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        let signature = hex::encode(signer.sign_oneshot_to_vec(data.as_bytes()).unwrap());
+
+        let proof = Proof::new(&data, &signature, "k1");
+        (serde_json::to_string(&proof).unwrap(), serde_json::to_string(&[notary_key]).unwrap())
+    }
+
+    const DECRYPT_SCRIPT: &str = r#"
+rx_secret, tx_secret = zap_verify.parse_secrets(secrets_json)
+assert rx_secret.cipher_suite == "Aes256Gcm"
+assert zap_verify.decrypt_records(data, rx_secret) == ["HTTP/1.1 200 OK\r\n\r\n", "hello"]
+assert zap_verify.decrypt_records(data[:0], rx_secret) == []
+
+secret = zap_verify.SecretData("Aes256Gcm", bytes([0x42] * 32), bytes([0x24] * 12))
+assert zap_verify.decrypt_records(data, secret, first_sequence_number=2)[1] == "hello"
+for wrong, start in [(tx_secret, 2), (rx_secret, 0)]:
+    try:
+        zap_verify.decrypt_records(data, wrong, start)
+        raise AssertionError("decrypted with the wrong secret")
+    except zap_verify.DecryptionError:
+        pass
+"#;
+
+    /// TLS 1.3 application data records of `plaintexts`, numbered from 2.
+    fn encrypted_records(key: &[u8], iv: &[u8], plaintexts: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (plaintext, sequence_number) in plaintexts.iter().zip(2u64..) {
+            let mut nonce = iv.to_vec();
+            for (byte, seq_byte) in nonce[4..].iter_mut().zip(sequence_number.to_be_bytes()) {
+                *byte ^= seq_byte;
+            }
+            let length = u16::try_from(plaintext.len() + 16).unwrap().to_be_bytes();
+            let header = [0x17, 0x03, 0x03, length[0], length[1]];
+            let mut tag = [0; 16];
+            let ciphertext = encrypt_aead(
+                Cipher::aes_256_gcm(),
+                key,
+                Some(&nonce),
+                &header,
+                plaintext.as_bytes(),
+                &mut tag,
+            )
+            .unwrap();
+            data.extend_from_slice(&header);
+            data.extend_from_slice(&ciphertext);
+            data.extend_from_slice(&tag);
+        }
+        data
+    }
+
+    #[test]
+    fn decrypts_records_from_python() {
+        let (key, iv) = ([0x42; 32], [0x24; 12]);
+        let data = encrypted_records(&key, &iv, &["HTTP/1.1 200 OK\r\n\r\n", "hello"]);
+        let secrets = SecretsPayload::new(
+            2,
+            1,
+            SecretData::new("Aes256Gcm", &key, &iv),
+            SecretData::new("Aes256Gcm", &[0x11; 32], &iv),
+        );
+
+        Python::with_gil(|py| {
+            let module = PyModule::new_bound(py, "zap_verify").unwrap();
+            zap_verify(&module).unwrap();
+            let globals = PyDict::new_bound(py);
+            globals.set_item("zap_verify", module).unwrap();
+            globals.set_item("secrets_json", serde_json::to_string(&secrets).unwrap()).unwrap();
+            globals.set_item("data", PyBytes::new_bound(py, &data)).unwrap();
+            if let Err(e) = py.run_bound(DECRYPT_SCRIPT, Some(&globals), None) {
+                e.print(py);
+                panic!("Python checks failed");
+            }
+        });
+    }
+
+    #[test]
+    fn computes_ranges_in_characters() {
+        let records = ["ab".to_string(), "".to_string(), "ë€".to_string()];
+        assert_eq!(disclosed_ranges(&records), [(0, 2), (2, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn verifies_proofs_from_python() {
+        let records = ["HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n", "\r\n{\"name\":\"Zoë\"}"];
+        let (proof_json, keys_json) = signed_proof(&records);
+
+        Python::with_gil(|py| {
+            let module = PyModule::new_bound(py, "zap_verify").unwrap();
+            zap_verify(&module).unwrap();
+            let globals = PyDict::new_bound(py);
+            globals.set_item("zap_verify", module).unwrap();
+            globals.set_item("proof_json", proof_json).unwrap();
+            globals.set_item("keys_json", keys_json).unwrap();
+            globals.set_item("records", records.to_vec()).unwrap();
+            if let Err(e) = py.run_bound(SCRIPT, Some(&globals), None) {
+                e.print(py);
+                panic!("Python checks failed");
+            }
+        });
+    }
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "verify")]
+use crate::notary::NotaryPublicKey;
+
+const MAX_HEADERS: usize = 64;
+
+/// Version of the attestation format, the `version` field of [`Attestation`].
+pub const ATTESTATION_VERSION: u32 = 1;
+
+/// An attestation signed by the notary, as returned by `/proof`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Proof {
+    /// The [`Attestation`] as serialized JSON, exactly the signed bytes.
+    data: String,
+    /// Hex signature over `data`.
+    signature: String,
+    key_id: String,
+}
+
+impl Proof {
+    pub fn new(data: &str, signature: &str, key_id: &str) -> Self {
+        Proof { data: data.to_string(), signature: signature.to_string(), key_id: key_id.to_string() }
+    }
+
+    pub fn get_key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Verifies the proof's signature with the matching key from `keys`.
+    #[cfg(feature = "verify")]
+    pub fn verify(&self, keys: &[NotaryPublicKey]) -> bool {
+        let Some(key) = keys.iter().find(|key| key.get_key_id() == self.key_id) else {
+            return false;
+        };
+        let Ok(signature) = hex::decode(&self.signature) else {
+            return false;
+        };
+        key.verify(self.data.as_bytes(), &signature)
+    }
+
+    pub fn get_data(&self) -> &str {
+        &self.data
+    }
+
+    pub fn get_signature(&self) -> &str {
+        &self.signature
+    }
+
+    /// Parses the signed data. Verify the proof first, the attestation is only as
+    /// trustworthy as the signature over it.
+    pub fn attestation(&self) -> Result<Attestation, serde_json::Error> {
+        Attestation::parse(&self.data)
+    }
+}
+
+/// The statement the notary signs about a session, carried as JSON in [`Proof::get_data`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attestation {
+    version: u32,
+    key_id: String,
+    algorithm: String,
+    issued_at: String,
+    transcript_sha256: String,
+    decrypted: Vec<String>,
+}
+
+impl Attestation {
+    /// A new attestation in the current format.
+    pub fn new(
+        key_id: &str,
+        algorithm: &str,
+        issued_at: String,
+        transcript_sha256: String,
+        decrypted: Vec<String>,
+    ) -> Self {
+        Attestation {
+            version: ATTESTATION_VERSION,
+            key_id: key_id.to_string(),
+            algorithm: algorithm.to_string(),
+            issued_at,
+            transcript_sha256,
+            decrypted,
+        }
+    }
+
+    pub fn parse(data: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(data)
+    }
+
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn get_key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn get_algorithm(&self) -> &str {
+        &self.algorithm
+    }
+
+    /// RFC 3339 timestamp of when the notary signed the attestation.
+    pub fn get_issued_at(&self) -> &str {
+        &self.issued_at
+    }
+
+    /// Hex SHA-256 over the ciphertext records the proxy recorded for the session.
+    pub fn get_transcript_sha256(&self) -> &str {
+        &self.transcript_sha256
+    }
+
+    /// The records the notary decrypted and disclosed, in transcript order.
+    pub fn get_decrypted(&self) -> &[String] {
+        &self.decrypted
+    }
+
+    /// Parses the disclosed records as the target's HTTP response. Returns `None` if they do
+    /// not start with a complete response head.
+    pub fn get_http_response(&self) -> Option<HttpResponse> {
+        let data = self.decrypted.concat();
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut response = httparse::Response::new(&mut headers);
+        let len = match response.parse(data.as_bytes()) {
+            Ok(httparse::Status::Complete(len)) => len,
+            _ => return None,
+        };
+
+        Some(HttpResponse {
+            status: response.code?,
+            reason: response.reason.unwrap_or("").to_string(),
+            headers: response
+                .headers
+                .iter()
+                .map(|header| (header.name.to_string(), String::from_utf8_lossy(header.value).into_owned()))
+                .collect(),
+            body: data[len..].to_string(),
+        })
+    }
+}
+
+/// An HTTP response recovered from the disclosed records of an attestation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HttpResponse {
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl HttpResponse {
+    pub fn get_status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn get_reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Returns the first header with the given name, compared case-insensitively.
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The body as sent by the target, chunked transfer coding is not removed.
+    pub fn get_body(&self) -> &str {
+        &self.body
+    }
+}
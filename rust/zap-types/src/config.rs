@@ -0,0 +1,324 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::NotaryPublicKey;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum ProxyCredentials {
+    Basic { username: String, password: String },
+    Bearer { token: String },
+}
+
+impl ProxyCredentials {
+    pub fn basic(username: &str, password: &str) -> Self {
+        ProxyCredentials::Basic { username: username.to_string(), password: password.to_string() }
+    }
+
+    pub fn bearer(token: &str) -> Self {
+        ProxyCredentials::Bearer { token: token.to_string() }
+    }
+
+    pub fn get_header_value(&self) -> String {
+        match self {
+            ProxyCredentials::Basic { username, password } => {
+                format!("Basic {}", BASE64.encode(format!("{}:{}", username, password)))
+            }
+            ProxyCredentials::Bearer { token } => format!("Bearer {}", token),
+        }
+    }
+}
+
+impl std::fmt::Debug for ProxyCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProxyCredentials::Basic { username, .. } => write!(f, "Basic({:?}, <redacted>)", username),
+            ProxyCredentials::Bearer { .. } => write!(f, "Bearer(<redacted>)"),
+        }
+    }
+}
+
+/// TLS settings for connecting to a proxy whose listeners use TLS.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ProxyTls {
+    ca_file: Option<PathBuf>,
+    client_cert_file: Option<PathBuf>,
+    client_key_file: Option<PathBuf>,
+}
+
+impl ProxyTls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts the CA certificates in `ca_file` instead of the public web PKI roots.
+    pub fn ca_file<P: Into<PathBuf>>(mut self, ca_file: P) -> Self {
+        self.ca_file = Some(ca_file.into());
+        self
+    }
+
+    /// Presents a client certificate to proxies that require mTLS.
+    pub fn client_identity<P: Into<PathBuf>>(mut self, cert_file: P, key_file: P) -> Self {
+        self.client_cert_file = Some(cert_file.into());
+        self.client_key_file = Some(key_file.into());
+        self
+    }
+
+    pub fn get_ca_file(&self) -> Option<&Path> {
+        self.ca_file.as_deref()
+    }
+
+    pub fn get_client_identity(&self) -> Option<(&Path, &Path)> {
+        self.client_cert_file.as_deref().zip(self.client_key_file.as_deref())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ZapServerConfig {
+    host: String,
+    api_port: u16,
+    proxy_port: u16,
+    #[serde(default)]
+    credentials: Option<ProxyCredentials>,
+    #[serde(default)]
+    tls: Option<ProxyTls>,
+    #[serde(default)]
+    pinned_keys: Vec<NotaryPublicKey>,
+}
+
+impl Default for ZapServerConfig {
+    fn default() -> Self {
+        Self::new("localhost", 8080, 55688)
+    }
+}
+
+impl ZapServerConfig {
+    pub fn new(host: &str, api_port: u16, proxy_port: u16) -> Self {
+        Self {
+            host: host.to_string(),
+            api_port,
+            proxy_port,
+            credentials: None,
+            tls: None,
+            pinned_keys: vec![],
+        }
+    }
+
+    pub fn with_tls(mut self, tls: ProxyTls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Only accepts proofs signed by one of `keys`.
+    pub fn with_pinned_keys(mut self, keys: Vec<NotaryPublicKey>) -> Self {
+        self.pinned_keys = keys;
+        self
+    }
+
+    pub fn with_credentials(mut self, credentials: ProxyCredentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    pub fn get_host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn get_api_port(&self) -> u16 {
+        self.api_port
+    }
+
+    pub fn get_proxy_port(&self) -> u16 {
+        self.proxy_port
+    }
+
+    /// `host:port` of the proxy listener, IPv6 literals in brackets.
+    pub fn get_proxy_url(&self) -> String {
+        format!("{}:{}", authority_host(&self.host), self.proxy_port)
+    }
+
+    /// `host:port` of the HTTP API, IPv6 literals in brackets.
+    pub fn get_api_url(&self) -> String {
+        format!("{}:{}", authority_host(&self.host), self.api_port)
+    }
+
+    pub fn get_api_base_url(&self) -> String {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        format!("{}://{}", scheme, self.get_api_url())
+    }
+
+    pub fn get_credentials(&self) -> Option<&ProxyCredentials> {
+        self.credentials.as_ref()
+    }
+
+    pub fn get_tls(&self) -> Option<&ProxyTls> {
+        self.tls.as_ref()
+    }
+
+    pub fn get_pinned_keys(&self) -> &[NotaryPublicKey] {
+        &self.pinned_keys
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Endpoint {
+    host: String,
+    port: u16,
+    #[serde(default = "default_route")]
+    route: String,
+    #[serde(with = "method")]
+    method: http::Method,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+}
+
+fn default_route() -> String {
+    "/".to_string()
+}
+
+/// Serializes an HTTP method as its name, e.g. `"GET"`.
+mod method {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(method: &http::Method, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(method.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<http::Method, D::Error> {
+        let method = String::deserialize(deserializer)?;
+        http::Method::from_bytes(method.as_bytes())
+            .map_err(|_| D::Error::custom(format!("invalid HTTP method {:?}", method)))
+    }
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Endpoint::new("www.example.com", 443, "/", http::Method::GET, vec![])
+    }
+}
+
+impl Endpoint {
+    pub fn new(
+        host: &str,
+        port: u16,
+        route: &str,
+        method: http::Method,
+        headers: Vec<(String, String)>,
+    ) -> Self {
+        Endpoint { host: host.to_string(), port, route: route.to_string(), method, headers }
+    }
+
+    pub fn get_host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    /// Host in the form used in URLs and `Host` headers, IPv6 literals in brackets.
+    pub fn get_authority_host(&self) -> String {
+        authority_host(&self.host)
+    }
+
+    /// `host:port` authority, e.g. `example.com:443` or `[::1]:443`.
+    pub fn get_url(&self) -> String {
+        format!("{}:{}", self.get_authority_host(), self.port)
+    }
+
+    pub fn get_route(&self) -> &str {
+        &self.route
+    }
+
+    pub fn get_method(&self) -> http::Method {
+        self.method.clone()
+    }
+
+    pub fn get_headers(&self) -> Vec<(String, String)> {
+        self.headers.clone()
+    }
+}
+
+/// `host` as written in an authority, IPv6 literals in brackets.
+fn authority_host(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn endpoint_round_trips_with_the_method_as_a_string() {
+        let headers = vec![("Accept".to_string(), "application/json".to_string())];
+        let endpoint = Endpoint::new("api.example.com", 8443, "/v1/balance", http::Method::POST, headers);
+        let value = serde_json::to_value(&endpoint).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "host": "api.example.com",
+                "port": 8443,
+                "route": "/v1/balance",
+                "method": "POST",
+                "headers": [["Accept", "application/json"]],
+            })
+        );
+
+        let parsed: Endpoint = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.get_url(), "api.example.com:8443");
+        assert_eq!(parsed.get_route(), "/v1/balance");
+        assert_eq!(parsed.get_method(), http::Method::POST);
+        assert_eq!(parsed.get_headers(), endpoint.get_headers());
+
+        let minimal: Endpoint =
+            serde_json::from_value(json!({ "host": "example.com", "port": 443, "method": "GET" })).unwrap();
+        assert_eq!(minimal.get_route(), "/");
+        assert!(minimal.get_headers().is_empty());
+        assert!(serde_json::from_value::<Endpoint>(
+            json!({ "host": "example.com", "port": 443, "method": "GET /" })
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn server_config_round_trips() {
+        let config = ZapServerConfig::new("notary.example", 3000, 55688)
+            .with_credentials(ProxyCredentials::bearer("token"))
+            .with_tls(ProxyTls::new().ca_file("/etc/zap/ca.pem"));
+        let json = serde_json::to_string(&config).unwrap();
+        let parsed: ZapServerConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+        assert_eq!(parsed.get_api_base_url(), "https://notary.example:3000");
+        assert_eq!(parsed.get_credentials().unwrap().get_header_value(), "Bearer token");
+        assert_eq!(parsed.get_tls().unwrap().get_ca_file(), Some(Path::new("/etc/zap/ca.pem")));
+
+        let minimal: ZapServerConfig =
+            serde_json::from_value(json!({ "host": "localhost", "api_port": 8080, "proxy_port": 55688 }))
+                .unwrap();
+        assert_eq!(minimal.get_api_base_url(), "http://localhost:8080");
+        assert!(minimal.get_credentials().is_none());
+        assert!(minimal.get_pinned_keys().is_empty());
+    }
+
+    #[test]
+    fn brackets_ipv6_hosts() {
+        let config = ZapServerConfig::new("::1", 3000, 55688);
+        assert_eq!(config.get_proxy_url(), "[::1]:55688");
+        assert_eq!(config.get_api_url(), "[::1]:3000");
+        assert_eq!(config.get_api_base_url(), "http://[::1]:3000");
+
+        let config = ZapServerConfig::new("notary.example", 3000, 55688);
+        assert_eq!(config.get_proxy_url(), "notary.example:55688");
+        assert_eq!(config.get_api_url(), "notary.example:3000");
+
+        let endpoint = Endpoint::new("2001:db8::1", 443, "/", http::Method::GET, vec![]);
+        assert_eq!(endpoint.get_url(), "[2001:db8::1]:443");
+    }
+}
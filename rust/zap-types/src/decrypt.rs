@@ -0,0 +1,142 @@
+use std::{error::Error, fmt};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+
+use crate::SecretData;
+
+/// Cipher suites [`decrypt_records`] can open, named as in [`SecretData`].
+pub const SUPPORTED_CIPHER_SUITES: &[&str] = &["Aes256Gcm"];
+
+/// Header of a TLS 1.3 application data record, hex encoded.
+const APPLICATION_DATA_HEADER: &str = "170303";
+const KEY_BYTES: usize = 32;
+const IV_BYTES: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecryptError {
+    UnsupportedCipherSuite(String),
+    /// The key or IV has the wrong length for the suite.
+    InvalidSecret,
+    /// A record failed authentication, the secret does not belong to these records.
+    Decryption {
+        sequence_number: u64,
+    },
+    MalformedRecord,
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecryptError::UnsupportedCipherSuite(suite) => write!(f, "Unsupported cipher suite {:?}", suite),
+            DecryptError::InvalidSecret => write!(f, "Invalid key or iv length"),
+            DecryptError::Decryption { sequence_number } => {
+                write!(f, "Decryption failed for record {}", sequence_number)
+            }
+            DecryptError::MalformedRecord => write!(f, "Malformed TLS record"),
+        }
+    }
+}
+
+impl Error for DecryptError {}
+
+/// Decrypts the TLS 1.3 application data records in `data`, one chunk of traffic in a single
+/// direction, with the traffic secret of that direction. Records are numbered from
+/// `first_sequence_number`, the per-record nonce is the IV XORed with that number.
+///
+/// Records that are not UTF-8 are returned as `"Decryption error"`.
+pub fn decrypt_records(
+    data: &[u8],
+    secret: &SecretData,
+    first_sequence_number: u64,
+) -> Result<Vec<String>, DecryptError> {
+    if !SUPPORTED_CIPHER_SUITES.contains(&secret.get_cipher_suite()) {
+        return Err(DecryptError::UnsupportedCipherSuite(secret.get_cipher_suite().to_string()));
+    }
+    let (key, iv) = (secret.get_key(), secret.get_iv());
+    if key.len() != KEY_BYTES || iv.len() != IV_BYTES {
+        return Err(DecryptError::InvalidSecret);
+    }
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+
+    let hex_data = hex::encode(data);
+    let mut records = Vec::new();
+    // Each chunk starts with the 2 byte length of the record that follows the header
+    let chunks = hex_data.split(APPLICATION_DATA_HEADER).filter(|chunk| chunk.len() > 2 * 2);
+    for (chunk, sequence_number) in chunks.zip(first_sequence_number..) {
+        let aad = hex::decode(format!("{}{}", APPLICATION_DATA_HEADER, &chunk[..2 * 2]))
+            .map_err(|_| DecryptError::MalformedRecord)?;
+        let ciphertext = hex::decode(&chunk[2 * 2..]).map_err(|_| DecryptError::MalformedRecord)?;
+
+        let mut nonce = [0u8; IV_BYTES];
+        nonce[4..].copy_from_slice(&sequence_number.to_be_bytes());
+        for (byte, iv_byte) in nonce.iter_mut().zip(iv) {
+            *byte ^= iv_byte;
+        }
+
+        let payload = Payload { msg: &ciphertext, aad: &aad };
+        let decrypted = cipher
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| DecryptError::Decryption { sequence_number })?;
+        match String::from_utf8(decrypted) {
+            Ok(text) => records.push(text),
+            Err(_) => records.push("Decryption error".to_string()),
+        }
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x42; 32];
+    const IV: [u8; 12] = [0x24; 12];
+
+    /// Encrypts `plaintext` as the record with `sequence_number`, header included.
+    fn record(plaintext: &[u8], sequence_number: u64) -> Vec<u8> {
+        let mut nonce = IV;
+        for (byte, seq_byte) in nonce[4..].iter_mut().zip(sequence_number.to_be_bytes()) {
+            *byte ^= seq_byte;
+        }
+        let length = u16::try_from(plaintext.len() + 16).unwrap().to_be_bytes();
+        let header = [0x17, 0x03, 0x03, length[0], length[1]];
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&KEY));
+        let ciphertext =
+            cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &header }).unwrap();
+        [&header[..], &ciphertext].concat()
+    }
+
+    #[test]
+    fn decrypts_consecutive_records() {
+        let data = [record(b"HTTP/1.1 200 OK\r\n\r\n", 2), record(b"hello", 3)].concat();
+        let secret = SecretData::new("Aes256Gcm", &KEY, &IV);
+        assert_eq!(decrypt_records(&data, &secret, 2).unwrap(), ["HTTP/1.1 200 OK\r\n\r\n", "hello"]);
+        assert_eq!(decrypt_records(&[], &secret, 2).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rejects_wrong_secrets() {
+        let data = record(b"hello", 2);
+        let secret = SecretData::new("Aes256Gcm", &KEY, &IV);
+        assert_eq!(decrypt_records(&data, &secret, 3), Err(DecryptError::Decryption { sequence_number: 3 }));
+
+        let wrong_key = SecretData::new("Aes256Gcm", &[0; 32], &IV);
+        assert_eq!(
+            decrypt_records(&data, &wrong_key, 2),
+            Err(DecryptError::Decryption { sequence_number: 2 })
+        );
+
+        let short_iv = SecretData::new("Aes256Gcm", &KEY, &IV[..8]);
+        assert_eq!(decrypt_records(&data, &short_iv, 2), Err(DecryptError::InvalidSecret));
+
+        let chacha = SecretData::new("Chacha20Poly1305", &KEY, &IV);
+        assert_eq!(
+            decrypt_records(&data, &chacha, 2),
+            Err(DecryptError::UnsupportedCipherSuite("Chacha20Poly1305".to_string()))
+        );
+    }
+}
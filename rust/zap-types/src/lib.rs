@@ -0,0 +1,27 @@
+//! Types exchanged between the Zap proxy and its clients.
+//!
+//! Both sides serialize these with serde, so field names cannot drift apart. Request bodies
+//! carry a schema version, see [`SCHEMA_VERSION`].
+
+mod attestation;
+mod config;
+#[cfg(feature = "decrypt")]
+mod decrypt;
+mod notary;
+mod secrets;
+
+pub use attestation::{Attestation, HttpResponse, Proof, ATTESTATION_VERSION};
+pub use config::{Endpoint, ProxyCredentials, ProxyTls, ZapServerConfig};
+#[cfg(feature = "decrypt")]
+pub use decrypt::{decrypt_records, DecryptError, SUPPORTED_CIPHER_SUITES};
+pub use notary::{NotaryInfo, NotaryPublicKey};
+pub use secrets::{SecretData, SecretsPayload};
+
+/// Version of the request bodies sent by clients. The proxy rejects versions it does not
+/// know, bodies without a version are version 1.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Header carrying the token the proxy issues on CONNECT for authenticating `/proof`.
+pub const SESSION_TOKEN_HEADER: &str = "X-Zap-Session-Token";
+/// Header carrying the id the proxy assigns to the tunnel, for fetching its attestation later.
+pub const SESSION_ID_HEADER: &str = "X-Zap-Session-Id";
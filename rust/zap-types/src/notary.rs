@@ -0,0 +1,285 @@
+#[cfg(feature = "verify")]
+use rustls::{
+    crypto::CryptoProvider,
+    pki_types::{pem::PemObject, SubjectPublicKeyInfoDer},
+    SignatureScheme,
+};
+use serde::{Deserialize, Serialize};
+
+/// Notary metadata served by the proxy's `GET /info`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotaryInfo {
+    version: String,
+    cipher_suites: Vec<String>,
+    tls_versions: Vec<String>,
+    attestation_versions: Vec<u32>,
+    /// Missing from proxies that predate versioned request bodies, which only know version 1.
+    #[serde(default = "first_versions")]
+    schema_versions: Vec<u32>,
+}
+
+fn first_versions() -> Vec<u32> {
+    vec![1]
+}
+
+impl NotaryInfo {
+    pub fn new(
+        version: &str,
+        cipher_suites: Vec<String>,
+        tls_versions: Vec<String>,
+        attestation_versions: Vec<u32>,
+        schema_versions: Vec<u32>,
+    ) -> Self {
+        NotaryInfo {
+            version: version.to_string(),
+            cipher_suites,
+            tls_versions,
+            attestation_versions,
+            schema_versions,
+        }
+    }
+
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn get_cipher_suites(&self) -> &[String] {
+        &self.cipher_suites
+    }
+
+    pub fn get_tls_versions(&self) -> &[String] {
+        &self.tls_versions
+    }
+
+    pub fn get_attestation_versions(&self) -> &[u32] {
+        &self.attestation_versions
+    }
+
+    /// Versions of the request bodies the proxy accepts, see [`SCHEMA_VERSION`](crate::SCHEMA_VERSION).
+    pub fn get_schema_versions(&self) -> &[u32] {
+        &self.schema_versions
+    }
+}
+
+/// A notary signing key served by the proxy's `GET /keys`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotaryPublicKey {
+    key_id: String,
+    algorithm: String,
+    public_key: String,
+    current: bool,
+    not_before: Option<String>,
+    not_after: Option<String>,
+}
+
+impl NotaryPublicKey {
+    /// `not_before` and `not_after` are RFC 3339 timestamps.
+    pub fn new(
+        key_id: &str,
+        algorithm: &str,
+        public_key: &str,
+        current: bool,
+        not_before: Option<String>,
+        not_after: Option<String>,
+    ) -> Self {
+        NotaryPublicKey {
+            key_id: key_id.to_string(),
+            algorithm: algorithm.to_string(),
+            public_key: public_key.to_string(),
+            current,
+            not_before,
+            not_after,
+        }
+    }
+
+    pub fn get_key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn get_algorithm(&self) -> &str {
+        &self.algorithm
+    }
+
+    /// PEM-encoded SubjectPublicKeyInfo.
+    pub fn get_public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.current
+    }
+
+    pub fn get_not_before(&self) -> Option<&str> {
+        self.not_before.as_deref()
+    }
+
+    pub fn get_not_after(&self) -> Option<&str> {
+        self.not_after.as_deref()
+    }
+
+    /// Verifies `signature` over `message` with this key.
+    #[cfg(feature = "verify")]
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        let Some(scheme) = signature_scheme(&self.algorithm) else {
+            return false;
+        };
+        let Ok(spki) = SubjectPublicKeyInfoDer::from_pem_slice(self.public_key.as_bytes()) else {
+            return false;
+        };
+        let Some(public_key) = subject_public_key(&spki) else {
+            return false;
+        };
+
+        let provider = CryptoProvider::get_default().cloned().unwrap_or_else(|| default_provider().into());
+        provider
+            .signature_verification_algorithms
+            .mapping
+            .iter()
+            .filter(|(candidate, _)| *candidate == scheme)
+            .flat_map(|(_, algorithms)| algorithms.iter())
+            .any(|algorithm| algorithm.verify_signature(public_key, message, signature).is_ok())
+    }
+}
+
+#[cfg(all(feature = "verify", feature = "aws-lc-rs"))]
+fn default_provider() -> CryptoProvider {
+    rustls::crypto::aws_lc_rs::default_provider()
+}
+
+#[cfg(all(feature = "verify", feature = "ring", not(feature = "aws-lc-rs")))]
+fn default_provider() -> CryptoProvider {
+    rustls::crypto::ring::default_provider()
+}
+
+#[cfg(all(feature = "verify", not(any(feature = "aws-lc-rs", feature = "ring"))))]
+compile_error!("The `verify` feature needs the `aws-lc-rs` or `ring` feature");
+
+#[cfg(feature = "verify")]
+fn signature_scheme(algorithm: &str) -> Option<SignatureScheme> {
+    match algorithm {
+        "RSA-PKCS1-SHA256" => Some(SignatureScheme::RSA_PKCS1_SHA256),
+        "RSA-PSS-SHA256" => Some(SignatureScheme::RSA_PSS_SHA256),
+        "ECDSA-P256-SHA256" => Some(SignatureScheme::ECDSA_NISTP256_SHA256),
+        "Ed25519" => Some(SignatureScheme::ED25519),
+        _ => None,
+    }
+}
+
+/// Extracts the `subjectPublicKey` bit string from a DER SubjectPublicKeyInfo, which is the
+/// form signature verification algorithms expect the key in.
+#[cfg(feature = "verify")]
+fn subject_public_key(spki: &[u8]) -> Option<&[u8]> {
+    let (spki, _) = der_element(spki, 0x30)?;
+    let (_algorithm, rest) = der_element(spki, 0x30)?;
+    let (bits, _) = der_element(rest, 0x03)?;
+    match bits.split_first() {
+        // No unused bits in a key encoding
+        Some((0, key)) => Some(key),
+        _ => None,
+    }
+}
+
+/// Splits the DER element with the expected tag off the front of `input`, returning its
+/// contents and the remaining input.
+#[cfg(feature = "verify")]
+fn der_element(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&actual_tag, rest) = input.split_first()?;
+    if actual_tag != tag {
+        return None;
+    }
+
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let len_bytes = (first & 0x7f) as usize;
+        if len_bytes == 0 || len_bytes > 4 || rest.len() < len_bytes {
+            return None;
+        }
+        let len = rest[..len_bytes].iter().fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, &rest[len_bytes..])
+    };
+
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
+#[cfg(all(test, feature = "verify"))]
+mod tests {
+    use super::*;
+
+    /// SubjectPublicKeyInfo of an Ed25519 key, the key bytes are `0x11` repeated.
+    fn ed25519_spki() -> Vec<u8> {
+        let mut spki = vec![0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+        spki.extend_from_slice(&[0x11; 32]);
+        spki
+    }
+
+    #[test]
+    fn extracts_subject_public_key() {
+        assert_eq!(subject_public_key(&ed25519_spki()), Some(&[0x11; 32][..]));
+
+        // Unused bits are not allowed in a key
+        let mut spki = ed25519_spki();
+        spki[11] = 0x01;
+        assert_eq!(subject_public_key(&spki), None);
+
+        // Not a sequence
+        let mut spki = ed25519_spki();
+        spki[0] = 0x31;
+        assert_eq!(subject_public_key(&spki), None);
+    }
+
+    #[test]
+    fn rejects_truncated_spki() {
+        let spki = ed25519_spki();
+        for len in 0..spki.len() {
+            assert_eq!(subject_public_key(&spki[..len]), None, "truncated to {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn reads_long_form_lengths() {
+        let mut element = vec![0x04, 0x81, 0x80];
+        element.extend_from_slice(&[0x22; 0x80]);
+        element.push(0xff);
+        let (contents, rest) = der_element(&element, 0x04).unwrap();
+        assert_eq!(contents, [0x22; 0x80]);
+        assert_eq!(rest, [0xff]);
+
+        let mut element = vec![0x04, 0x82, 0x01, 0x00];
+        element.extend_from_slice(&[0x22; 0x100]);
+        assert_eq!(der_element(&element, 0x04).unwrap().0.len(), 0x100);
+    }
+
+    #[test]
+    fn rejects_oversized_lengths() {
+        for element in [
+            // Longer than the input
+            &[0x04, 0x05, 0x00, 0x00][..],
+            &[0x04, 0x84, 0xff, 0xff, 0xff, 0xff, 0x00],
+            // More length bytes than supported, or than present
+            &[0x04, 0x85, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00],
+            &[0x04, 0x82, 0x01],
+            // Indefinite length is not DER
+            &[0x04, 0x80, 0x00, 0x00],
+        ] {
+            assert_eq!(der_element(element, 0x04), None, "{:02x?}", element);
+        }
+    }
+
+    #[test]
+    fn key_with_malformed_spki_never_verifies() {
+        let key = NotaryPublicKey::new("k1", "Ed25519", "not a PEM", true, None, None);
+        assert!(!key.verify(b"message", &[0; 64]));
+
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &ed25519_spki()[..20])
+        );
+        let key = NotaryPublicKey::new("k1", "Ed25519", &pem, true, None, None);
+        assert!(!key.verify(b"message", &[0; 64]));
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::SCHEMA_VERSION;
+
+/// Traffic key of one direction of a TLS session. Hex encoded on the wire, held as bytes that
+/// are wiped when dropped.
+#[derive(Serialize, Deserialize)]
+pub struct SecretData {
+    cipher_suite: String,
+    #[serde(with = "hex_secret")]
+    key: Zeroizing<Vec<u8>>,
+    #[serde(with = "hex_secret")]
+    iv: Zeroizing<Vec<u8>>,
+}
+
+impl SecretData {
+    pub fn new(cipher_suite: &str, key: &[u8], iv: &[u8]) -> Self {
+        SecretData {
+            cipher_suite: cipher_suite.to_string(),
+            key: Zeroizing::new(key.to_vec()),
+            iv: Zeroizing::new(iv.to_vec()),
+        }
+    }
+
+    /// Suite the secret belongs to, e.g. `Aes256Gcm`.
+    pub fn get_cipher_suite(&self) -> &str {
+        &self.cipher_suite
+    }
+
+    pub fn get_key(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn get_iv(&self) -> &[u8] {
+        &self.iv
+    }
+}
+
+impl fmt::Debug for SecretData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretData")
+            .field("cipher_suite", &self.cipher_suite)
+            .field("key", &"<redacted>")
+            .field("iv", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Hex encodes secret bytes, wiping the encoded copies as well.
+mod hex_secret {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use zeroize::Zeroizing;
+
+    pub fn serialize<S: Serializer>(bytes: &Zeroizing<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = Zeroizing::new(hex::encode(bytes.as_slice()));
+        serializer.serialize_str(&encoded)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Zeroizing<Vec<u8>>, D::Error> {
+        let encoded = Zeroizing::new(String::deserialize(deserializer)?);
+        hex::decode(encoded.as_str()).map(Zeroizing::new).map_err(D::Error::custom)
+    }
+}
+
+/// Body of `POST /proof`, the session secrets the notary decrypts the transcript with.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SecretsPayload {
+    #[serde(default = "first_version")]
+    version: u32,
+    rx_sequence_number: u64,
+    tx_sequence_number: u64,
+    rx_secret: SecretData,
+    tx_secret: SecretData,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl SecretsPayload {
+    pub fn new(
+        rx_sequence_number: u64,
+        tx_sequence_number: u64,
+        rx_secret: SecretData,
+        tx_secret: SecretData,
+    ) -> Self {
+        SecretsPayload {
+            version: SCHEMA_VERSION,
+            rx_sequence_number,
+            tx_sequence_number,
+            rx_secret,
+            tx_secret,
+        }
+    }
+
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn get_rx_sequence_number(&self) -> u64 {
+        self.rx_sequence_number
+    }
+
+    pub fn get_tx_sequence_number(&self) -> u64 {
+        self.tx_sequence_number
+    }
+
+    /// Secret for the records sent by the target.
+    pub fn get_rx_secret(&self) -> &SecretData {
+        &self.rx_secret
+    }
+
+    /// Secret for the records sent by the client.
+    pub fn get_tx_secret(&self) -> &SecretData {
+        &self.tx_secret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn payload_json(version: Option<u32>) -> serde_json::Value {
+        let mut value = json!({
+            "rx_sequence_number": 2,
+            "tx_sequence_number": 3,
+            "rx_secret": { "cipher_suite": "Aes256Gcm", "key": "0102", "iv": "0a0b" },
+            "tx_secret": { "cipher_suite": "Aes256Gcm", "key": "0304", "iv": "0c0d" },
+        });
+        if let Some(version) = version {
+            value["version"] = json!(version);
+        }
+        value
+    }
+
+    #[test]
+    fn payload_round_trips_with_hex_secrets() {
+        let payload = SecretsPayload::new(
+            2,
+            3,
+            SecretData::new("Aes256Gcm", &[1, 2], &[10, 11]),
+            SecretData::new("Aes256Gcm", &[3, 4], &[12, 13]),
+        );
+        let value = serde_json::to_value(&payload).unwrap();
+        assert_eq!(value, payload_json(Some(SCHEMA_VERSION)));
+
+        let parsed: SecretsPayload = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.get_version(), SCHEMA_VERSION);
+        assert_eq!(parsed.get_rx_sequence_number(), 2);
+        assert_eq!(parsed.get_tx_sequence_number(), 3);
+        assert_eq!(parsed.get_rx_secret().get_key(), [1, 2]);
+        assert_eq!(parsed.get_tx_secret().get_iv(), [12, 13]);
+
+        let mut not_hex = payload_json(None);
+        not_hex["rx_secret"]["key"] = json!("zz");
+        assert!(serde_json::from_value::<SecretsPayload>(not_hex).is_err());
+    }
+
+    #[test]
+    fn version_defaults_to_1() {
+        let parsed: SecretsPayload = serde_json::from_value(payload_json(None)).unwrap();
+        assert_eq!(parsed.get_version(), 1);
+    }
+
+    #[test]
+    fn debug_output_is_redacted() {
+        let secret = SecretData::new("Aes256Gcm", &[0xab; 32], &[0xcd; 12]);
+        assert_eq!(
+            format!("{:?}", secret),
+            r#"SecretData { cipher_suite: "Aes256Gcm", key: "<redacted>", iv: "<redacted>" }"#
+        );
+
+        let payload = SecretsPayload::new(2, 3, secret, SecretData::new("Aes256Gcm", &[0xef; 32], &[0; 12]));
+        let debug = format!("{:?}", payload);
+        assert_eq!(debug.matches("<redacted>").count(), 4, "{}", debug);
+        assert!(!debug.contains("171") && !debug.contains("239"), "{}", debug);
+    }
+}